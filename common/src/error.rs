@@ -185,6 +185,12 @@ impl From<std::ffi::NulError> for VError {
     }
 }
 
+impl From<snap::Error> for VError {
+    fn from(value: snap::Error) -> Self {
+        Self::new(VErrorKind::BadPacket, value.to_string())
+    }
+}
+
 impl<T> From<snap::write::IntoInnerError<T>> for VError {
     fn from(value: snap::write::IntoInnerError<T>) -> Self {
         Self::new(VErrorKind::Other, value.to_string())
@@ -4,7 +4,7 @@ use std::ops::{Deref, DerefMut};
 use crate::VarInt;
 
 /// Type and size independent vector type
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 #[repr(C)]
 pub struct Vector<T, const N: usize> {
     components: [T; N],
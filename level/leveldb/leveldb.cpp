@@ -10,6 +10,7 @@
 #include <leveldb/decompress_allocator.h>
 #include <leveldb/status.h>
 #include <leveldb/db.h>
+#include <leveldb/write_batch.h>
 
 class EmptyLogger : public leveldb::Logger {
 public:
@@ -19,6 +20,7 @@ public:
 struct Database {
     leveldb::Options options = leveldb::Options();
     leveldb::ReadOptions read_options = leveldb::ReadOptions();
+    leveldb::WriteOptions write_options = leveldb::WriteOptions();
     leveldb::DB* database = nullptr;
 
     ~Database() noexcept {
@@ -40,6 +42,8 @@ LevelResult level_open_database(const char* path) {
     try {
         std::unique_ptr<Database> database = std::make_unique<Database>();
 
+        // Allows servers to start without an existing world.
+        database->options.create_if_missing = true;
         database->options.filter_policy = leveldb::NewBloomFilterPolicy(10);
         database->options.block_cache = leveldb::NewLRUCache(40 * 1024 * 1024);
         database->options.info_log = new EmptyLogger();
@@ -47,6 +51,8 @@ LevelResult level_open_database(const char* path) {
         database->options.compressors[1] = new leveldb::ZlibCompressor();
 
         database->read_options.decompress_allocator = new leveldb::DecompressAllocator();
+        // Writes are synchronous to prevent data loss in case of a crash.
+        database->write_options.sync = true;
 
         leveldb::Status status = leveldb::DB::Open(database->options, path, &database->database);
 
@@ -98,6 +104,15 @@ LevelResult level_get_key(void* database_ptr, const char* key, int key_size) {
         std::string value;
 
         auto status = database->database->Get(database->read_options, leveldb::Slice(key, key_size), &value);
+        if(status.IsNotFound()) {
+            // A missing key is not an error, it is indicated by a null pointer instead.
+            result.is_success = true;
+            result.size = 0;
+            result.data = nullptr;
+
+            return result;
+        }
+
         if(!status.ok()) {
             std::string cpp_src = status.ToString();
             const char* src = cpp_src.c_str();
@@ -133,6 +148,158 @@ LevelResult level_get_key(void* database_ptr, const char* key, int key_size) {
     return result;
 }
 
+// Copies the given message into the result and marks it as failed.
+static void set_error(LevelResult& result, const char* message) {
+    size_t src_size = strlen(message) + 1; // Make space for null terminator.
+
+    result.is_success = false;
+    result.size = static_cast<int>(src_size);
+    result.data = new char[src_size];
+    memcpy(result.data, message, src_size);
+}
+
+// Converts a LevelDB status into a result without data.
+static LevelResult status_to_result(const leveldb::Status& status) {
+    LevelResult result{};
+
+    if(status.ok()) {
+        result.is_success = true;
+        result.size = 0;
+        result.data = nullptr;
+    } else {
+        set_error(result, status.ToString().c_str());
+    }
+
+    return result;
+}
+
+LevelResult level_put_key(void* database_ptr, const char* key, int key_size, const char* value, int value_size) {
+    LevelResult result{};
+
+    try {
+        auto database = reinterpret_cast<Database*>(database_ptr);
+        auto status = database->database->Put(
+            database->write_options, leveldb::Slice(key, key_size), leveldb::Slice(value, value_size)
+        );
+
+        return status_to_result(status);
+    } catch(const std::exception& e) {
+        set_error(result, e.what());
+    } catch(...) {
+        set_error(result, "Unknown exception while writing key");
+    }
+
+    return result;
+}
+
+LevelResult level_delete_key(void* database_ptr, const char* key, int key_size) {
+    LevelResult result{};
+
+    try {
+        auto database = reinterpret_cast<Database*>(database_ptr);
+        auto status = database->database->Delete(database->write_options, leveldb::Slice(key, key_size));
+
+        return status_to_result(status);
+    } catch(const std::exception& e) {
+        set_error(result, e.what());
+    } catch(...) {
+        set_error(result, "Unknown exception while deleting key");
+    }
+
+    return result;
+}
+
+void* level_create_write_batch() {
+    return new leveldb::WriteBatch();
+}
+
+void level_destroy_write_batch(void* batch_ptr) {
+    auto batch = reinterpret_cast<leveldb::WriteBatch*>(batch_ptr);
+    delete batch;
+}
+
+void level_write_batch_put(void* batch_ptr, const char* key, int key_size, const char* value, int value_size) {
+    auto batch = reinterpret_cast<leveldb::WriteBatch*>(batch_ptr);
+    batch->Put(leveldb::Slice(key, key_size), leveldb::Slice(value, value_size));
+}
+
+void level_write_batch_delete(void* batch_ptr, const char* key, int key_size) {
+    auto batch = reinterpret_cast<leveldb::WriteBatch*>(batch_ptr);
+    batch->Delete(leveldb::Slice(key, key_size));
+}
+
+void level_write_batch_clear(void* batch_ptr) {
+    auto batch = reinterpret_cast<leveldb::WriteBatch*>(batch_ptr);
+    batch->Clear();
+}
+
+LevelResult level_write_batch(void* database_ptr, void* batch_ptr) {
+    LevelResult result{};
+
+    try {
+        auto database = reinterpret_cast<Database*>(database_ptr);
+        auto batch = reinterpret_cast<leveldb::WriteBatch*>(batch_ptr);
+
+        auto status = database->database->Write(database->write_options, batch);
+        return status_to_result(status);
+    } catch(const std::exception& e) {
+        set_error(result, e.what());
+    } catch(...) {
+        set_error(result, "Unknown exception while writing batch");
+    }
+
+    return result;
+}
+
+void* level_create_iterator(void* database_ptr) {
+    auto database = reinterpret_cast<Database*>(database_ptr);
+    return database->database->NewIterator(database->read_options);
+}
+
+void level_destroy_iterator(void* iterator_ptr) {
+    auto iterator = reinterpret_cast<leveldb::Iterator*>(iterator_ptr);
+    delete iterator;
+}
+
+void level_iterator_seek_to_first(void* iterator_ptr) {
+    auto iterator = reinterpret_cast<leveldb::Iterator*>(iterator_ptr);
+    iterator->SeekToFirst();
+}
+
+void level_iterator_seek(void* iterator_ptr, const char* key, int key_size) {
+    auto iterator = reinterpret_cast<leveldb::Iterator*>(iterator_ptr);
+    iterator->Seek(leveldb::Slice(key, key_size));
+}
+
+void level_iterator_next(void* iterator_ptr) {
+    auto iterator = reinterpret_cast<leveldb::Iterator*>(iterator_ptr);
+    iterator->Next();
+}
+
+int level_iterator_valid(void* iterator_ptr) {
+    auto iterator = reinterpret_cast<leveldb::Iterator*>(iterator_ptr);
+    return iterator->Valid();
+}
+
+LevelSlice level_iterator_key(void* iterator_ptr) {
+    auto iterator = reinterpret_cast<leveldb::Iterator*>(iterator_ptr);
+    leveldb::Slice key = iterator->key();
+
+    return LevelSlice{ static_cast<int>(key.size()), key.data() };
+}
+
+LevelSlice level_iterator_value(void* iterator_ptr) {
+    auto iterator = reinterpret_cast<leveldb::Iterator*>(iterator_ptr);
+    leveldb::Slice value = iterator->value();
+
+    return LevelSlice{ static_cast<int>(value.size()), value.data() };
+}
+
+LevelResult level_iterator_status(void* iterator_ptr) {
+    auto iterator = reinterpret_cast<leveldb::Iterator*>(iterator_ptr);
+    return status_to_result(iterator->status());
+}
+
 void level_deallocate_array(char* array) {
     delete[] array;
 }
\ No newline at end of file
@@ -10,13 +10,60 @@ extern "C" {
         void* data;
     };
 
-    // Open a LevelDB database.
+    // Open a LevelDB database, creating it if it does not exist.
     struct LevelResult level_open_database(const char* path);
     // Close a LevelDB database.
     // This also frees the pointers, it must no longer be used.
     void level_close_database(void* database);
     // Loads a key from the database.
+    // If the key does not exist, the result is successful but its data pointer is null.
     struct LevelResult level_get_key(void* database, const char* key, int key_size);
+    // Writes a single key-value pair into the database.
+    struct LevelResult level_put_key(void* database, const char* key, int key_size, const char* value, int value_size);
+    // Removes a single key from the database.
+    struct LevelResult level_delete_key(void* database, const char* key, int key_size);
+
+    // Creates a new empty write batch.
+    // The batch must be destroyed using level_destroy_write_batch.
+    void* level_create_write_batch();
+    // Destroys a write batch, it must no longer be used.
+    void level_destroy_write_batch(void* batch);
+    // Adds a put operation to the write batch.
+    void level_write_batch_put(void* batch, const char* key, int key_size, const char* value, int value_size);
+    // Adds a delete operation to the write batch.
+    void level_write_batch_delete(void* batch, const char* key, int key_size);
+    // Clears all operations in the write batch.
+    void level_write_batch_clear(void* batch);
+    // Atomically applies all operations in the batch to the database.
+    struct LevelResult level_write_batch(void* database, void* batch);
+    // Slice of data owned by LevelDB.
+    // This data must not be deallocated and is only valid until the owner is modified.
+    struct LevelSlice {
+        int size;
+        const char* data;
+    };
+
+    // Creates an iterator over all keys in the database.
+    // The iterator is initially invalid and must be positioned using one of the seek functions.
+    // It must be destroyed using level_destroy_iterator before the database is closed.
+    void* level_create_iterator(void* database);
+    // Destroys an iterator, it must no longer be used.
+    void level_destroy_iterator(void* iterator);
+    // Moves the iterator to the first key in the database.
+    void level_iterator_seek_to_first(void* iterator);
+    // Moves the iterator to the first key that is equal to or greater than the given key.
+    void level_iterator_seek(void* iterator, const char* key, int key_size);
+    // Moves the iterator to the next key.
+    void level_iterator_next(void* iterator);
+    // Returns 1 if the iterator is positioned at a key, 0 otherwise.
+    int level_iterator_valid(void* iterator);
+    // Returns the key the iterator is currently positioned at.
+    struct LevelSlice level_iterator_key(void* iterator);
+    // Returns the value the iterator is currently positioned at.
+    struct LevelSlice level_iterator_value(void* iterator);
+    // Returns whether an error occurred while iterating.
+    struct LevelResult level_iterator_status(void* iterator);
+
     // Deallocates a string previously allocated by another function.
     void level_deallocate_array(char* array);
 
@@ -0,0 +1,243 @@
+use std::collections::HashMap;
+
+use bytes::{BufMut, Bytes, BytesMut};
+use common::{bail, VResult};
+
+use crate::sub_chunk::StorageRecord;
+
+/// Offset basis of the 32-bit FNV-1a hash.
+const FNV1A_32_OFFSET: u32 = 0x811c9dc5;
+/// Prime of the 32-bit FNV-1a hash.
+const FNV1A_32_PRIME: u32 = 0x01000193;
+
+/// Block that unknown block states are replaced with when they are sent to the client.
+const UPDATE_BLOCK: &str = "minecraft:info_update";
+/// Name of the air block.
+const AIR_BLOCK: &str = "minecraft:air";
+
+/// How runtime IDs are assigned to block states.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RuntimeIdMode {
+    /// Block states are numbered in the order of the canonical block states.
+    Sequential,
+    /// The runtime ID of a block state is the hash of its name and states.
+    Hashed,
+}
+
+/// Contains every block state known by the server and maps them to the runtime IDs
+/// used by the network format.
+#[derive(Debug)]
+pub struct BlockRegistry {
+    /// Canonical block states, in sequential runtime ID order.
+    states: Vec<nbt::Value>,
+    /// Maps the hash of a block state to its index in the canonical states.
+    lookup: HashMap<u32, u32>,
+    /// Maps block names to the index of their first canonical state.
+    defaults: HashMap<String, u32>,
+    /// How runtime IDs are assigned.
+    mode: RuntimeIdMode,
+    /// Checksum of all block states.
+    checksum: u64,
+    /// Runtime ID of the block that unknown block states are replaced with.
+    fallback: u32,
+}
+
+impl BlockRegistry {
+    /// Loads the registry from a list of canonical block states.
+    ///
+    /// The block states should be network encoded NBT compounds,
+    /// each containing a name, states and version.
+    pub fn new(mut buffer: Bytes, mode: RuntimeIdMode) -> VResult<Self> {
+        let mut states = Vec::new();
+        let mut lookup = HashMap::new();
+        let mut defaults = HashMap::new();
+        let mut checksum_input = BytesMut::new();
+
+        while !buffer.is_empty() {
+            let state = nbt::deserialize_net(&mut buffer)?.value;
+            let Some(hash) = hash_block_state(&state) else {
+                bail!(
+                    Other,
+                    "Canonical block state {} is invalid",
+                    states.len()
+                );
+            };
+
+            if lookup.insert(hash, states.len() as u32).is_some() {
+                bail!(
+                    Other,
+                    "Canonical block state {} has the same hash as a previous state",
+                    states.len()
+                );
+            }
+
+            if let Some(name) = block_name(&state) {
+                defaults
+                    .entry(name.to_owned())
+                    .or_insert(states.len() as u32);
+            }
+
+            serialize_sorted_le("", &state, &mut checksum_input);
+            states.push(state);
+        }
+
+        let Some(fallback) = defaults
+            .get(UPDATE_BLOCK)
+            .or_else(|| defaults.get(AIR_BLOCK))
+        else {
+            bail!(Other, "Canonical block states do not contain {AIR_BLOCK}");
+        };
+        let fallback = states[*fallback as usize].clone();
+
+        tracing::debug!("Loaded {} block states", states.len());
+
+        let mut registry = Self {
+            checksum: xxhash_rust::xxh64::xxh64(&checksum_input, 0),
+            states,
+            lookup,
+            defaults,
+            mode,
+            fallback: 0,
+        };
+        registry.fallback = registry.runtime_id(&fallback).unwrap_or(0);
+
+        Ok(registry)
+    }
+
+    /// How runtime IDs are assigned.
+    #[inline]
+    pub const fn mode(&self) -> RuntimeIdMode {
+        self.mode
+    }
+
+    /// Amount of registered block states.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    /// Whether the registry contains no block states.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    /// Checksum of all block states, used by the client to verify its own block states.
+    #[inline]
+    pub const fn checksum(&self) -> u64 {
+        self.checksum
+    }
+
+    /// Returns the runtime ID of a block state.
+    ///
+    /// The version of the block state is ignored, only its name and states are compared.
+    /// Returns `None` if the block state is not registered.
+    pub fn runtime_id(&self, block: &nbt::Value) -> Option<u32> {
+        let hash = hash_block_state(block)?;
+        let index = *self.lookup.get(&hash)?;
+
+        Some(match self.mode {
+            RuntimeIdMode::Sequential => index,
+            RuntimeIdMode::Hashed => hash,
+        })
+    }
+
+    /// Returns the runtime ID of the default state of a block,
+    /// which is the first canonical state with the given name.
+    pub fn default_runtime_id(&self, name: &str) -> Option<u32> {
+        let index = *self.defaults.get(name)?;
+        self.runtime_id(&self.states[index as usize])
+    }
+
+    /// Returns the default state of a block,
+    /// which is the first canonical state with the given name.
+    pub fn default_state(&self, name: &str) -> Option<&nbt::Value> {
+        let index = *self.defaults.get(name)?;
+        self.states.get(index as usize)
+    }
+
+    /// Returns the block state with the given runtime ID.
+    pub fn block_state(&self, runtime_id: u32) -> Option<&nbt::Value> {
+        let index = match self.mode {
+            RuntimeIdMode::Sequential => runtime_id,
+            RuntimeIdMode::Hashed => *self.lookup.get(&runtime_id)?,
+        };
+
+        self.states.get(index as usize)
+    }
+
+    /// Converts a storage record with NBT block states to one with runtime IDs.
+    ///
+    /// Unknown block states are replaced with the update block.
+    pub fn to_runtime(&self, record: &StorageRecord) -> StorageRecord<u32> {
+        record.map_palette(|block| {
+            self.runtime_id(block).unwrap_or_else(|| {
+                tracing::warn!("Unknown block state {block:?}");
+                self.fallback
+            })
+        })
+    }
+}
+
+/// Returns the name of a block state.
+fn block_name(block: &nbt::Value) -> Option<&str> {
+    match block {
+        nbt::Value::Compound(map) => match map.get("name") {
+            Some(nbt::Value::String(name)) => Some(name),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Computes the hashed runtime ID of a block state.
+///
+/// This is the 32-bit FNV-1a hash of the little endian NBT encoding of the name and states,
+/// with the keys of every compound in sorted order.
+/// Returns `None` if the value is not a valid block state.
+pub fn hash_block_state(block: &nbt::Value) -> Option<u32> {
+    let nbt::Value::Compound(map) = block else {
+        return None;
+    };
+
+    let name = map
+        .get("name")
+        .filter(|n| matches!(n, nbt::Value::String(_)))?;
+    let states = match map.get("states") {
+        Some(states @ nbt::Value::Compound(_)) => states.clone(),
+        // Legacy block states do not have any states.
+        _ => nbt::Value::Compound(HashMap::new()),
+    };
+
+    let identity = nbt::Value::Compound(HashMap::from([
+        ("name".to_owned(), name.clone()),
+        ("states".to_owned(), states),
+    ]));
+
+    let mut buffer = BytesMut::new();
+    serialize_sorted_le("", &identity, &mut buffer);
+
+    Some(buffer.iter().fold(FNV1A_32_OFFSET, |hash, byte| {
+        (hash ^ *byte as u32).wrapping_mul(FNV1A_32_PRIME)
+    }))
+}
+
+/// Writes a little endian NBT tag where the keys of every compound are sorted,
+/// so that equal block states always produce the same bytes.
+fn serialize_sorted_le(name: &str, value: &nbt::Value, buffer: &mut BytesMut) {
+    let nbt::Value::Compound(map) = value else {
+        return nbt::serialize_le(name, value, buffer);
+    };
+
+    buffer.put_u8(nbt::TAG_COMPOUND);
+    buffer.put_u16_le(name.len() as u16);
+    buffer.put(name.as_bytes());
+
+    let mut entries = map.iter().collect::<Vec<_>>();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    for (name, value) in entries {
+        serialize_sorted_le(name, value, buffer);
+    }
+
+    buffer.put_u8(nbt::TAG_END);
+}
@@ -0,0 +1,91 @@
+use std::collections::HashMap;
+
+use bytes::{Buf, Bytes, BytesMut};
+use common::{bail, VResult, Vector3i};
+
+/// Additional data attached to a block, such as the text of a sign or the items in a chest.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockEntity {
+    /// Position of the block in the world.
+    pub position: Vector3i,
+    /// Type of block entity, such as `Chest` or `Sign`.
+    pub id: String,
+    /// All tags other than the ID and position.
+    pub data: HashMap<String, nbt::Value>,
+}
+
+impl BlockEntity {
+    /// Creates a block entity without any data.
+    pub fn new(id: impl Into<String>, position: Vector3i) -> Self {
+        Self {
+            position,
+            id: id.into(),
+            data: HashMap::new(),
+        }
+    }
+
+    /// Converts an NBT compound to a block entity.
+    ///
+    /// The compound must contain the `id`, `x`, `y` and `z` tags.
+    pub fn from_nbt(value: nbt::Value) -> VResult<Self> {
+        let nbt::Value::Compound(mut data) = value else {
+            bail!(InvalidChunk, "Expected block entity to be a compound");
+        };
+
+        let Some(nbt::Value::String(id)) = data.remove("id") else {
+            bail!(InvalidChunk, "Block entity is missing its ID");
+        };
+
+        let mut coordinates = [0; 3];
+        for (coordinate, name) in coordinates.iter_mut().zip(["x", "y", "z"]) {
+            let Some(nbt::Value::Int(value)) = data.remove(name) else {
+                bail!(
+                    InvalidChunk,
+                    "Block entity {id} is missing its {name} coordinate"
+                );
+            };
+            *coordinate = value;
+        }
+
+        Ok(Self {
+            position: Vector3i::from(coordinates),
+            id,
+            data,
+        })
+    }
+
+    /// Converts the block entity to an NBT compound, including its ID and position.
+    pub fn to_nbt(&self) -> nbt::Value {
+        let mut data = self.data.clone();
+        data.extend([
+            ("id".to_owned(), nbt::Value::String(self.id.clone())),
+            ("x".to_owned(), nbt::Value::Int(self.position.x)),
+            ("y".to_owned(), nbt::Value::Int(self.position.y)),
+            ("z".to_owned(), nbt::Value::Int(self.position.z)),
+        ]);
+
+        nbt::Value::Compound(data)
+    }
+
+    /// Reads the block entities of a chunk from the database format,
+    /// which consists of little-endian NBT compounds stored back to back.
+    pub fn deserialize_all(mut buffer: Bytes) -> VResult<Vec<Self>> {
+        let mut entities = Vec::new();
+        while buffer.has_remaining() {
+            let tag = nbt::deserialize_le(&mut buffer)?;
+            entities.push(Self::from_nbt(tag.value)?);
+        }
+
+        Ok(entities)
+    }
+
+    /// Writes the block entity in the database format.
+    pub fn serialize(&self, buffer: &mut BytesMut) {
+        nbt::serialize_le("", &self.to_nbt(), buffer);
+    }
+
+    /// Writes the block entity in the format used by chunk packets and the `BlockActorData` packet.
+    pub fn serialize_network(&self, buffer: &mut BytesMut) {
+        nbt::serialize_net("", &self.to_nbt(), buffer);
+    }
+}
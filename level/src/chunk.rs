@@ -0,0 +1,877 @@
+use std::collections::{HashMap, HashSet};
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use common::{bail, Deserialize, Serialize, VError, VResult, Vector3b};
+
+use crate::sub_chunk::CHUNK_SIZE;
+use crate::{
+    BlockEntity, BlockRegistry, ChunkDatabase, DatabaseKey, DatabaseTag,
+    Dimension, Entity, PaletteEncoding, StorageRecord, SubChunk,
+    SubChunkVersion, WriteBatch,
+};
+
+/// Chunk version written by the server.
+/// This is the version used since 1.18.30.
+pub const CURRENT_CHUNK_VERSION: u8 = 40;
+
+/// Block state version of the air block.
+const AIR_VERSION: i32 = 17959425;
+
+/// Header value indicating that a biome storage is a copy of the previous one.
+const COPY_PREVIOUS_HEADER: u8 = 0x7f;
+
+/// Size of the heightmap that is stored in front of the biome data.
+const HEIGHTMAP_SIZE: usize = 256;
+
+/// Creates the air block.
+fn air() -> nbt::Value {
+    nbt::Value::Compound(HashMap::from([
+        (
+            "name".to_owned(),
+            nbt::Value::String("minecraft:air".to_owned()),
+        ),
+        ("states".to_owned(), nbt::Value::Compound(HashMap::new())),
+        ("version".to_owned(), nbt::Value::Int(AIR_VERSION)),
+    ]))
+}
+
+/// Whether the given block is air.
+pub(crate) fn is_air(block: &nbt::Value) -> bool {
+    match block {
+        nbt::Value::Compound(map) => matches!(
+            map.get("name"),
+            Some(nbt::Value::String(name)) if name == "minecraft:air"
+        ),
+        _ => false,
+    }
+}
+
+/// Generation state of a chunk.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum FinalizedState {
+    /// The chunk has not been ticked yet.
+    NeedsInstaticking,
+    /// The chunk has been generated, but not populated with features.
+    NeedsPopulation,
+    /// The chunk is fully generated.
+    Finalized,
+}
+
+impl TryFrom<i32> for FinalizedState {
+    type Error = VError;
+
+    fn try_from(value: i32) -> VResult<Self> {
+        Ok(match value {
+            0 => Self::NeedsInstaticking,
+            1 => Self::NeedsPopulation,
+            2 => Self::Finalized,
+            _ => bail!(InvalidChunk, "Invalid finalized state {value}"),
+        })
+    }
+}
+
+/// A full column of sub chunks, together with the biomes and heightmap.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Chunk {
+    /// X coordinate of the chunk.
+    x: i32,
+    /// Z coordinate of the chunk.
+    z: i32,
+    /// Dimension the chunk is located in.
+    dimension: Dimension,
+    /// Version of the chunk format that the chunk was loaded with.
+    version: u8,
+    /// Generation state of the chunk.
+    finalized: FinalizedState,
+    /// Height of the highest non-air block in each column,
+    /// relative to the bottom of the dimension.
+    /// Stored in ZX order.
+    heightmap: [i16; HEIGHTMAP_SIZE],
+    /// Biome storages, one for each sub chunk from the bottom of the dimension.
+    biomes: Vec<StorageRecord<u32>>,
+    /// Sub chunks, from the bottom of the dimension.
+    /// Sub chunks that do not exist are fully filled with air.
+    sub_chunks: Vec<Option<SubChunk>>,
+    /// Block entities in this chunk, indexed by their world coordinates.
+    block_entities: HashMap<(i32, i32, i32), BlockEntity>,
+    /// Entities in this chunk.
+    entities: Vec<Entity>,
+    /// Storage IDs of the entity records that this chunk was loaded with.
+    /// Records of entities that have been removed since are deleted when the chunk is saved.
+    stored_entities: Vec<[u8; 8]>,
+}
+
+impl Chunk {
+    /// Creates an empty chunk filled with air.
+    pub fn new(x: i32, z: i32, dimension: Dimension) -> Self {
+        let count = dimension.sub_chunk_count();
+
+        Self {
+            x,
+            z,
+            dimension,
+            version: CURRENT_CHUNK_VERSION,
+            finalized: FinalizedState::Finalized,
+            heightmap: [0; HEIGHTMAP_SIZE],
+            biomes: vec![
+                StorageRecord::filled(dimension.default_biome());
+                count
+            ],
+            sub_chunks: vec![None; count],
+            block_entities: HashMap::new(),
+            entities: Vec::new(),
+            stored_entities: Vec::new(),
+        }
+    }
+
+    /// Loads the chunk at the given position from the database.
+    ///
+    /// Returns `None` if the chunk does not exist.
+    pub fn load(
+        database: &ChunkDatabase,
+        x: i32,
+        z: i32,
+        dimension: Dimension,
+    ) -> VResult<Option<Self>> {
+        let mut chunk = Self::new(x, z, dimension);
+        let mut exists = false;
+        let mut has_biomes = false;
+
+        for (key, mut value) in database.iter_chunk(x, z, dimension) {
+            match key.tag {
+                DatabaseTag::ChunkVersion | DatabaseTag::LegacyChunkVersion => {
+                    if !value.has_remaining() {
+                        bail!(InvalidChunk, "Chunk version is missing");
+                    }
+
+                    chunk.version = value.get_u8();
+                    exists = true;
+                }
+                DatabaseTag::FinalizedState => {
+                    if value.remaining() < 4 {
+                        bail!(InvalidChunk, "Finalized state is missing");
+                    }
+
+                    chunk.finalized =
+                        FinalizedState::try_from(value.get_i32_le())?;
+                }
+                DatabaseTag::Biome3d => {
+                    chunk.deserialize_biome_3d(value)?;
+                    has_biomes = true;
+                }
+                DatabaseTag::Data2d if !has_biomes => {
+                    chunk.deserialize_data_2d(value)?;
+                }
+                DatabaseTag::SubChunk => {
+                    let Some(slot) = chunk.slot_index(key.y) else {
+                        tracing::warn!(
+                            "Sub chunk {} of chunk [{x}, {z}] is out of range, ignoring it",
+                            key.y
+                        );
+                        continue;
+                    };
+
+                    let mut sub_chunk = SubChunk::deserialize(value)?;
+                    sub_chunk.set_index(key.y);
+                    chunk.sub_chunks[slot] = Some(sub_chunk);
+                }
+                DatabaseTag::Entity => {
+                    chunk.entities.extend(Entity::deserialize_all(value)?);
+                }
+                DatabaseTag::BlockEntity => {
+                    for entity in BlockEntity::deserialize_all(value)? {
+                        if let Err(e) = chunk.set_block_entity(entity) {
+                            tracing::warn!("Ignoring block entity in chunk [{x}, {z}]: {e}");
+                        }
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        if exists {
+            chunk.load_entity_digest(database)?;
+        }
+
+        Ok(exists.then_some(chunk))
+    }
+
+    /// Loads the entities that are stored in the digest format.
+    fn load_entity_digest(&mut self, database: &ChunkDatabase) -> VResult<()> {
+        let key = Entity::digest_key(self.x, self.z, self.dimension);
+        let Some(digest) = database.get_raw_key(key)? else {
+            return Ok(());
+        };
+
+        for id in digest.chunks_exact(8) {
+            let mut storage_id = [0; 8];
+            storage_id.copy_from_slice(id);
+            self.stored_entities.push(storage_id);
+
+            let Some(record) =
+                database.get_raw_key(Entity::actor_key(&storage_id))?
+            else {
+                tracing::warn!(
+                    "Entity record listed in chunk [{}, {}] does not exist",
+                    self.x,
+                    self.z
+                );
+                continue;
+            };
+
+            for entity in Entity::deserialize_all(record)? {
+                // Worlds that are being upgraded can contain entities in both formats.
+                if self.entity(entity.unique_id).is_none() {
+                    self.entities.push(entity);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Adds all records of this chunk to the batch.
+    ///
+    /// Sub chunks that do not exist are deleted from the database,
+    /// as well as the records of entities that were removed after the chunk was loaded.
+    pub fn save(&self, batch: &mut WriteBatch) {
+        self.save_records(batch);
+        for storage_id in self.stale_entities() {
+            batch.delete(Entity::actor_key(&storage_id));
+        }
+    }
+
+    /// Adds all records of this chunk to the batch,
+    /// except for the deletion of [stale entity records](Self::stale_entities).
+    pub(crate) fn save_records(&self, batch: &mut WriteBatch) {
+        let mut key = DatabaseKey {
+            x: self.x,
+            z: self.z,
+            y: 0,
+            dimension: self.dimension,
+            tag: DatabaseTag::ChunkVersion,
+        };
+
+        let mut key_buffer = BytesMut::with_capacity(14);
+        let mut value_buffer = BytesMut::new();
+        let mut put = |key: &DatabaseKey, value: Option<&[u8]>| {
+            key_buffer.clear();
+            key.serialize(&mut key_buffer);
+
+            match value {
+                Some(value) => batch.put(&key_buffer, value),
+                None => batch.delete(&key_buffer),
+            }
+        };
+
+        // Chunks are always saved in the current format, which replaces the legacy records.
+        put(&key, Some(&[CURRENT_CHUNK_VERSION]));
+
+        key.tag = DatabaseTag::LegacyChunkVersion;
+        put(&key, None);
+
+        key.tag = DatabaseTag::Data2d;
+        put(&key, None);
+
+        key.tag = DatabaseTag::FinalizedState;
+        put(&key, Some(&(self.finalized as i32).to_le_bytes()));
+
+        key.tag = DatabaseTag::Biome3d;
+        self.serialize_biome_3d(&mut value_buffer);
+        put(&key, Some(&value_buffer));
+
+        key.tag = DatabaseTag::BlockEntity;
+        if self.block_entities.is_empty() {
+            put(&key, None);
+        } else {
+            value_buffer.clear();
+            for entity in self.block_entities.values() {
+                entity.serialize(&mut value_buffer);
+            }
+            put(&key, Some(&value_buffer));
+        }
+
+        key.tag = DatabaseTag::SubChunk;
+        for (slot, sub_chunk) in self.sub_chunks.iter().enumerate() {
+            key.y = self.dimension.min_sub_chunk() + slot as i8;
+            match sub_chunk {
+                Some(sub_chunk) => {
+                    value_buffer.clear();
+                    sub_chunk.serialize(&mut value_buffer);
+                    put(&key, Some(&value_buffer));
+                }
+                None => put(&key, None),
+            }
+        }
+
+        // Entities are always saved in the digest format, which replaces the legacy record.
+        key.tag = DatabaseTag::Entity;
+        key.y = 0;
+        put(&key, None);
+
+        let mut digest = BytesMut::with_capacity(self.entities.len() * 8);
+        for entity in &self.entities {
+            let storage_id = entity.storage_id();
+            digest.put(storage_id.as_ref());
+
+            value_buffer.clear();
+            entity.serialize(&mut value_buffer);
+            batch.put(Entity::actor_key(&storage_id), &value_buffer);
+        }
+
+        let digest_key = Entity::digest_key(self.x, self.z, self.dimension);
+        if digest.is_empty() {
+            batch.delete(digest_key);
+        } else {
+            batch.put(digest_key, digest);
+        }
+    }
+
+    /// Storage IDs of the entity records that this chunk has stored,
+    /// but that no longer belong to any of its entities.
+    pub(crate) fn stale_entities(&self) -> Vec<[u8; 8]> {
+        self.stored_entities
+            .iter()
+            .filter(|id| !self.entities.iter().any(|e| e.storage_id() == **id))
+            .copied()
+            .collect()
+    }
+
+    /// Storage IDs of the entities in this chunk.
+    pub(crate) fn entity_storage_ids(&self) -> Vec<[u8; 8]> {
+        self.entities.iter().map(Entity::storage_id).collect()
+    }
+
+    /// Replaces the storage IDs of the entity records that this chunk has stored.
+    ///
+    /// This should be called after the records written by [`save`](Self::save) have been stored.
+    pub(crate) fn set_stored_entities(&mut self, storage_ids: Vec<[u8; 8]>) {
+        self.stored_entities = storage_ids;
+    }
+
+    /// Stops tracking entity records that have been written by another chunk,
+    /// so that they are not deleted when this chunk is saved.
+    pub(crate) fn forget_stored_entities(
+        &mut self,
+        storage_ids: &HashSet<[u8; 8]>,
+    ) {
+        self.stored_entities.retain(|id| !storage_ids.contains(id));
+    }
+
+    /// X coordinate of the chunk.
+    #[inline]
+    pub const fn x(&self) -> i32 {
+        self.x
+    }
+
+    /// Z coordinate of the chunk.
+    #[inline]
+    pub const fn z(&self) -> i32 {
+        self.z
+    }
+
+    /// Dimension the chunk is located in.
+    #[inline]
+    pub const fn dimension(&self) -> Dimension {
+        self.dimension
+    }
+
+    /// Version of the chunk format that the chunk was loaded with.
+    ///
+    /// Chunks are always saved with [`CURRENT_CHUNK_VERSION`].
+    #[inline]
+    pub const fn version(&self) -> u8 {
+        self.version
+    }
+
+    /// Generation state of the chunk.
+    #[inline]
+    pub const fn finalized_state(&self) -> FinalizedState {
+        self.finalized
+    }
+
+    /// Sets the generation state of the chunk.
+    #[inline]
+    pub fn set_finalized_state(&mut self, state: FinalizedState) {
+        self.finalized = state;
+    }
+
+    /// Height of the highest non-air block in each column,
+    /// relative to the bottom of the dimension.
+    /// The heightmap is stored in ZX order.
+    #[inline]
+    pub const fn heightmap(&self) -> &[i16; HEIGHTMAP_SIZE] {
+        &self.heightmap
+    }
+
+    /// Biome storages, one for each sub chunk from the bottom of the dimension.
+    #[inline]
+    pub fn biomes(&self) -> &[StorageRecord<u32>] {
+        &self.biomes
+    }
+
+    /// Mutable biome storages, one for each sub chunk from the bottom of the dimension.
+    #[inline]
+    pub(crate) fn biomes_mut(&mut self) -> &mut [StorageRecord<u32>] {
+        &mut self.biomes
+    }
+
+    /// Returns the sub chunk at the given vertical index.
+    ///
+    /// Returns `None` if the sub chunk is out of range or only contains air.
+    pub fn sub_chunk(&self, index: i8) -> Option<&SubChunk> {
+        self.sub_chunks.get(self.slot_index(index)?)?.as_ref()
+    }
+
+    /// Returns a mutable reference to the sub chunk at the given vertical index.
+    pub fn sub_chunk_mut(&mut self, index: i8) -> Option<&mut SubChunk> {
+        let slot = self.slot_index(index)?;
+        self.sub_chunks.get_mut(slot)?.as_mut()
+    }
+
+    /// Replaces the sub chunk at the given vertical index.
+    pub fn set_sub_chunk(
+        &mut self,
+        index: i8,
+        mut sub_chunk: SubChunk,
+    ) -> VResult<()> {
+        let Some(slot) = self.slot_index(index) else {
+            bail!(
+                InvalidChunk,
+                "Sub chunk index {index} is out of range for dimension {:?}",
+                self.dimension
+            );
+        };
+
+        sub_chunk.set_index(index);
+        self.sub_chunks[slot] = Some(sub_chunk);
+        self.recalculate_heightmap();
+
+        Ok(())
+    }
+
+    /// Removes the sub chunk at the given vertical index, replacing it with air.
+    pub fn remove_sub_chunk(&mut self, index: i8) -> Option<SubChunk> {
+        let slot = self.slot_index(index)?;
+        let removed = self.sub_chunks[slot].take();
+        self.recalculate_heightmap();
+
+        removed
+    }
+
+    /// Returns the block entity at the given world coordinates.
+    pub fn block_entity(&self, x: i32, y: i32, z: i32) -> Option<&BlockEntity> {
+        self.block_entities.get(&(x, y, z))
+    }
+
+    /// Adds a block entity, replacing the one at the same position.
+    ///
+    /// Fails if the block entity is not located inside of this chunk.
+    pub fn set_block_entity(&mut self, entity: BlockEntity) -> VResult<()> {
+        let [x, y, z] = entity.position.components();
+        if self.local_position(x, y, z).is_none() {
+            bail!(
+                InvalidChunk,
+                "Block entity at [{x}, {y}, {z}] is outside of chunk [{}, {}]",
+                self.x,
+                self.z
+            );
+        }
+
+        self.block_entities.insert((x, y, z), entity);
+        Ok(())
+    }
+
+    /// Removes the block entity at the given world coordinates.
+    pub fn remove_block_entity(
+        &mut self,
+        x: i32,
+        y: i32,
+        z: i32,
+    ) -> Option<BlockEntity> {
+        self.block_entities.remove(&(x, y, z))
+    }
+
+    /// Iterates over all block entities in this chunk.
+    pub fn block_entities(&self) -> impl Iterator<Item = &BlockEntity> {
+        self.block_entities.values()
+    }
+
+    /// Returns the entity with the given unique ID.
+    pub fn entity(&self, unique_id: i64) -> Option<&Entity> {
+        self.entities.iter().find(|e| e.unique_id == unique_id)
+    }
+
+    /// Adds an entity to this chunk, replacing the entity with the same unique ID.
+    ///
+    /// Fails if the entity is not located inside of this chunk.
+    pub fn add_entity(&mut self, entity: Entity) -> VResult<()> {
+        let x = entity.position.x.floor() as i32;
+        let z = entity.position.z.floor() as i32;
+        if x >> 4 != self.x || z >> 4 != self.z {
+            bail!(
+                InvalidChunk,
+                "Entity {} at [{x}, {z}] is outside of chunk [{}, {}]",
+                entity.identifier,
+                self.x,
+                self.z
+            );
+        }
+
+        self.remove_entity(entity.unique_id);
+        self.entities.push(entity);
+        Ok(())
+    }
+
+    /// Removes the entity with the given unique ID.
+    pub fn remove_entity(&mut self, unique_id: i64) -> Option<Entity> {
+        let index = self
+            .entities
+            .iter()
+            .position(|e| e.unique_id == unique_id)?;
+        Some(self.entities.remove(index))
+    }
+
+    /// Returns all entities in this chunk.
+    #[inline]
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    /// Iterates over all existing sub chunks, together with their vertical index.
+    pub fn sub_chunks(&self) -> impl Iterator<Item = (i8, &SubChunk)> {
+        let min = self.dimension.min_sub_chunk();
+        self.sub_chunks
+            .iter()
+            .enumerate()
+            .filter_map(move |(slot, s)| {
+                s.as_ref().map(|s| (min + slot as i8, s))
+            })
+    }
+
+    /// Amount of sub chunks that have to be sent to a client,
+    /// counted from the bottom of the dimension up to and including the highest existing sub chunk.
+    pub fn network_sub_chunk_count(&self) -> usize {
+        self.sub_chunks
+            .iter()
+            .rposition(Option::is_some)
+            .map(|slot| slot + 1)
+            .unwrap_or(0)
+    }
+
+    /// Encodes the sub chunks and biomes in the format used by the `LevelChunk` packet.
+    ///
+    /// This writes [`network_sub_chunk_count`](Self::network_sub_chunk_count) sub chunks,
+    /// followed by the biomes of every sub chunk, the border blocks and the block entities.
+    pub fn serialize_network(
+        &self,
+        registry: &BlockRegistry,
+        buffer: &mut BytesMut,
+    ) {
+        let min = self.dimension.min_sub_chunk();
+        for slot in 0..self.network_sub_chunk_count() {
+            self.serialize_network_sub_chunk(
+                min + slot as i8,
+                registry,
+                buffer,
+            );
+        }
+
+        self.serialize_network_biomes(buffer);
+
+        // Education Edition border blocks.
+        buffer.put_u8(0);
+
+        self.serialize_network_block_entities(None, buffer);
+    }
+
+    /// Encodes the block entities in the network format.
+    ///
+    /// If an index is given, only the block entities in that sub chunk are written.
+    pub fn serialize_network_block_entities(
+        &self,
+        index: Option<i8>,
+        buffer: &mut BytesMut,
+    ) {
+        for entity in self.block_entities.values() {
+            let sub_chunk = (entity.position.y >> 4) as i8;
+            if index.is_none() || index == Some(sub_chunk) {
+                entity.serialize_network(buffer);
+            }
+        }
+    }
+
+    /// Encodes a single sub chunk in the network format.
+    ///
+    /// Sub chunks that do not exist are encoded without any layers,
+    /// which the client fills with air.
+    pub fn serialize_network_sub_chunk(
+        &self,
+        index: i8,
+        registry: &BlockRegistry,
+        buffer: &mut BytesMut,
+    ) {
+        match self.sub_chunk(index) {
+            Some(sub_chunk) => sub_chunk.serialize_network(registry, buffer),
+            None => {
+                buffer.put_u8(SubChunkVersion::Limitless as u8);
+                buffer.put_u8(0);
+                buffer.put_i8(index);
+            }
+        }
+    }
+
+    /// Encodes the biomes of every sub chunk in the network format.
+    pub fn serialize_network_biomes(&self, buffer: &mut BytesMut) {
+        self.serialize_biomes(buffer, PaletteEncoding::Network);
+    }
+
+    /// Returns the block at the given world coordinates.
+    ///
+    /// Returns `None` if the position is not inside of this chunk.
+    pub fn get(&self, x: i32, y: i32, z: i32) -> Option<&nbt::Value> {
+        let (slot, position) = self.local_position(x, y, z)?;
+        match &self.sub_chunks[slot] {
+            Some(sub_chunk) => sub_chunk.get(position),
+            None => None,
+        }
+    }
+
+    /// Sets the block at the given world coordinates.
+    ///
+    /// Sub chunks that do not exist yet are created.
+    pub fn set(
+        &mut self,
+        x: i32,
+        y: i32,
+        z: i32,
+        block: nbt::Value,
+    ) -> VResult<()> {
+        let Some((slot, position)) = self.local_position(x, y, z) else {
+            bail!(
+                InvalidChunk,
+                "Position [{x}, {y}, {z}] is outside of chunk [{}, {}]",
+                self.x,
+                self.z
+            );
+        };
+
+        let air_block = is_air(&block);
+        let index = self.dimension.min_sub_chunk() + slot as i8;
+        let sub_chunk = match &mut self.sub_chunks[slot] {
+            Some(sub_chunk) => sub_chunk,
+            // Setting air in a sub chunk that does not exist is a no-op.
+            None if air_block => return Ok(()),
+            empty @ None => empty.insert(SubChunk::new(index, air())),
+        };
+
+        sub_chunk.set(position, block)?;
+
+        let column = ((z & 0xf) << 4 | (x & 0xf)) as usize;
+        let height = (y - self.dimension.height_range().start + 1) as i16;
+        if !air_block && height > self.heightmap[column] {
+            self.heightmap[column] = height;
+        } else if air_block && height == self.heightmap[column] {
+            self.heightmap[column] =
+                self.column_height((x & 0xf) as u8, (z & 0xf) as u8);
+        }
+
+        Ok(())
+    }
+
+    /// Returns the biome ID at the given world coordinates.
+    pub fn get_biome(&self, x: i32, y: i32, z: i32) -> Option<u32> {
+        let (slot, position) = self.local_position(x, y, z)?;
+        self.biomes.get(slot)?.get(position).copied()
+    }
+
+    /// Sets the biome ID at the given world coordinates.
+    pub fn set_biome(
+        &mut self,
+        x: i32,
+        y: i32,
+        z: i32,
+        biome: u32,
+    ) -> VResult<()> {
+        let Some((slot, position)) = self.local_position(x, y, z) else {
+            bail!(
+                InvalidChunk,
+                "Position [{x}, {y}, {z}] is outside of chunk [{}, {}]",
+                self.x,
+                self.z
+            );
+        };
+
+        self.biomes[slot].set(position, biome);
+        Ok(())
+    }
+
+    /// Replaces the biome of every position in this chunk.
+    pub fn fill_biome(&mut self, biome: u32) {
+        for record in &mut self.biomes {
+            record.fill(biome);
+        }
+    }
+
+    /// Converts a vertical sub chunk index to an index into the sub chunk list.
+    fn slot_index(&self, index: i8) -> Option<usize> {
+        let slot = index as i32 - self.dimension.min_sub_chunk() as i32;
+        (0..self.sub_chunks.len() as i32)
+            .contains(&slot)
+            .then_some(slot as usize)
+    }
+
+    /// Converts world coordinates to a sub chunk slot and a position within that sub chunk.
+    fn local_position(
+        &self,
+        x: i32,
+        y: i32,
+        z: i32,
+    ) -> Option<(usize, Vector3b)> {
+        if x >> 4 != self.x
+            || z >> 4 != self.z
+            || !self.dimension.height_range().contains(&y)
+        {
+            return None;
+        }
+
+        let slot = self.slot_index((y >> 4) as i8)?;
+        let position =
+            Vector3b::from([(x & 0xf) as u8, (y & 0xf) as u8, (z & 0xf) as u8]);
+
+        Some((slot, position))
+    }
+
+    /// Finds the height of the highest non-air block in a column.
+    fn column_height(&self, x: u8, z: u8) -> i16 {
+        for (slot, sub_chunk) in self.sub_chunks.iter().enumerate().rev() {
+            let Some(sub_chunk) = sub_chunk else {
+                continue;
+            };
+
+            for y in (0..16u8).rev() {
+                let block = sub_chunk.get(Vector3b::from([x, y, z]));
+                if block.map(|b| !is_air(b)).unwrap_or(false) {
+                    return (slot * 16 + y as usize + 1) as i16;
+                }
+            }
+        }
+
+        0
+    }
+
+    /// Recalculates the entire heightmap.
+    fn recalculate_heightmap(&mut self) {
+        for z in 0..16u8 {
+            for x in 0..16u8 {
+                self.heightmap[((z as usize) << 4) | x as usize] =
+                    self.column_height(x, z);
+            }
+        }
+    }
+
+    /// Decodes the heightmap and 3D biomes.
+    fn deserialize_biome_3d(&mut self, mut buffer: Bytes) -> VResult<()> {
+        self.deserialize_heightmap(&mut buffer)?;
+
+        for slot in 0..self.biomes.len() {
+            // Some chunks do not store biomes for every sub chunk.
+            if !buffer.has_remaining() {
+                break;
+            }
+
+            if buffer[0] >> 1 == COPY_PREVIOUS_HEADER {
+                buffer.advance(1);
+                if slot == 0 {
+                    bail!(
+                        InvalidChunk,
+                        "First biome storage cannot copy the previous storage"
+                    );
+                }
+
+                self.biomes[slot] = self.biomes[slot - 1].clone();
+            } else {
+                self.biomes[slot] = StorageRecord::deserialize(
+                    &mut buffer,
+                    PaletteEncoding::Disk,
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Decodes the heightmap and 2D biomes used before 1.18,
+    /// and converts the biomes to 3D biomes.
+    fn deserialize_data_2d(&mut self, mut buffer: Bytes) -> VResult<()> {
+        self.deserialize_heightmap(&mut buffer)?;
+        if buffer.remaining() < 256 {
+            bail!(InvalidChunk, "2D biome map is too short");
+        }
+
+        // The 2D biome map is stored in ZX order,
+        // while storage records use XZY order.
+        let biomes = buffer.split_to(256);
+        let mut indices = [0u16; CHUNK_SIZE];
+        let mut palette = Vec::new();
+        for (offset, index) in indices.iter_mut().enumerate() {
+            let x = (offset >> 8) & 0xf;
+            let z = (offset >> 4) & 0xf;
+            let biome = biomes[(z << 4) | x] as u32;
+
+            *index = match palette.iter().position(|b| *b == biome) {
+                Some(i) => i as u16,
+                None => {
+                    palette.push(biome);
+                    (palette.len() - 1) as u16
+                }
+            };
+        }
+
+        let record = StorageRecord::new(indices, palette)?;
+        for biome in &mut self.biomes {
+            *biome = record.clone();
+        }
+
+        Ok(())
+    }
+
+    fn deserialize_heightmap(&mut self, buffer: &mut Bytes) -> VResult<()> {
+        if buffer.remaining() < HEIGHTMAP_SIZE * 2 {
+            bail!(InvalidChunk, "Heightmap is too short");
+        }
+
+        for height in &mut self.heightmap {
+            *height = buffer.get_i16_le();
+        }
+
+        Ok(())
+    }
+
+    /// Encodes the heightmap and 3D biomes.
+    fn serialize_biome_3d(&self, buffer: &mut BytesMut) {
+        buffer.clear();
+        for height in self.heightmap {
+            buffer.put_i16_le(height);
+        }
+
+        self.serialize_biomes(buffer, PaletteEncoding::Disk);
+    }
+
+    /// Encodes the biome storages.
+    /// Storages that are equal to the previous one are replaced by a single header byte.
+    fn serialize_biomes(
+        &self,
+        buffer: &mut BytesMut,
+        encoding: PaletteEncoding,
+    ) {
+        let runtime_flag = (encoding == PaletteEncoding::Network) as u8;
+        for (slot, biome) in self.biomes.iter().enumerate() {
+            if slot > 0 && self.biomes[slot - 1] == *biome {
+                buffer.put_u8(COPY_PREVIOUS_HEADER << 1 | runtime_flag);
+            } else {
+                biome.serialize(buffer, encoding);
+            }
+        }
+    }
+}
@@ -1,12 +1,13 @@
 use std::{
     ffi::{c_void, CStr, CString},
+    marker::PhantomData,
     os::raw::{c_char, c_int},
 };
 
 use bytes::{Bytes, BytesMut};
-use common::{error, VError, VResult};
+use common::{error, Deserialize, VError, VResult};
 
-use crate::ffi;
+use crate::{ffi, DatabaseKey, DatabaseTag, Dimension, LevelKey};
 
 /// Rust interface around a C++ LevelDB database.
 #[derive(Debug)]
@@ -18,7 +19,14 @@ pub struct ChunkDatabase {
 
 impl ChunkDatabase {
     /// Opens the database at the specified path.
+    ///
+    /// An empty database is created if it does not exist yet.
     pub fn new<P: AsRef<str>>(path: P) -> VResult<Self> {
+        // LevelDB only creates the final directory, not its parents.
+        std::fs::create_dir_all(path.as_ref()).map_err(|e| {
+            error!(DatabaseFailure, "Failed to create {}: {}", path.as_ref(), e)
+        })?;
+
         let ffi_path = CString::new(path.as_ref())?;
         let result = unsafe {
             // SAFETY: This function is guaranteed to not return exceptions.
@@ -35,7 +43,12 @@ impl ChunkDatabase {
 
     /// Loads the value of the given key.
     /// This function requires a raw key, i.e. the key must have been serialised already.
-    pub fn get_raw_key<K: AsRef<[u8]>>(&self, key: K) -> VResult<Bytes> {
+    ///
+    /// Returns `None` if the key does not exist in the database.
+    pub fn get_raw_key<K: AsRef<[u8]>>(
+        &self,
+        key: K,
+    ) -> VResult<Option<Bytes>> {
         let key = key.as_ref();
         let result = unsafe {
             // SAFETY: This function is guaranteed to not modify any arguments.
@@ -50,6 +63,11 @@ impl ChunkDatabase {
         };
 
         if result.is_success == 1 {
+            if result.data.is_null() {
+                // Key does not exist.
+                return Ok(None);
+            }
+
             let data = unsafe {
                 std::slice::from_raw_parts(
                     result.data as *mut u8,
@@ -57,15 +75,169 @@ impl ChunkDatabase {
                 )
             };
 
-            let buffer = Bytes::from(data);
+            let buffer = Bytes::copy_from_slice(data);
 
             unsafe {
-                // SAFETY: Data is safe to deallocate because BytesMut copies the data.
+                // SAFETY: Data is safe to deallocate because Bytes copies the data.
                 // and it is not used anywhere else.
                 ffi::level_deallocate_array(result.data as *mut c_char)
             };
 
-            Ok(buffer)
+            Ok(Some(buffer))
+        } else {
+            Err(translate_ffi_error(result))
+        }
+    }
+
+    /// Writes a value to the given key, overwriting any existing value.
+    /// This function requires a raw key, i.e. the key must have been serialised already.
+    ///
+    /// Prefer [`write_batch`](Self::write_batch) when writing multiple keys at once.
+    pub fn put_raw_key<K: AsRef<[u8]>, V: AsRef<[u8]>>(
+        &self,
+        key: K,
+        value: V,
+    ) -> VResult<()> {
+        let key = key.as_ref();
+        let value = value.as_ref();
+        let result = unsafe {
+            // SAFETY: This function is guaranteed to not modify any arguments.
+            // LevelDB copies the key and value, the buffers are not used after the call.
+            ffi::level_put_key(
+                self.pointer,
+                key.as_ptr() as *const c_char,
+                key.len() as c_int,
+                value.as_ptr() as *const c_char,
+                value.len() as c_int,
+            )
+        };
+
+        if result.is_success == 1 {
+            Ok(())
+        } else {
+            Err(translate_ffi_error(result))
+        }
+    }
+
+    /// Removes the given key from the database.
+    /// Deleting a key that does not exist is not an error.
+    pub fn delete_raw_key<K: AsRef<[u8]>>(&self, key: K) -> VResult<()> {
+        let key = key.as_ref();
+        let result = unsafe {
+            // SAFETY: This function is guaranteed to not modify any arguments.
+            ffi::level_delete_key(
+                self.pointer,
+                key.as_ptr() as *const c_char,
+                key.len() as c_int,
+            )
+        };
+
+        if result.is_success == 1 {
+            Ok(())
+        } else {
+            Err(translate_ffi_error(result))
+        }
+    }
+
+    /// Creates an iterator over all keys and values in the database.
+    /// Keys are iterated in ascending byte order.
+    pub fn iter(&self) -> DatabaseIterator<'_> {
+        let mut iter = DatabaseIterator::new(self);
+        iter.seek_to_first();
+        iter
+    }
+
+    /// Creates an iterator that starts at the first key equal to or greater than `key`.
+    pub fn iter_from<K: AsRef<[u8]>>(&self, key: K) -> DatabaseIterator<'_> {
+        let mut iter = DatabaseIterator::new(self);
+        iter.seek(key);
+        iter
+    }
+
+    /// Creates an iterator over all keys that start with the given prefix.
+    pub fn iter_prefix<K: AsRef<[u8]>>(
+        &self,
+        prefix: K,
+    ) -> DatabaseIterator<'_> {
+        let prefix = Bytes::copy_from_slice(prefix.as_ref());
+
+        let mut iter = DatabaseIterator::new(self);
+        iter.seek(&prefix);
+        iter.prefix = Some(prefix);
+        iter
+    }
+
+    /// Creates an iterator over all records belonging to the given chunk.
+    ///
+    /// This includes sub chunks, biomes, block entities, entities and any other data
+    /// stored with a [`DatabaseTag`].
+    pub fn iter_chunk(
+        &self,
+        x: i32,
+        z: i32,
+        dimension: Dimension,
+    ) -> impl Iterator<Item = (DatabaseKey, Bytes)> + '_ {
+        let prefix = DatabaseKey::chunk_prefix(x, z, dimension);
+
+        // Overworld chunk keys are a prefix of the keys of the other dimensions at the same
+        // coordinates, so the dimension of the parsed key has to be checked as well.
+        self.iter_prefix(prefix).filter_map(move |(k, v)| {
+            let key = DatabaseKey::deserialize(k).ok()?;
+            (key.dimension == dimension).then_some((key, v))
+        })
+    }
+
+    /// Returns all keys in the database, parsed into a [`LevelKey`].
+    ///
+    /// Returns an error if LevelDB failed while iterating,
+    /// instead of silently returning only the keys that were read before the failure.
+    pub fn keys(&self) -> VResult<Vec<LevelKey>> {
+        let mut iter = self.iter();
+
+        // LevelKey deserialisation never fails, unknown keys are returned as such.
+        let keys = iter
+            .by_ref()
+            .filter_map(|(k, _)| LevelKey::deserialize(k).ok())
+            .collect();
+
+        iter.status()?;
+        Ok(keys)
+    }
+
+    /// Returns the position of every chunk stored in the database.
+    ///
+    /// Each chunk stores a version record, this function returns the keys of those records.
+    pub fn chunks(&self) -> VResult<Vec<DatabaseKey>> {
+        Ok(self
+            .keys()?
+            .into_iter()
+            .filter_map(|key| match key {
+                LevelKey::Chunk(
+                    key @ DatabaseKey {
+                        tag:
+                            DatabaseTag::ChunkVersion
+                            | DatabaseTag::LegacyChunkVersion,
+                        ..
+                    },
+                ) => Some(key),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// Atomically applies all operations in the batch.
+    /// Either all of the operations succeed, or none of them are applied.
+    ///
+    /// The batch is not cleared and can be reused after calling [`WriteBatch::clear`].
+    pub fn write_batch(&self, batch: &WriteBatch) -> VResult<()> {
+        let result = unsafe {
+            // SAFETY: Both pointers are valid for the lifetime of their owners.
+            // The batch is not modified by this function.
+            ffi::level_write_batch(self.pointer, batch.pointer)
+        };
+
+        if result.is_success == 1 {
+            Ok(())
         } else {
             Err(translate_ffi_error(result))
         }
@@ -87,8 +259,221 @@ unsafe impl Send for ChunkDatabase {}
 /// SAFETY: The LevelDB authors explicitly state their database is thread-safe.
 unsafe impl Sync for ChunkDatabase {}
 
+/// Collection of database updates that are applied atomically.
+///
+/// Operations are only written once the batch is passed to [`ChunkDatabase::write_batch`].
+#[derive(Debug)]
+pub struct WriteBatch {
+    /// Pointer to the C++ WriteBatch.
+    /// This must be deallocated by C++ when the batch is dropped.
+    pointer: *mut c_void,
+    /// Amount of operations currently in the batch.
+    len: usize,
+}
+
+impl WriteBatch {
+    /// Creates a new empty batch.
+    pub fn new() -> Self {
+        let pointer = unsafe {
+            // SAFETY: This function does not throw and always returns a valid pointer.
+            ffi::level_create_write_batch()
+        };
+
+        Self { pointer, len: 0 }
+    }
+
+    /// Adds a put operation for the given raw key to the batch.
+    pub fn put<K: AsRef<[u8]>, V: AsRef<[u8]>>(&mut self, key: K, value: V) {
+        let key = key.as_ref();
+        let value = value.as_ref();
+        unsafe {
+            // SAFETY: The key and value are copied into the batch.
+            ffi::level_write_batch_put(
+                self.pointer,
+                key.as_ptr() as *const c_char,
+                key.len() as c_int,
+                value.as_ptr() as *const c_char,
+                value.len() as c_int,
+            );
+        }
+
+        self.len += 1;
+    }
+
+    /// Adds a delete operation for the given raw key to the batch.
+    pub fn delete<K: AsRef<[u8]>>(&mut self, key: K) {
+        let key = key.as_ref();
+        unsafe {
+            // SAFETY: The key is copied into the batch.
+            ffi::level_write_batch_delete(
+                self.pointer,
+                key.as_ptr() as *const c_char,
+                key.len() as c_int,
+            );
+        }
+
+        self.len += 1;
+    }
+
+    /// Removes all operations from the batch.
+    pub fn clear(&mut self) {
+        unsafe {
+            // SAFETY: The pointer is valid for the lifetime of this batch.
+            ffi::level_write_batch_clear(self.pointer);
+        }
+
+        self.len = 0;
+    }
+
+    /// Amount of operations in this batch.
+    #[inline]
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Whether this batch contains any operations.
+    #[inline]
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Default for WriteBatch {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for WriteBatch {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::level_destroy_write_batch(self.pointer);
+        }
+    }
+}
+
+/// SAFETY: The batch is only accessed through mutable references,
+/// it can safely be moved to another thread.
+unsafe impl Send for WriteBatch {}
+
+/// Iterator over the keys and values in a [`ChunkDatabase`].
+///
+/// Every key and value is copied out of LevelDB, so the items can outlive the iterator.
+#[derive(Debug)]
+pub struct DatabaseIterator<'db> {
+    /// Pointer to the C++ iterator.
+    /// The iterator must be deallocated before the database is closed,
+    /// which is guaranteed by the lifetime.
+    pointer: *mut c_void,
+    /// If set, the iterator stops at the first key that does not start with this prefix.
+    prefix: Option<Bytes>,
+    _marker: PhantomData<&'db ChunkDatabase>,
+}
+
+impl<'db> DatabaseIterator<'db> {
+    fn new(database: &'db ChunkDatabase) -> Self {
+        let pointer = unsafe {
+            // SAFETY: The database pointer is valid for the lifetime of the iterator.
+            ffi::level_create_iterator(database.pointer)
+        };
+
+        Self {
+            pointer,
+            prefix: None,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Moves the iterator to the first key in the database.
+    pub fn seek_to_first(&mut self) {
+        unsafe {
+            ffi::level_iterator_seek_to_first(self.pointer);
+        }
+    }
+
+    /// Moves the iterator to the first key that is equal to or greater than `key`.
+    pub fn seek<K: AsRef<[u8]>>(&mut self, key: K) {
+        let key = key.as_ref();
+        unsafe {
+            // SAFETY: LevelDB does not hold on to the key after seeking.
+            ffi::level_iterator_seek(
+                self.pointer,
+                key.as_ptr() as *const c_char,
+                key.len() as c_int,
+            );
+        }
+    }
+
+    /// Returns an error if the underlying LevelDB iterator encountered an error.
+    ///
+    /// LevelDB iterators become invalid on error, which ends the iteration.
+    /// This should be checked after iterating to distinguish errors from the end of the data.
+    pub fn status(&self) -> VResult<()> {
+        let result = unsafe { ffi::level_iterator_status(self.pointer) };
+
+        if result.is_success == 1 {
+            Ok(())
+        } else {
+            Err(translate_ffi_error(result))
+        }
+    }
+}
+
+impl Iterator for DatabaseIterator<'_> {
+    type Item = (Bytes, Bytes);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let is_valid = unsafe { ffi::level_iterator_valid(self.pointer) };
+        if is_valid == 0 {
+            return None;
+        }
+
+        let (key, value) = unsafe {
+            // SAFETY: The iterator is valid, so the key and value exist.
+            // They are copied before the iterator is moved.
+            let key = ffi::level_iterator_key(self.pointer);
+            let value = ffi::level_iterator_value(self.pointer);
+
+            (
+                Bytes::copy_from_slice(std::slice::from_raw_parts(
+                    key.data as *const u8,
+                    key.size as usize,
+                )),
+                Bytes::copy_from_slice(std::slice::from_raw_parts(
+                    value.data as *const u8,
+                    value.size as usize,
+                )),
+            )
+        };
+
+        if let Some(prefix) = &self.prefix {
+            if !key.starts_with(prefix) {
+                return None;
+            }
+        }
+
+        unsafe {
+            ffi::level_iterator_next(self.pointer);
+        }
+
+        Some((key, value))
+    }
+}
+
+impl Drop for DatabaseIterator<'_> {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::level_destroy_iterator(self.pointer);
+        }
+    }
+}
+
 /// Translates an error received from the FFI, into a [`VError`].
 fn translate_ffi_error(result: ffi::LevelResult) -> VError {
+    if result.data.is_null() {
+        return error!(DatabaseFailure, "Unknown database error");
+    }
+
     let ffi_err = unsafe {
         // SAFETY: This string is guaranteed to have a null termination character.
         CStr::from_ptr(result.data as *const c_char)
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use common::{bail, VResult, Vector3f};
+
+use crate::{DatabaseKey, Dimension};
+
+/// Prefix of the keys that list the entities in a chunk.
+pub const DIGEST_PREFIX: &[u8] = b"digp";
+/// Prefix of the keys that store a single entity.
+pub const ACTOR_PREFIX: &[u8] = b"actorprefix";
+
+/// An entity stored in a chunk, such as a mob or a dropped item.
+///
+/// Since 1.18.30, entities are stored in separate `actorprefix` records,
+/// which are listed in the `digp` record of the chunk.
+/// Older worlds store all entities of a chunk in a single [`DatabaseTag::Entity`](crate::DatabaseTag::Entity) record.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entity {
+    /// Unique ID of the entity.
+    pub unique_id: i64,
+    /// Type of entity, such as `minecraft:zombie`.
+    pub identifier: String,
+    /// Position of the entity in the world.
+    pub position: Vector3f,
+    /// All other tags.
+    pub data: HashMap<String, nbt::Value>,
+}
+
+impl Entity {
+    /// Creates an entity without any data.
+    pub fn new(
+        unique_id: i64,
+        identifier: impl Into<String>,
+        position: Vector3f,
+    ) -> Self {
+        Self {
+            unique_id,
+            identifier: identifier.into(),
+            position,
+            data: HashMap::new(),
+        }
+    }
+
+    /// Returns the key of the record that lists the entities of a chunk.
+    pub fn digest_key(x: i32, z: i32, dimension: Dimension) -> BytesMut {
+        let mut key = BytesMut::with_capacity(DIGEST_PREFIX.len() + 12);
+        key.put(DIGEST_PREFIX);
+        key.put(DatabaseKey::chunk_prefix(x, z, dimension));
+        key
+    }
+
+    /// Returns the key of the record that stores the entity with the given storage ID.
+    pub fn actor_key(storage_id: &[u8]) -> BytesMut {
+        let mut key = BytesMut::with_capacity(ACTOR_PREFIX.len() + 8);
+        key.put(ACTOR_PREFIX);
+        key.put(storage_id);
+        key
+    }
+
+    /// ID used in the digest and the key of the entity record.
+    #[inline]
+    pub const fn storage_id(&self) -> [u8; 8] {
+        self.unique_id.to_le_bytes()
+    }
+
+    /// Converts an NBT compound to an entity.
+    ///
+    /// The compound must contain the `identifier`, `UniqueID` and `Pos` tags.
+    pub fn from_nbt(value: nbt::Value) -> VResult<Self> {
+        let nbt::Value::Compound(mut data) = value else {
+            bail!(InvalidChunk, "Expected entity to be a compound");
+        };
+
+        let Some(nbt::Value::String(identifier)) = data.remove("identifier")
+        else {
+            bail!(InvalidChunk, "Entity is missing its identifier");
+        };
+
+        let Some(nbt::Value::Long(unique_id)) = data.remove("UniqueID") else {
+            bail!(InvalidChunk, "Entity {identifier} is missing its unique ID");
+        };
+
+        let position = match data.remove("Pos") {
+            Some(nbt::Value::List(list)) if list.len() == 3 => {
+                let mut position = [0.0; 3];
+                for (component, value) in position.iter_mut().zip(list) {
+                    let nbt::Value::Float(value) = value else {
+                        bail!(
+                            InvalidChunk,
+                            "Position of entity {identifier} must contain floats"
+                        );
+                    };
+                    *component = value;
+                }
+                Vector3f::from(position)
+            }
+            _ => bail!(
+                InvalidChunk,
+                "Entity {identifier} is missing its position"
+            ),
+        };
+
+        Ok(Self { unique_id, identifier, position, data })
+    }
+
+    /// Converts the entity to an NBT compound, including its identifier, unique ID and position.
+    pub fn to_nbt(&self) -> nbt::Value {
+        let mut data = self.data.clone();
+        data.extend([
+            (
+                "identifier".to_owned(),
+                nbt::Value::String(self.identifier.clone()),
+            ),
+            ("UniqueID".to_owned(), nbt::Value::Long(self.unique_id)),
+            (
+                "Pos".to_owned(),
+                nbt::Value::List(vec![
+                    nbt::Value::Float(self.position.x),
+                    nbt::Value::Float(self.position.y),
+                    nbt::Value::Float(self.position.z),
+                ]),
+            ),
+        ]);
+
+        nbt::Value::Compound(data)
+    }
+
+    /// Reads a list of entities, which consists of little-endian NBT compounds stored back to back.
+    ///
+    /// This is the format of the legacy entity records and of the `actorprefix` records.
+    pub fn deserialize_all(mut buffer: Bytes) -> VResult<Vec<Self>> {
+        let mut entities = Vec::new();
+        while buffer.has_remaining() {
+            let tag = nbt::deserialize_le(&mut buffer)?;
+            entities.push(Self::from_nbt(tag.value)?);
+        }
+
+        Ok(entities)
+    }
+
+    /// Writes the entity in the database format.
+    pub fn serialize(&self, buffer: &mut BytesMut) {
+        nbt::serialize_le("", &self.to_nbt(), buffer);
+    }
+}
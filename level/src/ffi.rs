@@ -11,18 +11,93 @@ pub struct LevelResult {
     pub data: *mut c_void,
 }
 
+/// Slice of data owned by LevelDB.
+///
+/// This data must not be deallocated and is only valid until its owner is modified.
+#[derive(Debug)]
+#[repr(C)]
+pub struct LevelSlice {
+    pub size: c_int,
+    pub data: *const c_char,
+}
+
 extern "C" {
-    /// Open a LevelDB database.
+    /// Open a LevelDB database, creating it if it does not exist.
     pub fn level_open_database(path: *const c_char) -> LevelResult;
     /// Close a LevelDB database.
     /// This also frees the pointers, it must no longer be used.
     pub fn level_close_database(database: *mut c_void);
     /// Loads a key from the database.
+    /// If the key does not exist, the result is successful but contains a null pointer.
     pub fn level_get_key(
         database: *mut c_void,
         key: *const c_char,
         key_size: c_int,
     ) -> LevelResult;
+    /// Writes a single key-value pair into the database.
+    pub fn level_put_key(
+        database: *mut c_void,
+        key: *const c_char,
+        key_size: c_int,
+        value: *const c_char,
+        value_size: c_int,
+    ) -> LevelResult;
+    /// Removes a single key from the database.
+    pub fn level_delete_key(
+        database: *mut c_void,
+        key: *const c_char,
+        key_size: c_int,
+    ) -> LevelResult;
+    /// Creates a new empty write batch.
+    pub fn level_create_write_batch() -> *mut c_void;
+    /// Destroys a write batch.
+    /// This also frees the pointer, it must no longer be used.
+    pub fn level_destroy_write_batch(batch: *mut c_void);
+    /// Adds a put operation to the write batch.
+    pub fn level_write_batch_put(
+        batch: *mut c_void,
+        key: *const c_char,
+        key_size: c_int,
+        value: *const c_char,
+        value_size: c_int,
+    );
+    /// Adds a delete operation to the write batch.
+    pub fn level_write_batch_delete(
+        batch: *mut c_void,
+        key: *const c_char,
+        key_size: c_int,
+    );
+    /// Removes all operations from the write batch.
+    pub fn level_write_batch_clear(batch: *mut c_void);
+    /// Atomically applies all operations in the batch to the database.
+    pub fn level_write_batch(
+        database: *mut c_void,
+        batch: *mut c_void,
+    ) -> LevelResult;
+    /// Creates an iterator over all keys in the database.
+    /// The iterator is initially invalid and must be positioned using one of the seek functions.
+    pub fn level_create_iterator(database: *mut c_void) -> *mut c_void;
+    /// Destroys an iterator.
+    /// This also frees the pointer, it must no longer be used.
+    pub fn level_destroy_iterator(iterator: *mut c_void);
+    /// Moves the iterator to the first key in the database.
+    pub fn level_iterator_seek_to_first(iterator: *mut c_void);
+    /// Moves the iterator to the first key that is equal to or greater than the given key.
+    pub fn level_iterator_seek(
+        iterator: *mut c_void,
+        key: *const c_char,
+        key_size: c_int,
+    );
+    /// Moves the iterator to the next key.
+    pub fn level_iterator_next(iterator: *mut c_void);
+    /// Returns 1 if the iterator is positioned at a key, 0 otherwise.
+    pub fn level_iterator_valid(iterator: *mut c_void) -> c_int;
+    /// Returns the key the iterator is currently positioned at.
+    pub fn level_iterator_key(iterator: *mut c_void) -> LevelSlice;
+    /// Returns the value the iterator is currently positioned at.
+    pub fn level_iterator_value(iterator: *mut c_void) -> LevelSlice;
+    /// Returns whether an error occurred while iterating.
+    pub fn level_iterator_status(iterator: *mut c_void) -> LevelResult;
     /// Deallocates a string previously allocated by another function.
     pub fn level_deallocate_array(array: *mut c_char);
 }
@@ -0,0 +1,167 @@
+use common::{bail, VResult};
+
+use crate::{BlockRegistry, Chunk, Dimension};
+
+/// Default layers of a superflat world.
+pub const DEFAULT_FLAT_PRESET: &str =
+    "minecraft:bedrock,2*minecraft:dirt,minecraft:grass;minecraft:plains";
+
+/// Creates chunks that do not exist in the database yet.
+pub trait Generator: std::fmt::Debug + Send + Sync {
+    /// Generates the chunk at the given position.
+    fn generate(&self, x: i32, z: i32, dimension: Dimension) -> VResult<Chunk>;
+}
+
+/// Generates chunks that only contain air.
+#[derive(Debug, Default)]
+pub struct VoidGenerator;
+
+impl Generator for VoidGenerator {
+    fn generate(&self, x: i32, z: i32, dimension: Dimension) -> VResult<Chunk> {
+        Ok(Chunk::new(x, z, dimension))
+    }
+}
+
+/// Single layer of a superflat world.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FlatLayer {
+    /// Block state that the layer consists of.
+    pub block: nbt::Value,
+    /// Thickness of the layer in blocks.
+    pub height: u32,
+}
+
+/// Generates superflat chunks.
+///
+/// Layers are stacked on top of each other, starting at the bottom of the dimension.
+#[derive(Debug, Clone)]
+pub struct FlatGenerator {
+    /// Layers, from the bottom up.
+    layers: Vec<FlatLayer>,
+    /// Biome that every chunk is filled with.
+    biome: u32,
+}
+
+impl FlatGenerator {
+    /// Creates a generator from a list of layers.
+    pub const fn new(layers: Vec<FlatLayer>, biome: u32) -> Self {
+        Self { layers, biome }
+    }
+
+    /// Parses a preset in the vanilla format.
+    ///
+    /// A preset consists of comma-separated layers, from the bottom up,
+    /// optionally followed by a semicolon and the name of the biome.
+    /// Each layer is a block name, optionally prefixed with the height and an asterisk,
+    /// such as `minecraft:bedrock,2*minecraft:dirt,minecraft:grass;minecraft:plains`.
+    ///
+    /// Block names are resolved to their default state using the registry,
+    /// biome names are resolved using `biome_id`.
+    /// If the preset does not specify a biome, the default biome of the dimension is used.
+    pub fn from_preset<F>(
+        preset: &str,
+        registry: &BlockRegistry,
+        biome_id: F,
+    ) -> VResult<Self>
+    where
+        F: FnOnce(&str) -> Option<u32>,
+    {
+        let mut parts = preset.split(';');
+        let layer_list = parts.next().unwrap_or("");
+
+        let mut layers = Vec::new();
+        for layer in layer_list.split(',').map(str::trim) {
+            if layer.is_empty() {
+                continue;
+            }
+
+            let (height, name) = match layer.split_once('*') {
+                Some((height, name)) => {
+                    let Ok(height) = height.trim().parse::<u32>() else {
+                        bail!(
+                            Other,
+                            "Invalid layer height in flat world preset: {layer}"
+                        );
+                    };
+                    (height, name.trim())
+                }
+                None => (1, layer),
+            };
+
+            let name = if name.contains(':') {
+                name.to_owned()
+            } else {
+                format!("minecraft:{name}")
+            };
+
+            let Some(block) = registry.default_state(&name) else {
+                bail!(Other, "Unknown block {name} in flat world preset");
+            };
+
+            layers.push(FlatLayer { block: block.clone(), height });
+        }
+
+        let biome = match parts.next().map(str::trim) {
+            Some(name) if !name.is_empty() => {
+                let name = if name.contains(':') {
+                    name.to_owned()
+                } else {
+                    format!("minecraft:{name}")
+                };
+
+                let Some(id) = biome_id(&name) else {
+                    bail!(Other, "Unknown biome {name} in flat world preset");
+                };
+                id
+            }
+            _ => Dimension::Overworld.default_biome(),
+        };
+
+        Ok(Self::new(layers, biome))
+    }
+
+    /// Layers, from the bottom up.
+    #[inline]
+    pub fn layers(&self) -> &[FlatLayer] {
+        &self.layers
+    }
+
+    /// Biome that every chunk is filled with.
+    #[inline]
+    pub const fn biome(&self) -> u32 {
+        self.biome
+    }
+}
+
+impl Generator for FlatGenerator {
+    fn generate(&self, x: i32, z: i32, dimension: Dimension) -> VResult<Chunk> {
+        let mut chunk = Chunk::new(x, z, dimension);
+        chunk.fill_biome(self.biome);
+
+        let range = dimension.height_range();
+        let mut y = range.start;
+        for layer in &self.layers {
+            for _ in 0..layer.height {
+                // Layers above the build limit are ignored.
+                if y >= range.end {
+                    return Ok(chunk);
+                }
+
+                for bz in 0..16 {
+                    for bx in 0..16 {
+                        chunk.set(
+                            x * 16 + bx,
+                            y,
+                            z * 16 + bz,
+                            layer.block.clone(),
+                        )?;
+                    }
+                }
+
+                y += 1;
+            }
+        }
+
+        Ok(chunk)
+    }
+}
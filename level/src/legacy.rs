@@ -0,0 +1,321 @@
+use std::collections::HashMap;
+
+use bytes::{Buf, Bytes};
+use common::{bail, VResult};
+
+use crate::sub_chunk::{StorageRecord, CHUNK_SIZE};
+
+/// Names of the blocks used by the pre-palette chunk formats, indexed by their legacy block ID.
+///
+/// Data from the Bedrock Edition block IDs from before 1.13.
+const LEGACY_BLOCK_NAMES: [&str; 256] = [
+    "air",
+    "stone",
+    "grass",
+    "dirt",
+    "cobblestone",
+    "planks",
+    "sapling",
+    "bedrock",
+    "flowing_water",
+    "water",
+    "flowing_lava",
+    "lava",
+    "sand",
+    "gravel",
+    "gold_ore",
+    "iron_ore",
+    "coal_ore",
+    "log",
+    "leaves",
+    "sponge",
+    "glass",
+    "lapis_ore",
+    "lapis_block",
+    "dispenser",
+    "sandstone",
+    "noteblock",
+    "bed",
+    "golden_rail",
+    "detector_rail",
+    "sticky_piston",
+    "web",
+    "tallgrass",
+    "deadbush",
+    "piston",
+    "pistonArmCollision",
+    "wool",
+    "element_0",
+    "yellow_flower",
+    "red_flower",
+    "brown_mushroom",
+    "red_mushroom",
+    "gold_block",
+    "iron_block",
+    "double_stone_slab",
+    "stone_slab",
+    "brick_block",
+    "tnt",
+    "bookshelf",
+    "mossy_cobblestone",
+    "obsidian",
+    "torch",
+    "fire",
+    "mob_spawner",
+    "oak_stairs",
+    "chest",
+    "redstone_wire",
+    "diamond_ore",
+    "diamond_block",
+    "crafting_table",
+    "wheat",
+    "farmland",
+    "furnace",
+    "lit_furnace",
+    "standing_sign",
+    "wooden_door",
+    "ladder",
+    "rail",
+    "stone_stairs",
+    "wall_sign",
+    "lever",
+    "stone_pressure_plate",
+    "iron_door",
+    "wooden_pressure_plate",
+    "redstone_ore",
+    "lit_redstone_ore",
+    "unlit_redstone_torch",
+    "redstone_torch",
+    "stone_button",
+    "snow_layer",
+    "ice",
+    "snow",
+    "cactus",
+    "clay",
+    "reeds",
+    "jukebox",
+    "fence",
+    "pumpkin",
+    "netherrack",
+    "soul_sand",
+    "glowstone",
+    "portal",
+    "lit_pumpkin",
+    "cake",
+    "unpowered_repeater",
+    "powered_repeater",
+    "invisibleBedrock",
+    "trapdoor",
+    "monster_egg",
+    "stonebrick",
+    "brown_mushroom_block",
+    "red_mushroom_block",
+    "iron_bars",
+    "glass_pane",
+    "melon_block",
+    "pumpkin_stem",
+    "melon_stem",
+    "vine",
+    "fence_gate",
+    "brick_stairs",
+    "stone_brick_stairs",
+    "mycelium",
+    "waterlily",
+    "nether_brick",
+    "nether_brick_fence",
+    "nether_brick_stairs",
+    "nether_wart",
+    "enchanting_table",
+    "brewing_stand",
+    "cauldron",
+    "end_portal",
+    "end_portal_frame",
+    "end_stone",
+    "dragon_egg",
+    "redstone_lamp",
+    "lit_redstone_lamp",
+    "dropper",
+    "activator_rail",
+    "cocoa",
+    "sandstone_stairs",
+    "emerald_ore",
+    "ender_chest",
+    "tripwire_hook",
+    "tripWire",
+    "emerald_block",
+    "spruce_stairs",
+    "birch_stairs",
+    "jungle_stairs",
+    "command_block",
+    "beacon",
+    "cobblestone_wall",
+    "flower_pot",
+    "carrots",
+    "potatoes",
+    "wooden_button",
+    "skull",
+    "anvil",
+    "trapped_chest",
+    "light_weighted_pressure_plate",
+    "heavy_weighted_pressure_plate",
+    "unpowered_comparator",
+    "powered_comparator",
+    "daylight_detector",
+    "redstone_block",
+    "quartz_ore",
+    "hopper",
+    "quartz_block",
+    "quartz_stairs",
+    "double_wooden_slab",
+    "wooden_slab",
+    "stained_hardened_clay",
+    "stained_glass_pane",
+    "leaves2",
+    "log2",
+    "acacia_stairs",
+    "dark_oak_stairs",
+    "slime",
+    "glow_stick",
+    "iron_trapdoor",
+    "prismarine",
+    "seaLantern",
+    "hay_block",
+    "carpet",
+    "hardened_clay",
+    "coal_block",
+    "packed_ice",
+    "double_plant",
+    "standing_banner",
+    "wall_banner",
+    "daylight_detector_inverted",
+    "red_sandstone",
+    "red_sandstone_stairs",
+    "double_stone_slab2",
+    "stone_slab2",
+    "spruce_fence_gate",
+    "birch_fence_gate",
+    "jungle_fence_gate",
+    "dark_oak_fence_gate",
+    "acacia_fence_gate",
+    "repeating_command_block",
+    "chain_command_block",
+    "hard_glass_pane",
+    "hard_stained_glass_pane",
+    "chemical_heat",
+    "spruce_door",
+    "birch_door",
+    "jungle_door",
+    "acacia_door",
+    "dark_oak_door",
+    "grass_path",
+    "frame",
+    "chorus_flower",
+    "purpur_block",
+    "colored_torch_rg",
+    "purpur_stairs",
+    "colored_torch_bp",
+    "undyed_shulker_box",
+    "end_bricks",
+    "frosted_ice",
+    "end_rod",
+    "end_gateway",
+    "allow",
+    "deny",
+    "border_block",
+    "magma",
+    "nether_wart_block",
+    "red_nether_brick",
+    "bone_block",
+    "structure_void",
+    "shulker_box",
+    "purple_glazed_terracotta",
+    "white_glazed_terracotta",
+    "orange_glazed_terracotta",
+    "magenta_glazed_terracotta",
+    "light_blue_glazed_terracotta",
+    "yellow_glazed_terracotta",
+    "lime_glazed_terracotta",
+    "pink_glazed_terracotta",
+    "gray_glazed_terracotta",
+    "silver_glazed_terracotta",
+    "cyan_glazed_terracotta",
+    "chalkboard",
+    "blue_glazed_terracotta",
+    "brown_glazed_terracotta",
+    "green_glazed_terracotta",
+    "red_glazed_terracotta",
+    "black_glazed_terracotta",
+    "concrete",
+    "concrete_powder",
+    "chemistry_table",
+    "underwater_torch",
+    "chorus_plant",
+    "stained_glass",
+    "camera",
+    "podzol",
+    "beetroot",
+    "stonecutter",
+    "glowingobsidian",
+    "netherreactor",
+    "info_update",
+    "info_update2",
+    "movingBlock",
+    "observer",
+    "structure_block",
+    "hard_glass",
+    "hard_stained_glass",
+    "reserved6",
+];
+
+/// Creates a palette entry in the pre-1.13 format, consisting of a name and a data value.
+fn legacy_block(id: u8, meta: u8) -> nbt::Value {
+    nbt::Value::Compound(HashMap::from([
+        (
+            "name".to_owned(),
+            nbt::Value::String(format!(
+                "minecraft:{}",
+                LEGACY_BLOCK_NAMES[id as usize]
+            )),
+        ),
+        ("val".to_owned(), nbt::Value::Short(meta as i16)),
+    ]))
+}
+
+/// Decodes the block ID and metadata arrays used by sub chunk versions 0 and 2 to 7,
+/// and converts them to a palette-based storage record.
+///
+/// The arrays are followed by light data in some versions, which is ignored
+/// since the server does not use it.
+pub(crate) fn deserialize_pre_palette(
+    buffer: &mut Bytes,
+) -> VResult<StorageRecord> {
+    if buffer.remaining() < CHUNK_SIZE + CHUNK_SIZE / 2 {
+        bail!(
+            InvalidChunk,
+            "Pre-palette sub chunk is too short, expected at least {} bytes, got {}",
+            CHUNK_SIZE + CHUNK_SIZE / 2,
+            buffer.remaining()
+        );
+    }
+
+    let ids = buffer.split_to(CHUNK_SIZE);
+    // Metadata is stored as nibbles, two blocks per byte.
+    let meta = buffer.split_to(CHUNK_SIZE / 2);
+
+    // Maps a combined ID and metadata value to a palette index.
+    let mut lookup = HashMap::new();
+    let mut palette = Vec::new();
+    let mut indices = [0u16; CHUNK_SIZE];
+
+    for (i, index) in indices.iter_mut().enumerate() {
+        let id = ids[i];
+        let data = (meta[i >> 1] >> ((i & 1) * 4)) & 0xf;
+
+        *index = *lookup.entry((id, data)).or_insert_with(|| {
+            palette.push(legacy_block(id, data));
+            (palette.len() - 1) as u16
+        });
+    }
+
+    StorageRecord::new(indices, palette)
+}
@@ -0,0 +1,243 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use common::{bail, error, Deserialize, Serialize, VResult, Vector3i};
+
+/// Version of the `level.dat` format written by the server.
+pub const LEVEL_STORAGE_VERSION: i32 = 10;
+/// Spawn Y coordinate used by vanilla when the spawn has not been placed on the ground yet.
+pub const SPAWN_Y_UNDEFINED: i32 = 32767;
+
+/// Value of a game rule stored in the `level.dat` file.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum GameRuleValue {
+    Bool(bool),
+    Int(i32),
+}
+
+/// General information about a level, stored in its `level.dat` file.
+///
+/// The file consists of an 8-byte header, containing the storage version and the size of the data,
+/// followed by a little-endian NBT compound.
+/// Tags that are not known by the server are kept, so that they are not lost when the file is written back.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LevelData {
+    /// Version of the `level.dat` format.
+    pub storage_version: i32,
+    /// Name of the level.
+    pub level_name: String,
+    /// Seed used to generate the level.
+    pub seed: i64,
+    /// Default spawn position of the level.
+    pub spawn: Vector3i,
+    /// Time of day, in ticks.
+    pub time: i64,
+    /// Amount of ticks that the level has been running for.
+    pub current_tick: i64,
+    /// Default game mode of players.
+    pub game_type: i32,
+    /// Difficulty of the level.
+    pub difficulty: i32,
+    /// Type of world generator (0 = limited, 1 = infinite, 2 = flat).
+    pub generator: i32,
+    /// Unix timestamp of the last time the level was saved.
+    pub last_played: i64,
+    /// All other tags, including the game rules.
+    properties: HashMap<String, nbt::Value>,
+}
+
+impl LevelData {
+    /// Creates the data of a new level.
+    pub fn new(level_name: impl Into<String>, seed: i64) -> Self {
+        Self {
+            storage_version: LEVEL_STORAGE_VERSION,
+            level_name: level_name.into(),
+            seed,
+            spawn: Vector3i::from([0, 50, 0]),
+            time: 0,
+            current_tick: 0,
+            game_type: 1,
+            difficulty: 2,
+            generator: 1,
+            last_played: 0,
+            properties: HashMap::new(),
+        }
+    }
+
+    /// Reads the `level.dat` file at the given path.
+    ///
+    /// Returns `None` if the file does not exist.
+    pub fn read<P: AsRef<Path>>(path: P) -> VResult<Option<Self>> {
+        let data = match std::fs::read(path.as_ref()) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(None)
+            }
+            Err(e) => {
+                bail!(
+                    DatabaseFailure,
+                    "Failed to read {}: {e}",
+                    path.as_ref().display()
+                )
+            }
+        };
+
+        Self::deserialize(Bytes::from(data)).map(Some)
+    }
+
+    /// Writes the level data to the given path.
+    ///
+    /// The data is first written to a temporary file, which then replaces the old file.
+    /// This makes sure that the file is not corrupted if the server crashes while saving.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> VResult<()> {
+        let path = path.as_ref();
+
+        let mut buffer = BytesMut::new();
+        self.serialize(&mut buffer);
+
+        let temporary = path.with_extension("dat_new");
+        std::fs::write(&temporary, &buffer)
+            .and_then(|_| std::fs::rename(&temporary, path))
+            .map_err(|e| {
+                error!(
+                    DatabaseFailure,
+                    "Failed to write {}: {e}",
+                    path.display()
+                )
+            })
+    }
+
+    /// Returns the value of a game rule.
+    ///
+    /// Game rules are stored using their lowercase name.
+    pub fn game_rule(&self, name: &str) -> Option<GameRuleValue> {
+        match self.properties.get(name)? {
+            nbt::Value::Byte(b) => Some(GameRuleValue::Bool(*b != 0)),
+            nbt::Value::Int(i) => Some(GameRuleValue::Int(*i)),
+            _ => None,
+        }
+    }
+
+    /// Sets the value of a game rule.
+    pub fn set_game_rule(&mut self, name: &str, value: GameRuleValue) {
+        let value = match value {
+            GameRuleValue::Bool(b) => nbt::Value::Byte(b as i8),
+            GameRuleValue::Int(i) => nbt::Value::Int(i),
+        };
+
+        self.properties.insert(name.to_owned(), value);
+    }
+}
+
+/// Removes a tag from the compound, failing if it has the wrong type.
+macro_rules! take_tag {
+    ($map: expr, $name: literal, $variant: ident, $default: expr) => {
+        match $map.remove($name) {
+            Some(nbt::Value::$variant(value)) => value,
+            Some(value) => bail!(
+                Other,
+                "Expected {} tag to be of type {}, found {:?}",
+                $name,
+                stringify!($variant),
+                value
+            ),
+            None => $default,
+        }
+    };
+}
+
+impl Deserialize for LevelData {
+    fn deserialize(mut buffer: Bytes) -> VResult<Self> {
+        if buffer.remaining() < 8 {
+            bail!(Other, "level.dat is missing its header");
+        }
+
+        let storage_version = buffer.get_i32_le();
+        let length = buffer.get_i32_le();
+        if length < 0 || length as usize != buffer.remaining() {
+            bail!(
+                Other,
+                "level.dat header specifies {length} bytes, found {}",
+                buffer.remaining()
+            );
+        }
+
+        let tag = nbt::deserialize_le(&mut buffer)?;
+        let nbt::Value::Compound(mut map) = tag.value else {
+            bail!(Other, "Expected level.dat root tag to be a compound");
+        };
+
+        let defaults = Self::new("", 0);
+        let level_name =
+            take_tag!(map, "LevelName", String, defaults.level_name);
+        let seed = take_tag!(map, "RandomSeed", Long, defaults.seed);
+        let spawn = Vector3i::from([
+            take_tag!(map, "SpawnX", Int, defaults.spawn.x),
+            take_tag!(map, "SpawnY", Int, defaults.spawn.y),
+            take_tag!(map, "SpawnZ", Int, defaults.spawn.z),
+        ]);
+        let time = take_tag!(map, "Time", Long, defaults.time);
+        let current_tick =
+            take_tag!(map, "currentTick", Long, defaults.current_tick);
+        let game_type = take_tag!(map, "GameType", Int, defaults.game_type);
+        let difficulty = take_tag!(map, "Difficulty", Int, defaults.difficulty);
+        let generator = take_tag!(map, "Generator", Int, defaults.generator);
+        let last_played =
+            take_tag!(map, "LastPlayed", Long, defaults.last_played);
+
+        // The version in the header is used if the tag is missing.
+        let storage_version =
+            take_tag!(map, "StorageVersion", Int, storage_version);
+
+        Ok(Self {
+            storage_version,
+            level_name,
+            seed,
+            spawn,
+            time,
+            current_tick,
+            game_type,
+            difficulty,
+            generator,
+            last_played,
+            properties: map,
+        })
+    }
+}
+
+impl Serialize for LevelData {
+    fn serialize(&self, buffer: &mut BytesMut) {
+        let mut map = self.properties.clone();
+        map.extend([
+            (
+                "StorageVersion".to_owned(),
+                nbt::Value::Int(self.storage_version),
+            ),
+            (
+                "LevelName".to_owned(),
+                nbt::Value::String(self.level_name.clone()),
+            ),
+            ("RandomSeed".to_owned(), nbt::Value::Long(self.seed)),
+            ("SpawnX".to_owned(), nbt::Value::Int(self.spawn.x)),
+            ("SpawnY".to_owned(), nbt::Value::Int(self.spawn.y)),
+            ("SpawnZ".to_owned(), nbt::Value::Int(self.spawn.z)),
+            ("Time".to_owned(), nbt::Value::Long(self.time)),
+            (
+                "currentTick".to_owned(),
+                nbt::Value::Long(self.current_tick),
+            ),
+            ("GameType".to_owned(), nbt::Value::Int(self.game_type)),
+            ("Difficulty".to_owned(), nbt::Value::Int(self.difficulty)),
+            ("Generator".to_owned(), nbt::Value::Int(self.generator)),
+            ("LastPlayed".to_owned(), nbt::Value::Long(self.last_played)),
+        ]);
+
+        let mut data = BytesMut::new();
+        nbt::serialize_le("", &nbt::Value::Compound(map), &mut data);
+
+        buffer.put_i32_le(self.storage_version);
+        buffer.put_i32_le(data.len() as i32);
+        buffer.put(data);
+    }
+}
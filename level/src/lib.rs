@@ -1,38 +1,82 @@
 #[cfg(test)]
 mod test;
 
+mod block;
+mod block_entity;
+mod chunk;
 mod database;
+mod entity;
 mod ffi;
+mod generator;
+mod legacy;
+mod level_data;
+mod player;
 mod sub_chunk;
+mod terrain;
 mod world;
 
-use std::{sync::Arc, time::Duration};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
 
-use common::VResult;
-use database::ChunkDatabase;
+pub use block::*;
+pub use block_entity::*;
+pub use chunk::*;
+use common::{bail, VResult};
+use dashmap::mapref::entry::Entry;
+use dashmap::mapref::one::RefMut;
+use dashmap::{DashMap, DashSet};
+pub use database::*;
+pub use entity::*;
+pub use generator::*;
+pub use level_data::*;
+pub use player::*;
 pub use sub_chunk::*;
+pub use terrain::*;
 use tokio::sync::oneshot::{Receiver, Sender};
 use tokio_util::sync::CancellationToken;
 pub use world::*;
 
-/// Interface that is used to read and write world data.
+/// Interface that is used to read and write the chunks of a single dimension.
+///
+/// Every dimension has its own chunk manager, but they share the same database.
 #[derive(Debug)]
 pub struct ChunkManager {
     /// Chunk database
-    database: ChunkDatabase,
+    database: Arc<ChunkDatabase>,
+    /// Dimension managed by this chunk manager.
+    dimension: Dimension,
+    /// Chunks that have been loaded from the database or modified.
+    chunks: DashMap<(i32, i32), Chunk>,
+    /// Positions of chunks that have been modified since the last flush.
+    dirty: DashSet<(i32, i32)>,
+    /// Held while flushing or unloading chunks.
+    /// This prevents chunks from being unloaded before a failed flush has marked them as dirty again.
+    flush_lock: Mutex<()>,
+    /// Generates chunks that do not exist in the database.
+    /// Missing chunks are not generated if this is `None`.
+    generator: Option<Box<dyn Generator>>,
     token: CancellationToken,
 }
 
 impl ChunkManager {
-    pub fn new<P: AsRef<str>>(
-        path: P,
+    /// Creates a chunk manager for a dimension of the given database.
+    ///
+    /// The returned receiver is notified when the chunk manager has saved its chunks and closed.
+    pub fn new(
+        database: Arc<ChunkDatabase>,
+        dimension: Dimension,
         autosave_interval: Duration,
+        generator: Option<Box<dyn Generator>>,
         token: CancellationToken,
-    ) -> VResult<(Arc<Self>, Receiver<()>)> {
-        tracing::info!("Loading level {}...", path.as_ref());
-
+    ) -> (Arc<Self>, Receiver<()>) {
         let manager = Arc::new(Self {
-            database: ChunkDatabase::new(path)?,
+            database,
+            dimension,
+            chunks: DashMap::new(),
+            dirty: DashSet::new(),
+            flush_lock: Mutex::new(()),
+            generator,
             token,
         });
 
@@ -42,16 +86,294 @@ impl ChunkManager {
             clone.autosave_job(sender, autosave_interval).await
         });
 
-        Ok((manager, receiver))
+        (manager, receiver)
+    }
+
+    /// Returns the underlying database.
+    #[inline]
+    pub fn database(&self) -> &ChunkDatabase {
+        &self.database
+    }
+
+    /// Dimension managed by this chunk manager.
+    #[inline]
+    pub const fn dimension(&self) -> Dimension {
+        self.dimension
+    }
+
+    /// Makes sure the chunk at the given position is cached and locks it.
+    ///
+    /// Chunks that do not exist in the database are generated if a generator is set.
+    /// Returns `None` if the chunk does not exist.
+    ///
+    /// Modified chunks must be marked as dirty before the returned lock is released,
+    /// otherwise they could be unloaded before they are saved.
+    fn load_chunk(
+        &self,
+        x: i32,
+        z: i32,
+    ) -> VResult<Option<RefMut<'_, (i32, i32), Chunk>>> {
+        if let Some(chunk) = self.chunks.get_mut(&(x, z)) {
+            return Ok(Some(chunk));
+        }
+
+        if let Some(chunk) = Chunk::load(&self.database, x, z, self.dimension)? {
+            return Ok(Some(self.chunks.entry((x, z)).or_insert(chunk)));
+        }
+
+        let Some(generator) = &self.generator else {
+            return Ok(None);
+        };
+
+        let chunk = generator.generate(x, z, self.dimension)?;
+        let chunk = match self.chunks.entry((x, z)) {
+            Entry::Occupied(entry) => entry.into_ref(),
+            Entry::Vacant(entry) => {
+                let chunk = entry.insert(chunk);
+
+                // The chunk must be cached before it is marked as dirty,
+                // otherwise a concurrent flush could skip it.
+                self.dirty.insert((x, z));
+                chunk
+            }
+        };
+
+        Ok(Some(chunk))
+    }
+
+    /// Loads the chunk at the given position.
+    ///
+    /// The chunk is cached after it has been loaded from the database.
+    /// Generated chunks are written to the database on the next [`flush`](Self::flush).
+    /// Returns `None` if the chunk does not exist and no generator is set.
+    pub fn get_chunk(&self, x: i32, z: i32) -> VResult<Option<Chunk>> {
+        Ok(self.load_chunk(x, z)?.map(|c| c.clone()))
+    }
+
+    /// Replaces the chunk at its position.
+    ///
+    /// The chunk is marked as dirty and will be written to disk on the next [`flush`](Self::flush).
+    /// Fails if the chunk belongs to a different dimension.
+    pub fn set_chunk(&self, chunk: Chunk) -> VResult<()> {
+        if chunk.dimension() != self.dimension {
+            bail!(
+                InvalidChunk,
+                "Chunk in {:?} cannot be stored in {:?}",
+                chunk.dimension(),
+                self.dimension
+            );
+        }
+
+        let position = (chunk.x(), chunk.z());
+        // The chunk stays locked until it has been marked as dirty.
+        let _chunk = match self.chunks.entry(position) {
+            Entry::Occupied(mut entry) => {
+                entry.insert(chunk);
+                entry.into_ref()
+            }
+            Entry::Vacant(entry) => entry.insert(chunk),
+        };
+        self.dirty.insert(position);
+
+        Ok(())
+    }
+
+    /// Loads the sub chunk at the given position.
+    ///
+    /// Returns `None` if the sub chunk does not exist.
+    pub fn get_sub_chunk(
+        &self,
+        x: i32,
+        y: i8,
+        z: i32,
+    ) -> VResult<Option<SubChunk>> {
+        Ok(self
+            .load_chunk(x, z)?
+            .and_then(|c| c.sub_chunk(y).cloned()))
+    }
+
+    /// Replaces the sub chunk at the given position.
+    ///
+    /// The chunk is created if it does not exist yet.
+    /// The chunk is marked as dirty and will be written to disk on the next [`flush`](Self::flush).
+    pub fn set_sub_chunk(
+        &self,
+        x: i32,
+        y: i8,
+        z: i32,
+        sub_chunk: SubChunk,
+    ) -> VResult<()> {
+        let mut chunk = match self.load_chunk(x, z)? {
+            Some(chunk) => chunk,
+            None => self
+                .chunks
+                .entry((x, z))
+                .or_insert_with(|| Chunk::new(x, z, self.dimension)),
+        };
+        chunk.set_sub_chunk(y, sub_chunk)?;
+
+        self.dirty.insert((x, z));
+        Ok(())
+    }
+
+    /// Removes the sub chunk at the given position.
+    ///
+    /// The sub chunk is deleted from the database on the next [`flush`](Self::flush).
+    pub fn remove_sub_chunk(&self, x: i32, y: i8, z: i32) -> VResult<()> {
+        let Some(mut chunk) = self.load_chunk(x, z)? else {
+            return Ok(());
+        };
+
+        chunk.remove_sub_chunk(y);
+        self.dirty.insert((x, z));
+        Ok(())
+    }
+
+    /// Loads the block entity at the given world coordinates.
+    ///
+    /// Returns `None` if there is no block entity at that position.
+    pub fn get_block_entity(
+        &self,
+        x: i32,
+        y: i32,
+        z: i32,
+    ) -> VResult<Option<BlockEntity>> {
+        Ok(self
+            .load_chunk(x >> 4, z >> 4)?
+            .and_then(|c| c.block_entity(x, y, z).cloned()))
+    }
+
+    /// Adds a block entity, replacing the one at the same position.
+    ///
+    /// The chunk is created if it does not exist yet.
+    /// The chunk is marked as dirty and will be written to disk on the next [`flush`](Self::flush).
+    pub fn set_block_entity(&self, entity: BlockEntity) -> VResult<()> {
+        let (x, z) = (entity.position.x >> 4, entity.position.z >> 4);
+
+        let mut chunk = match self.load_chunk(x, z)? {
+            Some(chunk) => chunk,
+            None => self
+                .chunks
+                .entry((x, z))
+                .or_insert_with(|| Chunk::new(x, z, self.dimension)),
+        };
+        chunk.set_block_entity(entity)?;
+
+        self.dirty.insert((x, z));
+        Ok(())
+    }
+
+    /// Removes the block entity at the given world coordinates.
+    ///
+    /// The removal is written to disk on the next [`flush`](Self::flush).
+    pub fn remove_block_entity(
+        &self,
+        x: i32,
+        y: i32,
+        z: i32,
+    ) -> VResult<Option<BlockEntity>> {
+        let Some(mut chunk) = self.load_chunk(x >> 4, z >> 4)? else {
+            return Ok(None);
+        };
+
+        let removed = chunk.remove_block_entity(x, y, z);
+        if removed.is_some() {
+            self.dirty.insert((x >> 4, z >> 4));
+        }
+
+        Ok(removed)
     }
 
     /// Writes the current level state to the disk.
     /// Internally, this uses LevelDB's WriteBatch method to perform bulk updates.
     /// These LevelDB are done synchronously to prevent data loss and the overhead is minimal due to batching.
     pub fn flush(&self) -> VResult<()> {
+        let _lock =
+            self.flush_lock.lock().unwrap_or_else(PoisonError::into_inner);
+        if self.dirty.is_empty() {
+            return Ok(());
+        }
+
+        // Positions are removed from the dirty set before their data is serialised.
+        // If a chunk is modified while flushing, it will simply be flushed again next time.
+        let positions = self.dirty.iter().map(|k| *k).collect::<Vec<_>>();
+
+        let mut batch = WriteBatch::new();
+        let mut saved = HashMap::with_capacity(positions.len());
+        let mut stale = Vec::new();
+        for position in &positions {
+            self.dirty.remove(position);
+
+            if let Some(chunk) = self.chunks.get(position) {
+                chunk.save_records(&mut batch);
+                saved.insert(*position, chunk.entity_storage_ids());
+                stale.extend(chunk.stale_entities());
+            }
+        }
+
+        // An entity that moved to another chunk is written by that chunk,
+        // so its record must not be deleted by the chunk that it left.
+        let written = saved.values().flatten().copied().collect::<HashSet<_>>();
+        for storage_id in stale {
+            if !written.contains(&storage_id) {
+                batch.delete(Entity::actor_key(&storage_id));
+            }
+        }
+
+        if let Err(e) = self.database.write_batch(&batch) {
+            // Make sure the changes are not lost and are retried on the next flush.
+            for position in positions {
+                self.dirty.insert(position);
+            }
+
+            return Err(e);
+        }
+
+        // Saved chunks now own exactly the records they wrote,
+        // other chunks no longer own the records of entities that moved out of them.
+        for mut chunk in self.chunks.iter_mut() {
+            match saved.remove(chunk.key()) {
+                Some(storage_ids) => chunk.set_stored_entities(storage_ids),
+                None if !written.is_empty() => {
+                    chunk.forget_stored_entities(&written)
+                }
+                None => (),
+            }
+        }
+
+        tracing::debug!(
+            "Saved {} chunks in {:?}",
+            positions.len(),
+            self.dimension
+        );
         Ok(())
     }
 
+    /// Removes cached chunks that are not kept by the given predicate.
+    ///
+    /// Chunks that have been modified since the last flush are kept until they have been saved.
+    /// Returns how many chunks were unloaded.
+    pub fn unload_chunks<F>(&self, keep: F) -> usize
+    where
+        F: Fn(i32, i32) -> bool,
+    {
+        let _lock =
+            self.flush_lock.lock().unwrap_or_else(PoisonError::into_inner);
+
+        // Chunks are marked as dirty while they are locked,
+        // which means they cannot be modified in between the check and the removal.
+        let mut unloaded = 0;
+        self.chunks.retain(|&(x, z), _| {
+            let retained = keep(x, z) || self.dirty.contains(&(x, z));
+            if !retained {
+                unloaded += 1;
+            }
+            retained
+        });
+
+        unloaded
+    }
+
     /// Simple job that runs [`flush`](Self::flush) on a specified interval.
     async fn autosave_job(&self, sender: Sender<()>, interval: Duration) {
         let mut interval = tokio::time::interval(interval);
@@ -85,6 +407,6 @@ impl ChunkManager {
 
         // Send the signal that the level has been closed.
         let _ = sender.send(());
-        tracing::info!("Closed level");
+        tracing::debug!("Closed {:?}", self.dimension);
     }
 }
@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+
+use bytes::{Bytes, BytesMut};
+use common::{bail, Deserialize, Serialize, VResult, Vector3f};
+
+use crate::{ChunkDatabase, Dimension, WriteBatch};
+
+/// Persistent data of a player, stored in the world database.
+///
+/// Like vanilla, the data is stored under a `player_server_<uuid>` key, where the UUID is generated by the server.
+/// A `player_<xuid>` key points to this key, or `player_<uuid>` with the client UUID for players without an Xbox account.
+/// Tags that are not known by the server, such as the inventory, are kept when the record is written back.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlayerRecord {
+    /// Position of the player.
+    pub position: Vector3f,
+    /// Pitch of the player, in degrees.
+    pub pitch: f32,
+    /// Yaw of the player, in degrees.
+    pub yaw: f32,
+    /// Dimension the player is in.
+    pub dimension: Dimension,
+    /// Game mode of the player.
+    pub game_mode: i32,
+    /// Permission level of the player.
+    pub permission_level: i32,
+    /// Key that the record is stored under.
+    /// This is generated when a new record is saved for the first time.
+    server_id: Option<String>,
+    /// All other tags.
+    properties: HashMap<String, nbt::Value>,
+}
+
+impl Default for PlayerRecord {
+    fn default() -> Self {
+        Self {
+            position: Vector3f::from([0.0; 3]),
+            pitch: 0.0,
+            yaw: 0.0,
+            dimension: Dimension::Overworld,
+            game_mode: 0,
+            permission_level: 1,
+            server_id: None,
+            properties: HashMap::new(),
+        }
+    }
+}
+
+impl PlayerRecord {
+    /// Generates a new key for player data.
+    ///
+    /// Like vanilla, this uses a random UUID rather than one provided by the client.
+    pub fn generate_server_id() -> String {
+        let uuid = uuid::Builder::from_random_bytes(rand::random()).into_uuid();
+        format!("player_server_{uuid}")
+    }
+
+    /// Returns the key of the record that points to the data of a player.
+    ///
+    /// This is the XUID if the player has an Xbox account, otherwise the client UUID.
+    fn pointer_key(xuid: u64, uuid: &str) -> String {
+        if xuid != 0 {
+            format!("player_{xuid}")
+        } else {
+            format!("player_{uuid}")
+        }
+    }
+
+    /// Loads the data of a player.
+    ///
+    /// The key of the data is looked up using the XUID or UUID of the player.
+    /// Returns `None` if the player has not joined before.
+    pub fn load(
+        database: &ChunkDatabase,
+        xuid: u64,
+        uuid: &str,
+    ) -> VResult<Option<Self>> {
+        let Some(pointer) =
+            database.get_raw_key(Self::pointer_key(xuid, uuid))?
+        else {
+            return Ok(None);
+        };
+
+        let tag = nbt::deserialize_le(&mut pointer.clone())?;
+        let nbt::Value::Compound(mut map) = tag.value else {
+            bail!(Other, "Expected player pointer root tag to be a compound");
+        };
+        let Some(nbt::Value::String(server_id)) = map.remove("ServerId") else {
+            bail!(Other, "Player pointer does not contain a server ID");
+        };
+
+        let Some(data) = database.get_raw_key(&server_id)? else {
+            return Ok(None);
+        };
+
+        let mut record = Self::deserialize(data)?;
+        record.server_id = Some(server_id);
+        Ok(Some(record))
+    }
+
+    /// Writes the data of a player to the database.
+    ///
+    /// Records that were loaded are written back to the same key,
+    /// new records are stored under a newly [generated](Self::generate_server_id) key.
+    pub fn save(
+        &mut self,
+        database: &ChunkDatabase,
+        xuid: u64,
+        uuid: &str,
+    ) -> VResult<()> {
+        let server_id = self
+            .server_id
+            .get_or_insert_with(Self::generate_server_id)
+            .clone();
+        let mut batch = WriteBatch::new();
+
+        let mut pointer = HashMap::from([
+            (
+                "SelfSignedId".to_owned(),
+                nbt::Value::String(uuid.to_owned()),
+            ),
+            ("ServerId".to_owned(), nbt::Value::String(server_id.clone())),
+        ]);
+        if xuid != 0 {
+            pointer.insert(
+                "MsaId".to_owned(),
+                nbt::Value::String(xuid.to_string()),
+            );
+        }
+
+        let mut buffer = BytesMut::new();
+        nbt::serialize_le("", &nbt::Value::Compound(pointer), &mut buffer);
+        batch.put(Self::pointer_key(xuid, uuid), buffer);
+
+        let mut buffer = BytesMut::new();
+        self.serialize(&mut buffer);
+        batch.put(server_id, buffer);
+
+        database.write_batch(&batch)
+    }
+}
+
+/// Reads a list of floats from the compound.
+fn take_floats<const N: usize>(
+    map: &mut HashMap<String, nbt::Value>,
+    name: &str,
+) -> VResult<Option<[f32; N]>> {
+    let Some(value) = map.remove(name) else {
+        return Ok(None);
+    };
+
+    let nbt::Value::List(list) = value else {
+        bail!(Other, "Expected player {name} tag to be a list");
+    };
+
+    let mut floats = [0.0; N];
+    if list.len() != N {
+        bail!(
+            Other,
+            "Expected player {name} tag to contain {N} floats, found {}",
+            list.len()
+        );
+    }
+
+    for (float, value) in floats.iter_mut().zip(list) {
+        let nbt::Value::Float(value) = value else {
+            bail!(Other, "Expected player {name} tag to contain floats");
+        };
+        *float = value;
+    }
+
+    Ok(Some(floats))
+}
+
+impl Deserialize for PlayerRecord {
+    fn deserialize(mut buffer: Bytes) -> VResult<Self> {
+        let tag = nbt::deserialize_le(&mut buffer)?;
+        let nbt::Value::Compound(mut map) = tag.value else {
+            bail!(Other, "Expected player data root tag to be a compound");
+        };
+
+        let mut record = Self::default();
+        if let Some(position) = take_floats::<3>(&mut map, "Pos")? {
+            record.position = Vector3f::from(position);
+        }
+
+        // Vanilla stores the yaw before the pitch.
+        if let Some([yaw, pitch]) = take_floats::<2>(&mut map, "Rotation")? {
+            record.yaw = yaw;
+            record.pitch = pitch;
+        }
+
+        if let Some(nbt::Value::Int(dimension)) = map.remove("DimensionId") {
+            record.dimension = Dimension::try_from(dimension)?;
+        }
+
+        if let Some(nbt::Value::Int(game_mode)) = map.remove("PlayerGameType") {
+            record.game_mode = game_mode;
+        }
+
+        // The permission level is part of the abilities, which are kept as a whole.
+        if let Some(nbt::Value::Compound(abilities)) = map.get("abilities") {
+            if let Some(nbt::Value::Int(level)) =
+                abilities.get("playerPermissionsLevel")
+            {
+                record.permission_level = *level;
+            }
+        }
+
+        record.properties = map;
+        Ok(record)
+    }
+}
+
+impl Serialize for PlayerRecord {
+    fn serialize(&self, buffer: &mut BytesMut) {
+        let mut map = self.properties.clone();
+
+        let abilities = map
+            .entry("abilities".to_owned())
+            .or_insert_with(|| nbt::Value::Compound(HashMap::new()));
+        if let nbt::Value::Compound(abilities) = abilities {
+            abilities.insert(
+                "playerPermissionsLevel".to_owned(),
+                nbt::Value::Int(self.permission_level),
+            );
+        }
+
+        map.extend([
+            (
+                "Pos".to_owned(),
+                nbt::Value::List(vec![
+                    nbt::Value::Float(self.position.x),
+                    nbt::Value::Float(self.position.y),
+                    nbt::Value::Float(self.position.z),
+                ]),
+            ),
+            (
+                "Rotation".to_owned(),
+                nbt::Value::List(vec![
+                    nbt::Value::Float(self.yaw),
+                    nbt::Value::Float(self.pitch),
+                ]),
+            ),
+            (
+                "DimensionId".to_owned(),
+                nbt::Value::Int(self.dimension as i32),
+            ),
+            ("PlayerGameType".to_owned(), nbt::Value::Int(self.game_mode)),
+        ]);
+
+        nbt::serialize_le("", &nbt::Value::Compound(map), buffer);
+    }
+}
@@ -1,133 +1,423 @@
 use bytes::{Buf, BufMut, Bytes, BytesMut};
-use common::{bail, BlockPosition, Deserialize, Serialize, VResult, Vector3b};
+use common::{
+    bail, error, Deserialize, ReadExtensions, Serialize, VResult, Vector3b,
+    WriteExtensions,
+};
 
-const CHUNK_SIZE: usize = 4096;
+use crate::block::BlockRegistry;
+use crate::chunk::is_air;
+use crate::legacy;
+
+pub(crate) const CHUNK_SIZE: usize = 4096;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum SubChunkVersion {
+    /// Single storage record without a layer count.
     Legacy = 1,
     Limited = 8,
     Limitless = 9,
 }
 
-#[inline]
-fn u32_ceil_div(lhs: u32, rhs: u32) -> u32 {
-    (lhs + rhs - 1) / rhs
+/// Bit sizes that can be used to encode palette indices.
+/// A size of 0 is also valid and means that the palette contains a single entry.
+const VALID_INDEX_SIZES: [u8; 8] = [1, 2, 3, 4, 5, 6, 8, 16];
+
+/// Header value indicating that the storage is a copy of the previous one.
+/// This is only used by biome storages.
+const COPY_PREVIOUS_HEADER: u8 = 0x7f;
+
+/// Format a [`StorageRecord`] is encoded in.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PaletteEncoding {
+    /// Format used by the world database.
+    /// The palette size and entries are stored as little endian integers or NBT.
+    Disk,
+    /// Format used by packets.
+    /// The palette size and entries are stored as variable size integers or network NBT.
+    Network,
+}
+
+/// An entry that can be stored in the palette of a [`StorageRecord`].
+pub trait PaletteEntry: Sized {
+    /// Whether this entry is a runtime ID.
+    /// Network storages containing runtime IDs have the runtime flag set in their header.
+    const RUNTIME: bool;
+
+    /// Reads a single palette entry.
+    fn deserialize_entry(
+        buffer: &mut Bytes,
+        encoding: PaletteEncoding,
+    ) -> VResult<Self>;
+
+    /// Writes a single palette entry.
+    fn serialize_entry(&self, buffer: &mut BytesMut, encoding: PaletteEncoding);
+}
+
+impl PaletteEntry for nbt::Value {
+    const RUNTIME: bool = false;
+
+    fn deserialize_entry(
+        buffer: &mut Bytes,
+        encoding: PaletteEncoding,
+    ) -> VResult<Self> {
+        Ok(match encoding {
+            PaletteEncoding::Disk => nbt::deserialize_le(buffer)?.value,
+            PaletteEncoding::Network => nbt::deserialize_net(buffer)?.value,
+        })
+    }
+
+    fn serialize_entry(
+        &self,
+        buffer: &mut BytesMut,
+        encoding: PaletteEncoding,
+    ) {
+        match encoding {
+            PaletteEncoding::Disk => nbt::serialize_le("", self, buffer),
+            PaletteEncoding::Network => nbt::serialize_net("", self, buffer),
+        }
+    }
+}
+
+impl PaletteEntry for u32 {
+    const RUNTIME: bool = true;
+
+    fn deserialize_entry(
+        buffer: &mut Bytes,
+        encoding: PaletteEncoding,
+    ) -> VResult<Self> {
+        Ok(match encoding {
+            PaletteEncoding::Disk => {
+                if buffer.remaining() < 4 {
+                    bail!(InvalidChunk, "Palette entry is missing");
+                }
+                buffer.get_u32_le()
+            }
+            PaletteEncoding::Network => buffer.get_var_i32()? as u32,
+        })
+    }
+
+    fn serialize_entry(
+        &self,
+        buffer: &mut BytesMut,
+        encoding: PaletteEncoding,
+    ) {
+        match encoding {
+            PaletteEncoding::Disk => buffer.put_u32_le(*self),
+            PaletteEncoding::Network => buffer.put_var_i32(*self as i32),
+        }
+    }
+}
+
+/// Returns the smallest index size that can address every entry in a palette of the given size.
+fn index_size_for(palette_size: usize) -> u8 {
+    if palette_size <= 1 {
+        return 0;
+    }
+
+    VALID_INDEX_SIZES
+        .into_iter()
+        .find(|size| 1usize << size >= palette_size)
+        .unwrap_or(16)
+}
+
+/// Reads a packed array of 4096 indices.
+///
+/// Indices do not cross word boundaries,
+/// the unused high bits of every word are padding.
+fn unpack_indices(
+    buffer: &mut Bytes,
+    index_size: u8,
+) -> VResult<[u16; CHUNK_SIZE]> {
+    let mut indices = [0u16; CHUNK_SIZE];
+    if index_size == 0 {
+        // Every index points to the single palette entry.
+        return Ok(indices);
+    }
+
+    // Amount of indices that fit in a single 32-bit integer.
+    let indices_per_word = u32::BITS as usize / index_size as usize;
+    // Amount of words needed to encode 4096 block indices.
+    let word_count = CHUNK_SIZE.div_ceil(indices_per_word);
+    if buffer.remaining() < word_count * 4 {
+        bail!(
+            InvalidChunk,
+            "Storage record is too short, expected {} words of indices",
+            word_count
+        );
+    }
+
+    let mask = !(!0u32 << index_size);
+    for chunk in indices.chunks_mut(indices_per_word) {
+        let mut word = buffer.get_u32_le();
+        for index in chunk {
+            *index = (word & mask) as u16;
+            word >>= index_size;
+        }
+    }
+
+    Ok(indices)
+}
+
+/// Writes a packed array of 4096 indices.
+fn pack_indices(
+    indices: &[u16; CHUNK_SIZE],
+    index_size: u8,
+    buffer: &mut BytesMut,
+) {
+    if index_size == 0 {
+        return;
+    }
+
+    let indices_per_word = u32::BITS as usize / index_size as usize;
+    let mask = !(!0u32 << index_size);
+    for chunk in indices.chunks(indices_per_word) {
+        let mut word = 0;
+        for (i, index) in chunk.iter().enumerate() {
+            word |= (*index as u32 & mask) << (i * index_size as usize);
+        }
+
+        buffer.put_u32_le(word);
+    }
 }
 
-#[derive(Debug, Clone)]
-pub struct StorageRecord {
+/// A layer of 4096 palette indices, together with the palette.
+///
+/// The palette of block storages consists of NBT block states,
+/// while network storages and biomes use numeric IDs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StorageRecord<T = nbt::Value> {
     indices: [u16; CHUNK_SIZE],
-    palette: Vec<nbt::Value>,
+    palette: Vec<T>,
+    /// Amount of indices referring to each palette entry.
+    usage: Vec<u16>,
 }
 
-impl StorageRecord {
-    fn deserialize(buffer: &mut Bytes) -> VResult<Self> {
-        // Size of each index in bits.
-        let index_size = buffer.get_u8() >> 1;
-        if index_size == 0x7f {
-            bail!(InvalidChunk, "Invalid block bit size {index_size}");
+impl<T: PaletteEntry> StorageRecord<T> {
+    /// Creates a storage record from its indices and palette.
+    ///
+    /// Fails if the palette is empty or an index is out of its range,
+    /// such records cannot be encoded.
+    pub(crate) fn new(
+        indices: [u16; CHUNK_SIZE],
+        palette: Vec<T>,
+    ) -> VResult<Self> {
+        if palette.is_empty() {
+            bail!(InvalidChunk, "Storage record palette is empty");
+        }
+
+        let mut usage = vec![0; palette.len()];
+        for index in indices {
+            let Some(count) = usage.get_mut(index as usize) else {
+                bail!(
+                    InvalidChunk,
+                    "Storage record index is out of palette range"
+                );
+            };
+            *count += 1;
+        }
+
+        Ok(Self { indices, palette, usage })
+    }
+
+    /// Creates a storage record with the same indices, converting every palette entry.
+    pub(crate) fn map_palette<U>(
+        &self,
+        f: impl FnMut(&T) -> U,
+    ) -> StorageRecord<U> {
+        StorageRecord {
+            indices: self.indices,
+            palette: self.palette.iter().map(f).collect(),
+            usage: self.usage.clone(),
+        }
+    }
+
+    /// Creates a storage record where every position contains the given value.
+    pub fn filled(value: T) -> Self {
+        Self {
+            indices: [0; CHUNK_SIZE],
+            palette: vec![value],
+            usage: vec![CHUNK_SIZE as u16],
         }
+    }
 
-        // Amount of indices that fit in a single 32-bit integer.
-        let indices_per_word = u32::BITS as usize / index_size as usize;
-        // Amount of words needed to encode 4096 block indices.
-        let word_count = CHUNK_SIZE / indices_per_word;
+    /// Palette indices of every block, in XZY order.
+    #[inline]
+    pub fn indices(&self) -> &[u16; CHUNK_SIZE] {
+        &self.indices
+    }
 
-        let mask = !(!0u32 << index_size);
-        let mut indices = [0u16; CHUNK_SIZE];
-        for i in 0..word_count {
-            // println!("{i} {}", i * indices_per_word);
-            let mut word = buffer.get_u32_le();
+    /// The palette referenced by the indices.
+    #[inline]
+    pub fn palette(&self) -> &[T] {
+        &self.palette
+    }
 
-            for j in 0..indices_per_word {
-                let index = word & mask;
-                indices[i * indices_per_word + j] = index as u16;
+    /// Returns the value at the given position.
+    pub fn get(&self, position: Vector3b) -> Option<&T> {
+        let index = self.indices[pos_to_offset(position)];
+        self.palette.get(index as usize)
+    }
 
-                word >>= index_size;
+    /// Removes palette entries that are no longer referenced by any index.
+    ///
+    /// Entries are removed automatically when they become unused through [`set`](Self::set),
+    /// but records loaded from disk can still contain unused entries.
+    pub fn compact(&mut self) {
+        let mut i = 0;
+        while i < self.palette.len() {
+            if self.usage[i] == 0 {
+                self.remove_entry(i);
+            } else {
+                i += 1;
             }
         }
+    }
 
-        // Padded sizes have an extra word.
-        match index_size {
-            3 | 5 | 6 => {
-                let mut word = buffer.get_u32_le();
-                let last_index =
-                    (word_count - 1) * indices_per_word + indices_per_word - 1;
+    /// Removes the palette entry at the given index,
+    /// moving the last entry into its place.
+    fn remove_entry(&mut self, index: usize) {
+        let last = self.palette.len() - 1;
 
-                let indices_left = 4096 - last_index;
-                for i in 0..indices_left {
-                    indices[last_index + i] = (word & mask) as u16;
-                    word >>= index_size;
+        self.palette.swap_remove(index);
+        self.usage.swap_remove(index);
+
+        if index != last {
+            for i in &mut self.indices {
+                if *i as usize == last {
+                    *i = index as u16;
                 }
             }
-            _ => (),
         }
+    }
+}
 
-        // Size of the block palette.
-        let palette_size = buffer.get_u32_le();
+impl<T: PaletteEntry + PartialEq> StorageRecord<T> {
+    /// Sets the value at the given position.
+    ///
+    /// The value is added to the palette if it does not exist yet,
+    /// which widens the index size once the palette outgrows it.
+    /// The previous value is removed from the palette if it is no longer used.
+    pub fn set(&mut self, position: Vector3b, value: T) {
+        let offset = pos_to_offset(position);
+        let old = self.indices[offset] as usize;
+        if self.palette.get(old) == Some(&value) {
+            return;
+        }
 
-        let mut palette = Vec::with_capacity(palette_size as usize);
-        for _ in 0..palette_size {
-            let properties = nbt::deserialize_le(buffer)?.value;
-            palette.push(properties);
+        let new = match self.palette.iter().position(|v| *v == value) {
+            Some(new) => new,
+            None => {
+                self.palette.push(value);
+                self.usage.push(0);
+                self.palette.len() - 1
+            }
+        };
+
+        self.indices[offset] = new as u16;
+        self.usage[new] += 1;
+
+        if let Some(count) = self.usage.get_mut(old) {
+            *count -= 1;
+            if *count == 0 {
+                self.remove_entry(old);
+            }
         }
+    }
 
-        Ok(Self { indices, palette })
+    /// Replaces every value in this record.
+    pub fn fill(&mut self, value: T) {
+        *self = Self::filled(value);
     }
+}
 
-    fn serialize(&self, buffer: &mut BytesMut) {
-        // Determine the required bits per index
-        let index_size = {
-            let palette_size = self.palette.len();
-
-            let mut bits_per_block = 0;
-            // Loop over allowed values.
-            for b in [1, 2, 3, 4, 5, 6, 8, 16] {
-                if 2usize.pow(b) >= palette_size {
-                    bits_per_block = b;
-                    break;
+impl<T: PaletteEntry> StorageRecord<T> {
+    pub fn deserialize(
+        buffer: &mut Bytes,
+        encoding: PaletteEncoding,
+    ) -> VResult<Self> {
+        if !buffer.has_remaining() {
+            bail!(InvalidChunk, "Storage record is missing");
+        }
+
+        // The lowest bit is the runtime flag, which is implied by the palette type.
+        let header = buffer.get_u8();
+        let index_size = header >> 1;
+        if index_size == COPY_PREVIOUS_HEADER {
+            bail!(
+                InvalidChunk,
+                "Storage record refers to previous storage, which is only valid for biomes"
+            );
+        }
+
+        if !VALID_INDEX_SIZES.contains(&index_size) && index_size != 0 {
+            bail!(InvalidChunk, "Invalid block bit size {index_size}");
+        }
+
+        let indices = unpack_indices(buffer, index_size)?;
+
+        // A storage with an index size of 0 always has a single palette entry,
+        // the size is therefore omitted.
+        let palette_size = if index_size == 0 {
+            1
+        } else {
+            match encoding {
+                PaletteEncoding::Disk => {
+                    if buffer.remaining() < 4 {
+                        bail!(InvalidChunk, "Palette size is missing");
+                    }
+                    buffer.get_u32_le() as usize
                 }
+                PaletteEncoding::Network => buffer.get_var_i32()? as usize,
             }
-
-            bits_per_block as u8
         };
 
-        buffer.put_u8(index_size << 1);
+        if palette_size > CHUNK_SIZE {
+            bail!(
+                InvalidChunk,
+                "Palette size {palette_size} exceeds chunk size"
+            );
+        }
 
-        // Amount of indices that fit in a single 32-bit integer.
-        let indices_per_word =
-            u32_ceil_div(u32::BITS, index_size as u32) as usize;
-        // Amount of words needed to encode 4096 block indices.
-        let word_count = {
-            let padding = match index_size {
-                3 | 5 | 6 => 1,
-                _ => 0,
-            };
-            CHUNK_SIZE / indices_per_word + padding
-        };
+        let mut palette = Vec::with_capacity(palette_size);
+        for _ in 0..palette_size {
+            palette.push(T::deserialize_entry(buffer, encoding)?);
+        }
 
-        let mask = !(!0u32 << index_size);
-        for i in 0..word_count {
-            let mut word = 0;
-            for j in 0..indices_per_word {
-                let index =
-                    self.indices[i * indices_per_word + j] as u32 & mask;
-                word |= index;
-                word <<= indices_per_word;
-            }
+        Self::new(indices, palette)
+    }
+
+    pub fn serialize(&self, buffer: &mut BytesMut, encoding: PaletteEncoding) {
+        let index_size = index_size_for(self.palette.len());
+        let runtime_flag =
+            (encoding == PaletteEncoding::Network && T::RUNTIME) as u8;
 
-            buffer.put_u32_le(word);
+        buffer.put_u8(index_size << 1 | runtime_flag);
+        pack_indices(&self.indices, index_size, buffer);
+
+        if index_size != 0 {
+            match encoding {
+                PaletteEncoding::Disk => {
+                    buffer.put_u32_le(self.palette.len() as u32)
+                }
+                PaletteEncoding::Network => {
+                    buffer.put_var_i32(self.palette.len() as i32)
+                }
+            }
         }
 
-        buffer.put_u32_le(self.palette.len() as u32);
         for entry in &self.palette {
-            nbt::serialize_le("", entry, buffer);
+            entry.serialize_entry(buffer, encoding);
         }
     }
 }
 
+/// Converts a position within a sub chunk to an index, the indices are in XZY order.
 fn pos_to_offset(position: Vector3b) -> usize {
+    debug_assert!(
+        position.x < 16 && position.y < 16 && position.z < 16,
+        "Position is outside of the sub chunk"
+    );
+
     16 * 16 * position.x as usize
         + 16 * position.z as usize
         + position.y as usize
@@ -142,7 +432,7 @@ fn offset_to_pos(offset: usize) -> Vector3b {
 }
 
 /// Represents the blocks in a sub chunk.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct SubChunk {
     /// Version of the chunk.
     /// This version affects the format of the chunk.
@@ -156,19 +446,152 @@ pub struct SubChunk {
 }
 
 impl SubChunk {
+    /// Index of the layer containing blocks.
+    pub const BLOCK_LAYER: usize = 0;
+    /// Index of the layer containing waterlogged blocks.
+    pub const WATERLOG_LAYER: usize = 1;
+
+    /// Creates a sub chunk at the given index, filled with a single block.
+    pub fn new(index: i8, block: nbt::Value) -> Self {
+        Self {
+            version: SubChunkVersion::Limitless,
+            index: index as u8,
+            storage_records: vec![StorageRecord::filled(block)],
+        }
+    }
+
+    /// Vertical index of this sub chunk.
+    #[inline]
+    pub const fn index(&self) -> i8 {
+        self.index as i8
+    }
+
+    /// Sets the vertical index of this sub chunk.
+    /// This is only stored by the limitless format.
+    #[inline]
+    pub(crate) fn set_index(&mut self, index: i8) {
+        self.index = index as u8;
+    }
+
+    /// Returns the block at the given position in the block layer.
     pub fn get(&self, position: Vector3b) -> Option<&nbt::Value> {
-        let offset = pos_to_offset(position);
-        let index = self.storage_records[0].indices[offset];
+        self.layer(Self::BLOCK_LAYER)?.get(position)
+    }
+
+    /// Sets the block at the given position in the block layer.
+    pub fn set(
+        &mut self,
+        position: Vector3b,
+        block: nbt::Value,
+    ) -> VResult<()> {
+        self.layer_mut(Self::BLOCK_LAYER)
+            .ok_or_else(|| {
+                error!(InvalidChunk, "Sub chunk has no block layer")
+            })?
+            .set(position, block);
+
+        Ok(())
+    }
 
-        self.storage_records[0].palette.get(index as usize)
+    /// Replaces every block in the block layer.
+    pub fn fill(&mut self, block: nbt::Value) {
+        match self.layer_mut(Self::BLOCK_LAYER) {
+            Some(layer) => layer.fill(block),
+            None => self.storage_records.push(StorageRecord::filled(block)),
+        }
+    }
+
+    /// Returns the storage record of the given layer.
+    #[inline]
+    pub fn layer(&self, layer: usize) -> Option<&StorageRecord> {
+        self.storage_records.get(layer)
+    }
+
+    /// Returns a mutable reference to the storage record of the given layer.
+    #[inline]
+    pub fn layer_mut(&mut self, layer: usize) -> Option<&mut StorageRecord> {
+        self.storage_records.get_mut(layer)
+    }
+
+    /// Amount of layers in this sub chunk.
+    #[inline]
+    pub fn layer_count(&self) -> usize {
+        self.storage_records.len()
+    }
+
+    /// Whether every layer of this sub chunk only contains air.
+    pub fn is_empty(&self) -> bool {
+        self.storage_records
+            .iter()
+            .all(|layer| layer.palette().iter().all(is_air))
+    }
+
+    /// Adds a new layer filled with the given block and returns it.
+    pub fn add_layer(&mut self, block: nbt::Value) -> &mut StorageRecord {
+        self.storage_records.push(StorageRecord::filled(block));
+        self.storage_records.last_mut().unwrap()
+    }
+
+    /// Encodes this sub chunk in the format used by packets.
+    ///
+    /// The network format is always the limitless format.
+    /// Block states are converted to runtime IDs using the given registry.
+    pub fn serialize_network(
+        &self,
+        registry: &BlockRegistry,
+        buffer: &mut BytesMut,
+    ) {
+        buffer.put_u8(SubChunkVersion::Limitless as u8);
+        buffer.put_u8(self.storage_records.len() as u8);
+        buffer.put_u8(self.index);
+
+        for storage_record in &self.storage_records {
+            registry
+                .to_runtime(storage_record)
+                .serialize(buffer, PaletteEncoding::Network);
+        }
+    }
+
+    /// Removes unused entries from the palettes of all layers.
+    pub fn compact(&mut self) {
+        for layer in &mut self.storage_records {
+            layer.compact();
+        }
     }
 }
 
 impl Deserialize for SubChunk {
     fn deserialize(mut buffer: Bytes) -> VResult<Self> {
+        if !buffer.has_remaining() {
+            bail!(InvalidChunk, "Sub chunk is empty");
+        }
+
         let version = buffer.get_u8();
         match version {
-            1 => todo!(),
+            // Pre-palette formats are converted to palettes,
+            // the chunk will be written back in the limited format.
+            0 | 2..=7 => {
+                let storage_record =
+                    legacy::deserialize_pre_palette(&mut buffer)?;
+
+                Ok(Self {
+                    version: SubChunkVersion::Limited,
+                    index: 0,
+                    storage_records: vec![storage_record],
+                })
+            }
+            1 => {
+                let storage_record = StorageRecord::deserialize(
+                    &mut buffer,
+                    PaletteEncoding::Disk,
+                )?;
+
+                Ok(Self {
+                    version: SubChunkVersion::Legacy,
+                    index: 0,
+                    storage_records: vec![storage_record],
+                })
+            }
             8 | 9 => {
                 let storage_count = buffer.get_u8();
                 let index = if version == 9 { buffer.get_u8() } else { 0 };
@@ -176,10 +599,11 @@ impl Deserialize for SubChunk {
                 let mut storage_records =
                     Vec::with_capacity(storage_count as usize);
 
-                println!("Decoding {storage_count} records");
                 for _ in 0..storage_count {
-                    storage_records
-                        .push(StorageRecord::deserialize(&mut buffer)?);
+                    storage_records.push(StorageRecord::deserialize(
+                        &mut buffer,
+                        PaletteEncoding::Disk,
+                    )?);
                 }
 
                 let version = if version == 8 {
@@ -199,7 +623,12 @@ impl Serialize for SubChunk {
     fn serialize(&self, buffer: &mut BytesMut) {
         buffer.put_u8(self.version as u8);
         match self.version {
-            SubChunkVersion::Legacy => todo!(),
+            SubChunkVersion::Legacy => {
+                // The legacy format only supports a single layer.
+                if let Some(storage_record) = self.storage_records.first() {
+                    storage_record.serialize(buffer, PaletteEncoding::Disk);
+                }
+            }
             _ => {
                 buffer.put_u8(self.storage_records.len() as u8);
 
@@ -208,7 +637,7 @@ impl Serialize for SubChunk {
                 }
 
                 for storage_record in &self.storage_records {
-                    storage_record.serialize(buffer);
+                    storage_record.serialize(buffer, PaletteEncoding::Disk);
                 }
             }
         }
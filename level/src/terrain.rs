@@ -0,0 +1,569 @@
+use std::ops::Range;
+
+use common::{bail, VResult};
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin};
+
+use crate::sub_chunk::CHUNK_SIZE;
+use crate::{
+    BlockRegistry, Chunk, Dimension, Generator, StorageRecord, SubChunk,
+};
+
+/// Water level of the overworld.
+const SEA_LEVEL: i32 = 62;
+/// Blocks below this level are deepslate instead of stone.
+const DEEPSLATE_LEVEL: i32 = 0;
+/// Lowest level that caves can be carved at, to keep the bedrock floor intact.
+const CAVE_FLOOR: i32 = -58;
+
+/// Blocks used by the terrain generator.
+///
+/// The discriminant is the index of the block in [`TERRAIN_BLOCKS`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[repr(u8)]
+enum TerrainBlock {
+    Air,
+    Bedrock,
+    Stone,
+    Deepslate,
+    Dirt,
+    Grass,
+    Sand,
+    Sandstone,
+    Gravel,
+    Water,
+    SnowLayer,
+    CoalOre,
+    DeepslateCoalOre,
+    CopperOre,
+    DeepslateCopperOre,
+    IronOre,
+    DeepslateIronOre,
+    GoldOre,
+    DeepslateGoldOre,
+    RedstoneOre,
+    LapisOre,
+    DeepslateLapisOre,
+    DiamondOre,
+    DeepslateDiamondOre,
+}
+
+/// Names of the blocks used by the terrain generator, in the order of [`TerrainBlock`].
+pub(crate) const TERRAIN_BLOCKS: [&str; 24] = [
+    "minecraft:air",
+    "minecraft:bedrock",
+    "minecraft:stone",
+    "minecraft:deepslate",
+    "minecraft:dirt",
+    "minecraft:grass",
+    "minecraft:sand",
+    "minecraft:sandstone",
+    "minecraft:gravel",
+    "minecraft:water",
+    "minecraft:snow_layer",
+    "minecraft:coal_ore",
+    "minecraft:deepslate_coal_ore",
+    "minecraft:copper_ore",
+    "minecraft:deepslate_copper_ore",
+    "minecraft:iron_ore",
+    "minecraft:deepslate_iron_ore",
+    "minecraft:gold_ore",
+    "minecraft:deepslate_gold_ore",
+    "minecraft:redstone_ore",
+    "minecraft:lapis_ore",
+    "minecraft:deepslate_lapis_ore",
+    "minecraft:diamond_ore",
+    "minecraft:deepslate_diamond_ore",
+];
+
+/// Describes how an ore is distributed.
+struct OreConfig {
+    /// Ore that replaces stone.
+    ore: TerrainBlock,
+    /// Ore that replaces deepslate.
+    deepslate_ore: TerrainBlock,
+    /// Levels that veins can start at.
+    range: Range<i32>,
+    /// Amount of veins per chunk.
+    veins: u32,
+    /// Maximum amount of blocks in a single vein.
+    size: u32,
+}
+
+/// Ores generated in the overworld.
+const ORES: [OreConfig; 7] = [
+    OreConfig {
+        ore: TerrainBlock::CoalOre,
+        deepslate_ore: TerrainBlock::DeepslateCoalOre,
+        range: 0..192,
+        veins: 20,
+        size: 12,
+    },
+    OreConfig {
+        ore: TerrainBlock::CopperOre,
+        deepslate_ore: TerrainBlock::DeepslateCopperOre,
+        range: -16..112,
+        veins: 8,
+        size: 8,
+    },
+    OreConfig {
+        ore: TerrainBlock::IronOre,
+        deepslate_ore: TerrainBlock::DeepslateIronOre,
+        range: -64..72,
+        veins: 10,
+        size: 8,
+    },
+    OreConfig {
+        ore: TerrainBlock::GoldOre,
+        deepslate_ore: TerrainBlock::DeepslateGoldOre,
+        range: -64..32,
+        veins: 4,
+        size: 8,
+    },
+    OreConfig {
+        ore: TerrainBlock::RedstoneOre,
+        deepslate_ore: TerrainBlock::RedstoneOre,
+        range: -64..16,
+        veins: 6,
+        size: 6,
+    },
+    OreConfig {
+        ore: TerrainBlock::LapisOre,
+        deepslate_ore: TerrainBlock::DeepslateLapisOre,
+        range: -64..64,
+        veins: 2,
+        size: 6,
+    },
+    OreConfig {
+        ore: TerrainBlock::DiamondOre,
+        deepslate_ore: TerrainBlock::DeepslateDiamondOre,
+        range: -64..16,
+        veins: 1,
+        size: 6,
+    },
+];
+
+/// Overworld biomes placed by the terrain generator.
+mod biome {
+    pub const OCEAN: u32 = 0;
+    pub const PLAINS: u32 = 1;
+    pub const DESERT: u32 = 2;
+    pub const EXTREME_HILLS: u32 = 3;
+    pub const FOREST: u32 = 4;
+    pub const TAIGA: u32 = 5;
+    pub const SWAMPLAND: u32 = 6;
+    pub const ICE_PLAINS: u32 = 12;
+    pub const ICE_MOUNTAINS: u32 = 13;
+    pub const BEACH: u32 = 16;
+    pub const JUNGLE: u32 = 21;
+    pub const DEEP_OCEAN: u32 = 24;
+    pub const COLD_BEACH: u32 = 26;
+    pub const SAVANNA: u32 = 35;
+    pub const WARM_OCEAN: u32 = 40;
+    pub const LUKEWARM_OCEAN: u32 = 42;
+    pub const DEEP_LUKEWARM_OCEAN: u32 = 43;
+    pub const COLD_OCEAN: u32 = 44;
+    pub const DEEP_COLD_OCEAN: u32 = 45;
+    pub const FROZEN_OCEAN: u32 = 46;
+    pub const DEEP_FROZEN_OCEAN: u32 = 47;
+}
+
+/// Deterministic random number generator used for ore and bedrock placement.
+///
+/// This is SplitMix64, which is fast and has no state besides a single integer.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a random number in the given range.
+    fn range(&mut self, range: Range<i32>) -> i32 {
+        let length = (range.end - range.start) as u64;
+        range.start + (self.next_u64() % length) as i32
+    }
+
+    /// Returns `true` with a probability of `numerator / denominator`.
+    fn chance(&mut self, numerator: u64, denominator: u64) -> bool {
+        self.next_u64() % denominator < numerator
+    }
+}
+
+/// Generates overworld terrain using noise.
+///
+/// The output only depends on the seed, so a chunk is always generated the same way
+/// for the same seed. Terrain is only generated in the overworld,
+/// chunks in other dimensions are empty.
+#[derive(Debug)]
+pub struct TerrainGenerator {
+    /// World seed.
+    seed: u64,
+    /// Large scale height variation, separating oceans from land.
+    continentalness: Fbm<Perlin>,
+    /// Small scale height variation.
+    detail: Fbm<Perlin>,
+    /// Mountain ridges.
+    ridges: Fbm<Perlin>,
+    /// Biome temperature.
+    temperature: Perlin,
+    /// Biome humidity.
+    humidity: Perlin,
+    /// The intersection of the zero surfaces of both cave noises forms cave tunnels.
+    caves: [Perlin; 2],
+    /// Block states, in the order of [`TerrainBlock`].
+    blocks: Vec<nbt::Value>,
+}
+
+impl TerrainGenerator {
+    /// Creates a generator for the given world seed.
+    ///
+    /// Fails if one of the blocks used by the generator does not exist in the registry.
+    pub fn new(seed: u64, registry: &BlockRegistry) -> VResult<Self> {
+        let mut blocks = Vec::with_capacity(TERRAIN_BLOCKS.len());
+        for name in TERRAIN_BLOCKS {
+            let Some(block) = registry.default_state(name) else {
+                bail!(Other, "Terrain generator requires unknown block {name}");
+            };
+            blocks.push(block.clone());
+        }
+
+        let mut seeds = SplitMix64(seed);
+        let mut next_seed = || seeds.next_u64() as u32;
+
+        Ok(Self {
+            seed,
+            continentalness: Fbm::new(next_seed())
+                .set_octaves(5)
+                .set_frequency(1.0 / 768.0),
+            detail: Fbm::new(next_seed())
+                .set_octaves(4)
+                .set_frequency(1.0 / 128.0),
+            ridges: Fbm::new(next_seed())
+                .set_octaves(3)
+                .set_frequency(1.0 / 384.0),
+            temperature: Perlin::new(next_seed()),
+            humidity: Perlin::new(next_seed()),
+            caves: [Perlin::new(next_seed()), Perlin::new(next_seed())],
+            blocks,
+        })
+    }
+
+    /// World seed of this generator.
+    #[inline]
+    pub const fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Height of the highest solid block in the column at the given world coordinates.
+    pub fn terrain_height(&self, x: i32, z: i32) -> i32 {
+        let point = [x as f64, z as f64];
+
+        let continentalness = self.continentalness.get(point);
+        let detail = self.detail.get(point);
+        let ridge = 1.0 - self.ridges.get(point).abs();
+        let mountains = (continentalness - 0.2).max(0.0) * ridge * ridge;
+
+        let height =
+            64.0 + continentalness * 32.0 + detail * 6.0 + mountains * 160.0;
+
+        (height as i32).clamp(-48, 300)
+    }
+
+    /// Biome of the column at the given world coordinates.
+    pub fn biome(&self, x: i32, z: i32) -> u32 {
+        self.column_biome(x, z, self.terrain_height(x, z))
+    }
+
+    /// Selects the biome of a column based on its height, temperature and humidity.
+    fn column_biome(&self, x: i32, z: i32, height: i32) -> u32 {
+        let point = [x as f64 / 1024.0, z as f64 / 1024.0];
+        let temperature = self.temperature.get(point);
+        let humidity = self.humidity.get(point);
+
+        if height < SEA_LEVEL - 20 {
+            match temperature {
+                t if t < -0.35 => biome::DEEP_FROZEN_OCEAN,
+                t if t < -0.1 => biome::DEEP_COLD_OCEAN,
+                t if t > 0.25 => biome::DEEP_LUKEWARM_OCEAN,
+                _ => biome::DEEP_OCEAN,
+            }
+        } else if height < SEA_LEVEL {
+            match temperature {
+                t if t < -0.35 => biome::FROZEN_OCEAN,
+                t if t < -0.1 => biome::COLD_OCEAN,
+                t if t > 0.45 => biome::WARM_OCEAN,
+                t if t > 0.25 => biome::LUKEWARM_OCEAN,
+                _ => biome::OCEAN,
+            }
+        } else if height <= SEA_LEVEL + 2 {
+            match temperature {
+                t if t < -0.35 => biome::COLD_BEACH,
+                t if t > 0.35 && humidity < 0.0 => biome::DESERT,
+                _ => biome::BEACH,
+            }
+        } else if height > 110 {
+            if temperature < -0.1 {
+                biome::ICE_MOUNTAINS
+            } else {
+                biome::EXTREME_HILLS
+            }
+        } else if temperature < -0.35 {
+            biome::ICE_PLAINS
+        } else if temperature < -0.1 {
+            biome::TAIGA
+        } else if temperature > 0.35 {
+            match humidity {
+                h if h < 0.0 => biome::DESERT,
+                h if h < 0.2 => biome::SAVANNA,
+                _ => biome::JUNGLE,
+            }
+        } else if humidity > 0.5 && height < SEA_LEVEL + 5 {
+            biome::SWAMPLAND
+        } else if humidity > 0.25 {
+            biome::FOREST
+        } else {
+            biome::PLAINS
+        }
+    }
+
+    /// Fills a single column with terrain, from the bottom of the world up to the sea level
+    /// or the surface, whichever is higher.
+    ///
+    /// The column contains one block for every level, starting at the bottom of the world.
+    fn fill_column(
+        &self,
+        column: &mut [TerrainBlock],
+        height: i32,
+        biome: u32,
+        random: &mut SplitMix64,
+    ) {
+        let min = Dimension::Overworld.height_range().start;
+        let top = column.len() as i32 + min;
+        let mut set = |y: i32, block: TerrainBlock| {
+            if (min..top).contains(&y) {
+                column[(y - min) as usize] = block;
+            }
+        };
+
+        for y in min..=height {
+            set(
+                y,
+                if y < DEEPSLATE_LEVEL {
+                    TerrainBlock::Deepslate
+                } else {
+                    TerrainBlock::Stone
+                },
+            );
+        }
+
+        // The bedrock floor becomes less dense towards the top.
+        set(min, TerrainBlock::Bedrock);
+        for i in 1..5 {
+            if random.chance(5 - i, 5) {
+                set(min + i as i32, TerrainBlock::Bedrock);
+            }
+        }
+
+        let (surface, filler, depth) = match biome {
+            biome::DESERT | biome::BEACH | biome::COLD_BEACH => {
+                (TerrainBlock::Sand, TerrainBlock::Sandstone, 3)
+            }
+            biome::DEEP_OCEAN
+            | biome::DEEP_COLD_OCEAN
+            | biome::DEEP_FROZEN_OCEAN
+            | biome::DEEP_LUKEWARM_OCEAN => {
+                (TerrainBlock::Gravel, TerrainBlock::Gravel, 2)
+            }
+            biome::OCEAN
+            | biome::COLD_OCEAN
+            | biome::FROZEN_OCEAN
+            | biome::LUKEWARM_OCEAN
+            | biome::WARM_OCEAN => (TerrainBlock::Sand, TerrainBlock::Sand, 2),
+            // Mountain peaks are bare stone.
+            _ if height > 140 => (TerrainBlock::Stone, TerrainBlock::Stone, 0),
+            _ => (TerrainBlock::Grass, TerrainBlock::Dirt, 3),
+        };
+
+        set(height, surface);
+        for y in height - depth..height {
+            set(y, filler);
+        }
+
+        for y in height + 1..=SEA_LEVEL {
+            set(y, TerrainBlock::Water);
+        }
+
+        let snowy = matches!(biome, biome::ICE_PLAINS | biome::ICE_MOUNTAINS)
+            || height > 180;
+        if snowy && height >= SEA_LEVEL {
+            set(height + 1, TerrainBlock::SnowLayer);
+        }
+    }
+
+    /// Whether the block at the given world coordinates is part of a cave.
+    fn is_cave(&self, x: i32, y: i32, z: i32) -> bool {
+        let point = [x as f64 / 48.0, y as f64 / 32.0, z as f64 / 48.0];
+        let a = self.caves[0].get(point);
+        let b = self.caves[1].get(point);
+
+        // Caves become wider deeper underground.
+        let threshold = if y < DEEPSLATE_LEVEL { 0.006 } else { 0.003 };
+        a * a + b * b < threshold
+    }
+
+    /// Places ore veins in the chunk.
+    ///
+    /// Veins are contained within the chunk and only replace stone and deepslate.
+    fn place_ores(
+        &self,
+        columns: &mut [TerrainBlock],
+        height: usize,
+        random: &mut SplitMix64,
+    ) {
+        let min = Dimension::Overworld.height_range().start;
+        for config in &ORES {
+            for _ in 0..config.veins {
+                let mut x = random.range(0..16);
+                let mut y = random.range(config.range.clone());
+                let mut z = random.range(0..16);
+
+                for _ in 0..config.size {
+                    if (0..16).contains(&x)
+                        && (0..16).contains(&z)
+                        && (0..height as i32).contains(&(y - min))
+                    {
+                        let index =
+                            (x * 16 + z) as usize * height + (y - min) as usize;
+                        match columns[index] {
+                            TerrainBlock::Stone => columns[index] = config.ore,
+                            TerrainBlock::Deepslate => {
+                                columns[index] = config.deepslate_ore
+                            }
+                            _ => (),
+                        }
+                    }
+
+                    match random.range(0..3) {
+                        0 => x += random.range(-1..2),
+                        1 => y += random.range(-1..2),
+                        _ => z += random.range(-1..2),
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Generator for TerrainGenerator {
+    fn generate(&self, x: i32, z: i32, dimension: Dimension) -> VResult<Chunk> {
+        let mut chunk = Chunk::new(x, z, dimension);
+        if dimension != Dimension::Overworld {
+            return Ok(chunk);
+        }
+
+        let range = dimension.height_range();
+        let height = (range.end - range.start) as usize;
+
+        let mut random = SplitMix64(
+            self.seed
+                ^ (x as u64).wrapping_mul(0x9e3779b97f4a7c15)
+                ^ (z as u64).wrapping_mul(0xc2b2ae3d27d4eb4f),
+        );
+
+        // Blocks of every column, in XZY order.
+        let mut columns = vec![TerrainBlock::Air; 16 * 16 * height];
+        let mut biomes = [0u32; 16 * 16];
+
+        for bx in 0..16 {
+            for bz in 0..16 {
+                let (wx, wz) = (x * 16 + bx, z * 16 + bz);
+                let surface = self.terrain_height(wx, wz);
+                let biome = self.column_biome(wx, wz, surface);
+                biomes[(bx * 16 + bz) as usize] = biome;
+
+                let start = (bx * 16 + bz) as usize * height;
+                let column = &mut columns[start..start + height];
+                self.fill_column(column, surface, biome, &mut random);
+
+                // Caves are not carved directly below water, since the water would be floating.
+                let cave_top = if surface < SEA_LEVEL {
+                    surface - 8
+                } else {
+                    surface
+                };
+                for y in CAVE_FLOOR..=cave_top {
+                    if self.is_cave(wx, y, wz) {
+                        column[(y - range.start) as usize] = TerrainBlock::Air;
+                    }
+                }
+            }
+        }
+
+        self.place_ores(&mut columns, height, &mut random);
+
+        // Every sub chunk has the same biomes, because biomes only vary horizontally.
+        let mut biome_palette = Vec::new();
+        let mut biome_indices = [0u16; CHUNK_SIZE];
+        for (offset, index) in biome_indices.iter_mut().enumerate() {
+            let biome = biomes[offset >> 4];
+            *index = match biome_palette.iter().position(|b| *b == biome) {
+                Some(i) => i as u16,
+                None => {
+                    biome_palette.push(biome);
+                    (biome_palette.len() - 1) as u16
+                }
+            };
+        }
+        let biome_record = StorageRecord::new(biome_indices, biome_palette)?;
+        for record in chunk.biomes_mut() {
+            *record = biome_record.clone();
+        }
+
+        let min = dimension.min_sub_chunk();
+        for slot in 0..dimension.sub_chunk_count() {
+            let mut palette: Vec<TerrainBlock> = Vec::new();
+            let mut indices = [0u16; CHUNK_SIZE];
+
+            // Sub chunk indices are in XZY order, which matches the column layout.
+            for (offset, index) in indices.iter_mut().enumerate() {
+                let column = offset >> 4;
+                let block =
+                    columns[column * height + slot * 16 + (offset & 0xf)];
+
+                *index = match palette.iter().position(|b| *b == block) {
+                    Some(i) => i as u16,
+                    None => {
+                        palette.push(block);
+                        (palette.len() - 1) as u16
+                    }
+                };
+            }
+
+            if palette == [TerrainBlock::Air] {
+                continue;
+            }
+
+            let palette = palette
+                .into_iter()
+                .map(|block| self.blocks[block as usize].clone())
+                .collect();
+
+            let mut sub_chunk = SubChunk::new(
+                min + slot as i8,
+                self.blocks[TerrainBlock::Air as usize].clone(),
+            );
+            if let Some(layer) = sub_chunk.layer_mut(SubChunk::BLOCK_LAYER) {
+                *layer = StorageRecord::new(indices, palette)?;
+            }
+
+            chunk.set_sub_chunk(min + slot as i8, sub_chunk)?;
+        }
+
+        Ok(chunk)
+    }
+}
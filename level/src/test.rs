@@ -1,28 +1,865 @@
-use bytes::BytesMut;
-use common::{Deserialize, Serialize, Vector3b};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
 
+use bytes::{BufMut, Bytes, BytesMut};
+use common::{Deserialize, Serialize, Vector3b, Vector3f, Vector3i};
+use tokio_util::sync::CancellationToken;
+use xxhash_rust::xxh64::Xxh64;
+
+use crate::terrain::TERRAIN_BLOCKS;
 use crate::{
-    database::ChunkDatabase, DatabaseKey, DatabaseTag, Dimension, SubChunk,
+    BlockEntity, BlockRegistry, Chunk, ChunkDatabase, ChunkManager,
+    DatabaseKey, DatabaseTag, Dimension, Entity, FlatGenerator, GameRuleValue,
+    Generator, LevelData, LevelKey, PaletteEncoding, PaletteEntry,
+    PlayerRecord, RuntimeIdMode, StorageRecord, SubChunk, TerrainGenerator,
+    WriteBatch, CURRENT_CHUNK_VERSION, LEVEL_STORAGE_VERSION,
 };
 
 #[test]
 fn database_open() {
     let db = ChunkDatabase::new("test/db").unwrap();
 
+    // The test world does not contain a chunk at (0, 2), which older versions of these tests used.
+    // Sub chunk 3 of the chunk at (-3, 5) does exist.
     let mut buffer = BytesMut::new();
     let key = DatabaseKey {
-        x: 0,
+        x: -3,
         y: 3,
-        z: 2,
+        z: 5,
         dimension: Dimension::Overworld,
         tag: DatabaseTag::SubChunk,
     }
     .serialize(&mut buffer);
 
-    let data = db.get_raw_key(buffer.freeze()).unwrap();
+    let data = db.get_raw_key(buffer.freeze()).unwrap().unwrap();
     let sub_chunk = SubChunk::deserialize(data).unwrap();
 
     // let block = sub_chunk.get(Vector3b::from([]))
 
     println!("{:?}", sub_chunk.get(Vector3b::from([13, 5, 6])));
 }
+
+#[test]
+fn database_iterate() {
+    let db = ChunkDatabase::new("test/db").unwrap();
+
+    let chunks = db.chunks().unwrap();
+    assert!(!chunks.is_empty());
+    // See `database_open` for why this chunk is used.
+    assert!(chunks
+        .iter()
+        .any(|k| k.x == -3 && k.z == 5 && k.dimension == Dimension::Overworld));
+
+    let has_sub_chunk = db
+        .iter_chunk(-3, 5, Dimension::Overworld)
+        .any(|(k, _)| k.tag == DatabaseTag::SubChunk && k.y == 3);
+    assert!(has_sub_chunk);
+}
+
+#[test]
+fn database_key_parse() {
+    let key = DatabaseKey {
+        x: -5,
+        z: 12,
+        y: -4,
+        dimension: Dimension::Nether,
+        tag: DatabaseTag::SubChunk,
+    };
+
+    let mut buffer = BytesMut::new();
+    key.serialize(&mut buffer);
+    assert_eq!(buffer.len(), 14);
+    assert_eq!(DatabaseKey::deserialize(buffer.freeze()).unwrap(), key);
+
+    let mut buffer = BytesMut::new();
+    buffer.put_i32_le(1);
+    buffer.put_i32_le(2);
+    buffer.put_u8(0x99);
+    let parsed = LevelKey::deserialize(buffer.freeze()).unwrap();
+    assert_eq!(
+        parsed,
+        LevelKey::Chunk(DatabaseKey {
+            x: 1,
+            z: 2,
+            y: 0,
+            dimension: Dimension::Overworld,
+            tag: DatabaseTag::Unknown(0x99)
+        })
+    );
+
+    // Same length as an overworld chunk key.
+    assert_eq!(
+        LevelKey::deserialize(Bytes::from_static(b"mobevents")).unwrap(),
+        LevelKey::MobEvents
+    );
+    assert_eq!(
+        LevelKey::deserialize(Bytes::from_static(b"player_server_abc"))
+            .unwrap(),
+        LevelKey::Player("server_abc".to_owned())
+    );
+    assert_eq!(
+        LevelKey::deserialize(Bytes::from_static(b"VILLAGE_1234_INFO"))
+            .unwrap(),
+        LevelKey::Village("1234_INFO".to_owned())
+    );
+    assert!(matches!(
+        LevelKey::deserialize(Bytes::from_static(b"??")).unwrap(),
+        LevelKey::Unknown(_)
+    ));
+}
+
+#[test]
+fn sub_chunk_pre_palette() {
+    let mut ids = [0u8; 4096];
+    let mut meta = [0u8; 2048];
+
+    // Position (1, 3, 2), stored in XZY order.
+    let offset = (1 << 8) | (2 << 4) | 3;
+    ids[offset] = 1;
+    meta[offset >> 1] = 2 << 4;
+
+    let mut buffer = BytesMut::new();
+    buffer.put_u8(2);
+    buffer.put(ids.as_ref());
+    buffer.put(meta.as_ref());
+
+    let sub_chunk = SubChunk::deserialize(buffer.freeze()).unwrap();
+
+    let nbt::Value::Compound(block) =
+        sub_chunk.get(Vector3b::from([1, 3, 2])).unwrap()
+    else {
+        panic!("Expected compound");
+    };
+    assert_eq!(
+        block.get("name"),
+        Some(&nbt::Value::String("minecraft:stone".to_owned()))
+    );
+    assert_eq!(block.get("val"), Some(&nbt::Value::Short(2)));
+
+    let nbt::Value::Compound(block) =
+        sub_chunk.get(Vector3b::from([0, 0, 0])).unwrap()
+    else {
+        panic!("Expected compound");
+    };
+    assert_eq!(
+        block.get("name"),
+        Some(&nbt::Value::String("minecraft:air".to_owned()))
+    );
+}
+
+/// Creates a storage record with random indices that uses every entry of the palette.
+fn random_record<T: PaletteEntry>(
+    palette: Vec<T>,
+    seed: u32,
+) -> StorageRecord<T> {
+    let mut state = seed | 1;
+    let mut indices = [0u16; 4096];
+    for (i, index) in indices.iter_mut().enumerate() {
+        // Xorshift, deterministic so that failures can be reproduced.
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+
+        *index = if i < palette.len() {
+            i as u16
+        } else {
+            (state % palette.len() as u32) as u16
+        };
+    }
+
+    StorageRecord::new(indices, palette).unwrap()
+}
+
+fn assert_round_trip<T: PaletteEntry + PartialEq + std::fmt::Debug>(
+    record: &StorageRecord<T>,
+    encoding: PaletteEncoding,
+) {
+    let mut buffer = BytesMut::new();
+    record.serialize(&mut buffer, encoding);
+
+    let mut buffer = buffer.freeze();
+    let decoded =
+        StorageRecord::<T>::deserialize(&mut buffer, encoding).unwrap();
+    assert!(buffer.is_empty());
+    assert_eq!(&decoded, record);
+}
+
+#[test]
+fn storage_record_round_trip() {
+    // Palette sizes that require every valid index size: 0, 1, 2, 3, 4, 5, 6, 8 and 16 bits.
+    for (seed, size) in
+        [1, 2, 4, 8, 16, 32, 64, 256, 4096].into_iter().enumerate()
+    {
+        for encoding in [PaletteEncoding::Disk, PaletteEncoding::Network] {
+            let palette = (0..size as u32).map(|i| i * 7).collect();
+            assert_round_trip(
+                &random_record::<u32>(palette, seed as u32),
+                encoding,
+            );
+
+            let palette = (0..size)
+                .map(|i| {
+                    nbt::Value::Compound(
+                        [(
+                            "name".to_owned(),
+                            nbt::Value::String(format!("block_{i}")),
+                        )]
+                        .into_iter()
+                        .collect(),
+                    )
+                })
+                .collect();
+            assert_round_trip(
+                &random_record::<nbt::Value>(palette, seed as u32),
+                encoding,
+            );
+        }
+    }
+}
+
+#[test]
+fn storage_record_empty_palette() {
+    // A record without palette entries would be written with an index size of 0,
+    // which is read back as a record with a single entry.
+    assert!(StorageRecord::<u32>::new([0; 4096], Vec::new()).is_err());
+    assert!(StorageRecord::new([1; 4096], vec![5u32]).is_err());
+
+    let mut buffer = BytesMut::new();
+    buffer.put_u8(1 << 1);
+    buffer.put_bytes(0, 4096 / 8);
+    buffer.put_u32_le(0);
+    assert!(StorageRecord::<u32>::deserialize(
+        &mut buffer.freeze(),
+        PaletteEncoding::Disk
+    )
+    .is_err());
+}
+
+#[test]
+fn sub_chunk_round_trip() {
+    let db = ChunkDatabase::new("test/db").unwrap();
+
+    let mut count = 0;
+    for key in db.chunks().unwrap() {
+        for (key, data) in db.iter_chunk(key.x, key.z, key.dimension) {
+            if key.tag != DatabaseTag::SubChunk {
+                continue;
+            }
+
+            let sub_chunk = SubChunk::deserialize(data).unwrap();
+
+            let mut buffer = BytesMut::new();
+            sub_chunk.serialize(&mut buffer);
+            assert_eq!(
+                SubChunk::deserialize(buffer.freeze()).unwrap(),
+                sub_chunk
+            );
+
+            count += 1;
+        }
+    }
+    assert_ne!(count, 0);
+}
+
+fn block(name: &str) -> nbt::Value {
+    nbt::Value::Compound(
+        [("name".to_owned(), nbt::Value::String(name.to_owned()))]
+            .into_iter()
+            .collect(),
+    )
+}
+
+#[test]
+fn sub_chunk_edit() {
+    let mut sub_chunk = SubChunk::new(0, block("minecraft:air"));
+    let position = Vector3b::from([1, 2, 3]);
+
+    sub_chunk
+        .set(position.clone(), block("minecraft:stone"))
+        .unwrap();
+    assert_eq!(
+        sub_chunk.get(position.clone()),
+        Some(&block("minecraft:stone"))
+    );
+    assert_eq!(sub_chunk.layer(0).unwrap().palette().len(), 2);
+
+    // Air should be removed from the palette once it is no longer used.
+    sub_chunk.fill(block("minecraft:dirt"));
+    sub_chunk
+        .set(position.clone(), block("minecraft:stone"))
+        .unwrap();
+    sub_chunk
+        .set(position.clone(), block("minecraft:dirt"))
+        .unwrap();
+    assert_eq!(
+        sub_chunk.layer(0).unwrap().palette(),
+        &[block("minecraft:dirt")]
+    );
+
+    // Grow the palette past 256 entries, requiring 16-bit indices.
+    for i in 0..300u16 {
+        let position = Vector3b::from([
+            (i >> 8) as u8,
+            (i & 0xf) as u8,
+            ((i >> 4) & 0xf) as u8,
+        ]);
+        sub_chunk
+            .set(position, block(&format!("minecraft:block_{i}")))
+            .unwrap();
+    }
+    assert_eq!(sub_chunk.layer(0).unwrap().palette().len(), 301);
+
+    let water = sub_chunk.add_layer(block("minecraft:air"));
+    water.set(position.clone(), block("minecraft:water"));
+    assert_eq!(sub_chunk.layer_count(), 2);
+
+    let mut buffer = BytesMut::new();
+    sub_chunk.serialize(&mut buffer);
+    assert_eq!(SubChunk::deserialize(buffer.freeze()).unwrap(), sub_chunk);
+}
+
+#[test]
+fn chunk_load() {
+    let db = ChunkDatabase::new("test/db").unwrap();
+
+    let mut chunk = Chunk::load(&db, -3, 5, Dimension::Overworld)
+        .unwrap()
+        .unwrap();
+    assert!(chunk.sub_chunks().count() > 0);
+    assert_eq!(chunk.biomes().len(), 24);
+    assert!(Chunk::load(&db, 1000, 1000, Dimension::Overworld)
+        .unwrap()
+        .is_none());
+
+    // World coordinates of the chunk.
+    let (x, z) = (-3 * 16 + 4, 5 * 16 + 7);
+    assert!(chunk.get(x, -64, z).is_some());
+    assert!(chunk.get(x, 320, z).is_none());
+    assert!(chunk.get(0, 0, 0).is_none());
+
+    chunk.set(x, 319, z, block("minecraft:stone")).unwrap();
+    assert_eq!(chunk.get(x, 319, z), Some(&block("minecraft:stone")));
+    assert_eq!(chunk.heightmap()[(7 << 4) | 4], 384);
+    assert!(chunk.set(x, 320, z, block("minecraft:stone")).is_err());
+
+    chunk.set_biome(x, -64, z, 5).unwrap();
+    assert_eq!(chunk.get_biome(x, -64, z), Some(5));
+}
+
+#[test]
+fn chunk_legacy_upgrade() {
+    let path = std::env::temp_dir()
+        .join(format!("nova-chunk-upgrade-{}", std::process::id()));
+    let db = ChunkDatabase::new(path.to_str().unwrap()).unwrap();
+
+    let raw_key = |tag| {
+        let mut buffer = BytesMut::new();
+        DatabaseKey {
+            x: 3,
+            z: 3,
+            y: 0,
+            dimension: Dimension::Overworld,
+            tag,
+        }
+        .serialize(&mut buffer);
+        buffer
+    };
+
+    // Chunks from before 1.18 store their version and 2D biomes in legacy records.
+    let mut data_2d = BytesMut::new();
+    data_2d.put_bytes(0, 512);
+    data_2d.put_bytes(4, 256);
+    db.put_raw_key(raw_key(DatabaseTag::LegacyChunkVersion), [22])
+        .unwrap();
+    db.put_raw_key(raw_key(DatabaseTag::Data2d), data_2d)
+        .unwrap();
+
+    let chunk = Chunk::load(&db, 3, 3, Dimension::Overworld)
+        .unwrap()
+        .unwrap();
+    assert_eq!(chunk.version(), 22);
+
+    let mut batch = WriteBatch::new();
+    chunk.save(&mut batch);
+    db.write_batch(&batch).unwrap();
+
+    let version = db.get_raw_key(raw_key(DatabaseTag::ChunkVersion)).unwrap();
+    assert_eq!(version.as_deref(), Some([CURRENT_CHUNK_VERSION].as_ref()));
+    for tag in [DatabaseTag::LegacyChunkVersion, DatabaseTag::Data2d] {
+        assert!(db.get_raw_key(raw_key(tag)).unwrap().is_none());
+    }
+
+    let chunk = Chunk::load(&db, 3, 3, Dimension::Overworld)
+        .unwrap()
+        .unwrap();
+    assert_eq!(chunk.version(), CURRENT_CHUNK_VERSION);
+    assert_eq!(chunk.get_biome(48, 0, 48), Some(4));
+
+    drop(db);
+    let _ = std::fs::remove_dir_all(path);
+}
+
+#[test]
+fn chunk_unload() {
+    // The autosave job is spawned, but never runs because the runtime is not driven.
+    // This makes sure nothing is written to the test world.
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .unwrap();
+    let _guard = runtime.enter();
+
+    let db = Arc::new(ChunkDatabase::new("test/db").unwrap());
+    let (manager, _) = ChunkManager::new(
+        db,
+        Dimension::Overworld,
+        Duration::from_secs(60),
+        None,
+        CancellationToken::new(),
+    );
+
+    assert!(manager.get_chunk(-3, 5).unwrap().is_some());
+    assert_eq!(manager.unload_chunks(|x, z| (x, z) == (-3, 5)), 0);
+    assert_eq!(manager.unload_chunks(|_, _| false), 1);
+
+    // Modified chunks are not unloaded before they have been saved.
+    manager
+        .set_chunk(Chunk::new(1000, 1000, Dimension::Overworld))
+        .unwrap();
+    assert_eq!(manager.unload_chunks(|_, _| false), 0);
+    assert!(manager.get_chunk(1000, 1000).unwrap().is_some());
+
+    // Unloaded chunks are loaded from the database again.
+    assert!(manager.get_chunk(-3, 5).unwrap().is_some());
+}
+
+#[test]
+fn chunk_network_format() {
+    let mut chunk = Chunk::new(0, 0, Dimension::Overworld);
+    assert_eq!(chunk.network_sub_chunk_count(), 0);
+
+    chunk.set(1, 2, 3, block("minecraft:stone")).unwrap();
+    assert_eq!(chunk.network_sub_chunk_count(), 5);
+
+    let mut buffer = BytesMut::new();
+    chunk.serialize_network(&registry(RuntimeIdMode::Sequential), &mut buffer);
+
+    // The lowest sub chunk does not exist and is sent without any layers.
+    assert_eq!(&buffer[..3], &[9, 0, -4i8 as u8]);
+}
+
+/// Creates a registry containing air and stone.
+fn registry(mode: RuntimeIdMode) -> BlockRegistry {
+    let mut buffer = BytesMut::new();
+    for name in ["minecraft:air", "minecraft:stone"] {
+        nbt::serialize_net("", &block(name), &mut buffer);
+    }
+
+    BlockRegistry::new(buffer.freeze(), mode).unwrap()
+}
+
+#[test]
+fn block_registry() {
+    let sequential = registry(RuntimeIdMode::Sequential);
+    assert_eq!(sequential.len(), 2);
+    assert_eq!(sequential.runtime_id(&block("minecraft:stone")), Some(1));
+    assert_eq!(sequential.runtime_id(&block("minecraft:dirt")), None);
+    assert_eq!(sequential.block_state(1), Some(&block("minecraft:stone")));
+
+    // Hashed runtime IDs do not depend on the order of the block states.
+    let hashed = registry(RuntimeIdMode::Hashed);
+    let id = hashed.runtime_id(&block("minecraft:stone")).unwrap();
+    assert_eq!(Some(id), crate::hash_block_state(&block("minecraft:stone")));
+    assert_eq!(hashed.block_state(id), Some(&block("minecraft:stone")));
+
+    // Unknown blocks are replaced with air, since the registry has no update block.
+    let mut record = StorageRecord::filled(block("minecraft:dirt"));
+    record.set(Vector3b::from([0, 0, 0]), block("minecraft:stone"));
+    let runtime = sequential.to_runtime(&record);
+    assert_eq!(runtime.palette(), &[0, 1]);
+}
+
+#[test]
+fn flat_generator() {
+    let registry = registry(RuntimeIdMode::Sequential);
+    let generator = FlatGenerator::from_preset(
+        "3*minecraft:stone,air,minecraft:stone;minecraft:desert",
+        &registry,
+        |name| (name == "minecraft:desert").then_some(2),
+    )
+    .unwrap();
+    assert_eq!(generator.layers().len(), 3);
+    assert_eq!(generator.biome(), 2);
+
+    let chunk = generator.generate(-1, 2, Dimension::Overworld).unwrap();
+    assert_eq!(chunk.get(-16, -64, 32), Some(&block("minecraft:stone")));
+    assert_eq!(chunk.get(-1, -62, 47), Some(&block("minecraft:stone")));
+    assert_eq!(chunk.get(-5, -61, 40), Some(&block("minecraft:air")));
+    assert_eq!(chunk.get(-5, -60, 40), Some(&block("minecraft:stone")));
+    assert_ne!(chunk.get(-5, -59, 40), Some(&block("minecraft:stone")));
+    assert_eq!(chunk.heightmap()[0], 5);
+    assert_eq!(chunk.get_biome(-5, 100, 40), Some(2));
+
+    assert!(FlatGenerator::from_preset("2*dirt", &registry, |_| None).is_err());
+    assert!(
+        FlatGenerator::from_preset("stone;ocean", &registry, |_| None).is_err()
+    );
+}
+
+/// Hashes the block names and biomes of a generated chunk.
+/// Unlike the network encoding, this does not depend on block runtime IDs.
+fn generated_chunk_hash(generator: &TerrainGenerator, x: i32, z: i32) -> u64 {
+    let dimension = Dimension::Overworld;
+    let chunk = generator.generate(x, z, dimension).unwrap();
+
+    let mut hasher = Xxh64::new(0);
+    for y in dimension.height_range() {
+        for (dx, dz) in (0..16).flat_map(|dx| (0..16).map(move |dz| (dx, dz))) {
+            let (x, z) = (x * 16 + dx, z * 16 + dz);
+            // Sub chunks that do not exist are filled with air.
+            let name = match chunk.get(x, y, z) {
+                Some(nbt::Value::Compound(block)) => match block.get("name") {
+                    Some(nbt::Value::String(name)) => name.as_str(),
+                    _ => panic!("Block at [{x}, {y}, {z}] has no name"),
+                },
+                Some(_) => panic!("Block at [{x}, {y}, {z}] is not a compound"),
+                None => "minecraft:air",
+            };
+
+            hasher.update(name.as_bytes());
+            hasher.update(&chunk.get_biome(x, y, z).unwrap().to_le_bytes());
+        }
+    }
+
+    hasher.digest()
+}
+
+#[test]
+fn terrain_generator() {
+    let mut buffer = BytesMut::new();
+    for name in TERRAIN_BLOCKS {
+        nbt::serialize_net("", &block(name), &mut buffer);
+    }
+    let registry =
+        BlockRegistry::new(buffer.freeze(), RuntimeIdMode::Sequential).unwrap();
+
+    let generator = TerrainGenerator::new(69420, &registry).unwrap();
+    let chunk = generator.generate(3, -7, Dimension::Overworld).unwrap();
+    let surface = generator.terrain_height(48, -112);
+    assert!(chunk.get(48, surface, -112).is_some());
+    assert_eq!(
+        chunk.get_biome(48, surface, -112),
+        Some(generator.biome(48, -112))
+    );
+
+    // Generation only depends on the seed.
+    let same = TerrainGenerator::new(69420, &registry).unwrap();
+    let hash = generated_chunk_hash(&generator, 3, -7);
+    assert_eq!(hash, generated_chunk_hash(&same, 3, -7));
+    // Changes to this hash mean that existing worlds will have seams at the border of generated chunks.
+    assert_eq!(hash, 0xa7f0_7fed_7624_fb47);
+
+    let other = TerrainGenerator::new(1, &registry).unwrap();
+    assert_ne!(hash, generated_chunk_hash(&other, 3, -7));
+}
+
+#[test]
+fn level_data() {
+    let mut data = LevelData::new("Test level", -1234);
+    data.spawn = Vector3i::from([10, -20, 30]);
+    data.time = 6000;
+    data.set_game_rule("dodaylightcycle", GameRuleValue::Bool(false));
+    data.set_game_rule("randomtickspeed", GameRuleValue::Int(3));
+
+    let mut buffer = BytesMut::new();
+    data.serialize(&mut buffer);
+    let buffer = buffer.freeze();
+
+    // The header contains the storage version and the size of the NBT data.
+    assert_eq!(&buffer[0..4], &LEVEL_STORAGE_VERSION.to_le_bytes());
+    assert_eq!(&buffer[4..8], &(buffer.len() as i32 - 8).to_le_bytes());
+
+    let parsed = LevelData::deserialize(buffer).unwrap();
+    assert_eq!(parsed, data);
+    assert_eq!(
+        parsed.game_rule("dodaylightcycle"),
+        Some(GameRuleValue::Bool(false))
+    );
+    assert_eq!(
+        parsed.game_rule("randomtickspeed"),
+        Some(GameRuleValue::Int(3))
+    );
+    assert_eq!(parsed.game_rule("pvp"), None);
+
+    assert!(LevelData::read("test/missing/level.dat").unwrap().is_none());
+}
+
+#[test]
+fn player_record() {
+    let mut record = PlayerRecord::default();
+    record.position = Vector3f::from([1.5, 70.0, -3.25]);
+    record.pitch = 45.0;
+    record.yaw = 90.0;
+    record.dimension = Dimension::Nether;
+    record.game_mode = 1;
+    record.permission_level = 2;
+
+    let mut buffer = BytesMut::new();
+    record.serialize(&mut buffer);
+    let parsed = PlayerRecord::deserialize(buffer.freeze()).unwrap();
+    assert_eq!(parsed.position, record.position);
+    assert_eq!((parsed.pitch, parsed.yaw), (45.0, 90.0));
+    assert_eq!(parsed.dimension, Dimension::Nether);
+    assert_eq!(parsed.game_mode, 1);
+    assert_eq!(parsed.permission_level, 2);
+
+    // Writing the record back does not change it.
+    record = parsed;
+    let mut buffer = BytesMut::new();
+    record.serialize(&mut buffer);
+    assert_eq!(PlayerRecord::deserialize(buffer.freeze()).unwrap(), record);
+}
+
+#[test]
+fn player_record_pointer() {
+    let path = std::env::temp_dir()
+        .join(format!("nova-player-{}", std::process::id()));
+    let db = ChunkDatabase::new(path.to_str().unwrap()).unwrap();
+
+    // Data written by vanilla, the server ID is unrelated to the client UUID.
+    let server_id = "player_server_0b5d6a44-7bd6-4b4f-9ad0-7f4f0f0c1d2e";
+    let uuid = "d8a1c7a2-3f6e-4c1b-8e0e-5b6d9f6f2a11";
+    let pointer = nbt::Value::Compound(HashMap::from([
+        ("MsaId".to_owned(), nbt::Value::String("2535".to_owned())),
+        (
+            "SelfSignedId".to_owned(),
+            nbt::Value::String(uuid.to_owned()),
+        ),
+        (
+            "ServerId".to_owned(),
+            nbt::Value::String(server_id.to_owned()),
+        ),
+    ]));
+    let mut buffer = BytesMut::new();
+    nbt::serialize_le("", &pointer, &mut buffer);
+    db.put_raw_key("player_2535", buffer).unwrap();
+
+    let mut buffer = BytesMut::new();
+    PlayerRecord::default().serialize(&mut buffer);
+    db.put_raw_key(server_id, buffer).unwrap();
+
+    let mut record = PlayerRecord::load(&db, 2535, uuid).unwrap().unwrap();
+    record.game_mode = 1;
+    record.save(&db, 2535, uuid).unwrap();
+
+    // The record is written back to the key that the pointer refers to.
+    let stored = db.get_raw_key(server_id).unwrap().unwrap();
+    assert_eq!(PlayerRecord::deserialize(stored).unwrap().game_mode, 1);
+    let players = db
+        .keys()
+        .unwrap()
+        .into_iter()
+        .filter(|key| matches!(key, LevelKey::Player(_)))
+        .count();
+    assert_eq!(players, 2);
+
+    // Players without an Xbox account are looked up by their UUID,
+    // but their data is stored under a key generated by the server.
+    assert!(PlayerRecord::load(&db, 0, uuid).unwrap().is_none());
+    let mut record = PlayerRecord::default();
+    record.game_mode = 2;
+    record.save(&db, 0, uuid).unwrap();
+    let loaded = PlayerRecord::load(&db, 0, uuid).unwrap().unwrap();
+    assert_eq!(loaded.game_mode, 2);
+    assert!(db
+        .get_raw_key(format!("player_server_{uuid}"))
+        .unwrap()
+        .is_none());
+
+    drop(db);
+    let _ = std::fs::remove_dir_all(path);
+}
+
+#[test]
+fn block_entities() {
+    let mut sign = BlockEntity::new("Sign", Vector3i::from([-20, -40, 35]));
+    sign.data.insert(
+        "Text".to_owned(),
+        nbt::Value::String("Hello world".to_owned()),
+    );
+    let chest = BlockEntity::new("Chest", Vector3i::from([-17, 70, 33]));
+
+    let mut buffer = BytesMut::new();
+    sign.serialize(&mut buffer);
+    chest.serialize(&mut buffer);
+    let parsed = BlockEntity::deserialize_all(buffer.freeze()).unwrap();
+    assert_eq!(parsed, [sign.clone(), chest.clone()]);
+
+    let mut chunk = Chunk::new(-2, 2, Dimension::Overworld);
+    chunk.set_block_entity(sign.clone()).unwrap();
+    chunk.set_block_entity(chest).unwrap();
+    assert!(chunk
+        .set_block_entity(BlockEntity::new("Chest", Vector3i::from([0, 0, 0])))
+        .is_err());
+    assert_eq!(chunk.block_entity(-20, -40, 35), Some(&sign));
+
+    // Only the sign is located in sub chunk -3.
+    let mut network = BytesMut::new();
+    chunk.serialize_network_block_entities(Some(-3), &mut network);
+    let mut expected = BytesMut::new();
+    sign.serialize_network(&mut expected);
+    assert_eq!(network, expected);
+
+    assert!(chunk.remove_block_entity(-17, 70, 33).is_some());
+    assert_eq!(chunk.block_entities().count(), 1);
+}
+
+#[test]
+fn entity_storage() {
+    let path = std::env::temp_dir()
+        .join(format!("nova-entities-{}", std::process::id()));
+    let db = ChunkDatabase::new(path.to_str().unwrap()).unwrap();
+
+    let zombie = Entity::new(
+        -42,
+        "minecraft:zombie",
+        Vector3f::from([-20.5, 64.0, 35.0]),
+    );
+    let mut item =
+        Entity::new(7, "minecraft:item", Vector3f::from([-17.0, 70.0, 33.9]));
+    item.data.insert("Age".to_owned(), nbt::Value::Short(20));
+
+    // Entities of older worlds are stored in a single record per chunk.
+    let mut legacy = BytesMut::new();
+    zombie.serialize(&mut legacy);
+    item.serialize(&mut legacy);
+
+    let key = |tag| {
+        let mut buffer = BytesMut::new();
+        DatabaseKey {
+            x: -2,
+            z: 2,
+            y: 0,
+            dimension: Dimension::Nether,
+            tag,
+        }
+        .serialize(&mut buffer);
+        buffer
+    };
+    db.put_raw_key(key(DatabaseTag::ChunkVersion), [40])
+        .unwrap();
+    db.put_raw_key(key(DatabaseTag::Entity), &legacy).unwrap();
+
+    let chunk = Chunk::load(&db, -2, 2, Dimension::Nether).unwrap().unwrap();
+    assert_eq!(chunk.entities(), [zombie.clone(), item.clone()]);
+
+    // Saving migrates the entities to the digest format.
+    let mut batch = WriteBatch::new();
+    chunk.save(&mut batch);
+    db.write_batch(&batch).unwrap();
+    assert!(db.get_raw_key(key(DatabaseTag::Entity)).unwrap().is_none());
+
+    let digest_key = Entity::digest_key(-2, 2, Dimension::Nether);
+    assert_eq!(
+        LevelKey::deserialize(digest_key.clone().freeze()).unwrap(),
+        LevelKey::EntityDigest {
+            x: -2,
+            z: 2,
+            dimension: Dimension::Nether
+        }
+    );
+    assert_eq!(db.get_raw_key(&digest_key).unwrap().unwrap().len(), 16);
+
+    let mut chunk =
+        Chunk::load(&db, -2, 2, Dimension::Nether).unwrap().unwrap();
+    assert_eq!(chunk.entity(7), Some(&item));
+
+    // Records of removed entities are deleted.
+    assert!(chunk.remove_entity(-42).is_some());
+    let mut batch = WriteBatch::new();
+    chunk.save(&mut batch);
+    db.write_batch(&batch).unwrap();
+
+    let actor_key = Entity::actor_key(&zombie.storage_id());
+    assert_eq!(
+        LevelKey::deserialize(actor_key.clone().freeze()).unwrap(),
+        LevelKey::Actor(zombie.storage_id())
+    );
+    assert!(db.get_raw_key(&actor_key).unwrap().is_none());
+
+    let chunk = Chunk::load(&db, -2, 2, Dimension::Nether).unwrap().unwrap();
+    assert_eq!(chunk.entities(), [item]);
+
+    drop(db);
+    let _ = std::fs::remove_dir_all(path);
+}
+
+#[test]
+fn entity_storage_flush() {
+    // The autosave job is never run, chunks are only saved by explicit flushes.
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()
+        .unwrap();
+    let _guard = runtime.enter();
+
+    let path = std::env::temp_dir()
+        .join(format!("nova-entity-flush-{}", std::process::id()));
+    let db = Arc::new(ChunkDatabase::new(path.to_str().unwrap()).unwrap());
+    let (manager, _) = ChunkManager::new(
+        db.clone(),
+        Dimension::Overworld,
+        Duration::from_secs(60),
+        None,
+        CancellationToken::new(),
+    );
+
+    let mut zombie =
+        Entity::new(9, "minecraft:zombie", Vector3f::from([1.0, 64.0, 1.0]));
+    let record = Entity::actor_key(&zombie.storage_id());
+
+    let mut first = Chunk::new(0, 0, Dimension::Overworld);
+    first.add_entity(zombie.clone()).unwrap();
+    manager.set_chunk(first).unwrap();
+    manager
+        .set_chunk(Chunk::new(1, 0, Dimension::Overworld))
+        .unwrap();
+    manager.flush().unwrap();
+
+    // The entity moves to the second chunk, which is saved on its own.
+    zombie.position = Vector3f::from([17.0, 64.0, 1.0]);
+    let mut second = manager.get_chunk(1, 0).unwrap().unwrap();
+    second.add_entity(zombie.clone()).unwrap();
+    manager.set_chunk(second).unwrap();
+    manager.flush().unwrap();
+
+    // Removing it from the first chunk must not delete the record written by the second.
+    let mut first = manager.get_chunk(0, 0).unwrap().unwrap();
+    assert!(first.remove_entity(9).is_some());
+    manager.set_chunk(first).unwrap();
+    manager.flush().unwrap();
+    assert!(db.get_raw_key(&record).unwrap().is_some());
+
+    // Moving back within a single flush keeps the record as well.
+    zombie.position = Vector3f::from([1.0, 64.0, 1.0]);
+    let mut first = manager.get_chunk(0, 0).unwrap().unwrap();
+    let mut second = manager.get_chunk(1, 0).unwrap().unwrap();
+    assert!(second.remove_entity(9).is_some());
+    first.add_entity(zombie).unwrap();
+    manager.set_chunk(second).unwrap();
+    manager.set_chunk(first).unwrap();
+    manager.flush().unwrap();
+    assert!(db.get_raw_key(&record).unwrap().is_some());
+
+    // Entities that were added after loading are deleted once they are removed.
+    let mut first = manager.get_chunk(0, 0).unwrap().unwrap();
+    assert!(first.remove_entity(9).is_some());
+    manager.set_chunk(first).unwrap();
+    manager.flush().unwrap();
+    assert!(db.get_raw_key(&record).unwrap().is_none());
+
+    drop(manager);
+    drop(db);
+    let _ = std::fs::remove_dir_all(path);
+}
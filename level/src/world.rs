@@ -1,36 +1,92 @@
-use bytes::{BufMut, Bytes, BytesMut};
-use common::{Serialize, VResult};
+use std::ops::Range;
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use common::{bail, Deserialize, Serialize, VError, VResult};
+
+use crate::{Entity, ACTOR_PREFIX, DIGEST_PREFIX};
 
 /// Database key prefixes.
 ///
 /// Data from [`Minecraft fandom`](https://minecraft.fandom.com/wiki/Bedrock_Edition_level_format#Chunk_key_format).
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum DatabaseTag {
     /// 3D biome map.
-    Biome3d = 0x2b,
+    Biome3d,
     /// Version of the specified chunk.
-    ChunkVersion = 0x2c,
+    ChunkVersion,
+    /// Heightmap and 2D biome map.
+    Data2d,
+    /// Heightmap and 2D biome colours, used by versions before 1.0.
+    LegacyData2d,
     /// Sub chunk data.
-    SubChunk = 0x2f,
+    SubChunk,
     /// A block entity.
-    BlockEntity = 0x31,
+    BlockEntity,
     /// An entity.
-    Entity = 0x32,
+    Entity,
     /// Pending tick data.
-    PendingTicks = 0x33,
+    PendingTicks,
     /// Biome state.
-    BiomeState = 0x35,
+    BiomeState,
     /// Finalized state.
-    FinalizedState = 0x36,
+    FinalizedState,
     /// Education Edition border blocks.
-    BorderBlocks = 0x38,
+    BorderBlocks,
     /// Bounding boxes for structure spawns stored in binary format.
-    HardCodedSpawnAreas = 0x39,
+    HardCodedSpawnAreas,
     /// Random tick data.
-    RandomTicks = 0x3a,
+    RandomTicks,
+    /// Version of the specified chunk, used by versions before 1.16.100.
+    LegacyChunkVersion,
+    /// A tag that is not known by the server.
+    Unknown(u8),
 }
 
-#[derive(Debug, Clone)]
+impl From<u8> for DatabaseTag {
+    fn from(value: u8) -> Self {
+        match value {
+            0x2b => Self::Biome3d,
+            0x2c => Self::ChunkVersion,
+            0x2d => Self::Data2d,
+            0x2e => Self::LegacyData2d,
+            0x2f => Self::SubChunk,
+            0x31 => Self::BlockEntity,
+            0x32 => Self::Entity,
+            0x33 => Self::PendingTicks,
+            0x35 => Self::BiomeState,
+            0x36 => Self::FinalizedState,
+            0x38 => Self::BorderBlocks,
+            0x39 => Self::HardCodedSpawnAreas,
+            0x3a => Self::RandomTicks,
+            0x76 => Self::LegacyChunkVersion,
+            _ => Self::Unknown(value),
+        }
+    }
+}
+
+impl From<DatabaseTag> for u8 {
+    fn from(value: DatabaseTag) -> Self {
+        match value {
+            DatabaseTag::Biome3d => 0x2b,
+            DatabaseTag::ChunkVersion => 0x2c,
+            DatabaseTag::Data2d => 0x2d,
+            DatabaseTag::LegacyData2d => 0x2e,
+            DatabaseTag::SubChunk => 0x2f,
+            DatabaseTag::BlockEntity => 0x31,
+            DatabaseTag::Entity => 0x32,
+            DatabaseTag::PendingTicks => 0x33,
+            DatabaseTag::BiomeState => 0x35,
+            DatabaseTag::FinalizedState => 0x36,
+            DatabaseTag::BorderBlocks => 0x38,
+            DatabaseTag::HardCodedSpawnAreas => 0x39,
+            DatabaseTag::RandomTicks => 0x3a,
+            DatabaseTag::LegacyChunkVersion => 0x76,
+            DatabaseTag::Unknown(tag) => tag,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub struct DatabaseKey {
     /// X coordinate of the chunk.
     pub x: i32,
@@ -46,6 +102,22 @@ pub struct DatabaseKey {
 }
 
 impl DatabaseKey {
+    /// Creates the prefix shared by all keys of the given chunk.
+    ///
+    /// Note that overworld prefixes are also a prefix of keys in other dimensions
+    /// at the same coordinates.
+    pub fn chunk_prefix(x: i32, z: i32, dimension: Dimension) -> BytesMut {
+        let mut buffer = BytesMut::with_capacity(12);
+        buffer.put_i32_le(x);
+        buffer.put_i32_le(z);
+
+        if dimension != Dimension::Overworld {
+            buffer.put_i32_le(dimension as i32);
+        }
+
+        buffer
+    }
+
     pub fn serialized_size(&self) -> usize {
         4 + 4
             + if self.dimension != Dimension::Overworld {
@@ -71,15 +143,171 @@ impl Serialize for DatabaseKey {
             buffer.put_i32_le(self.dimension as i32);
         }
 
-        buffer.put_u8(self.tag as u8);
+        buffer.put_u8(self.tag.into());
         if self.tag == DatabaseTag::SubChunk {
             buffer.put_i8(self.y);
         }
     }
 }
 
+impl Deserialize for DatabaseKey {
+    fn deserialize(mut buffer: Bytes) -> VResult<Self> {
+        // Overworld keys do not contain a dimension.
+        // Keys with a Y index have an extra byte.
+        let (has_dimension, has_index) = match buffer.len() {
+            9 => (false, false),
+            10 => (false, true),
+            13 => (true, false),
+            14 => (true, true),
+            len => bail!(DatabaseFailure, "Invalid chunk key length {len}"),
+        };
+
+        let x = buffer.get_i32_le();
+        let z = buffer.get_i32_le();
+        let dimension = if has_dimension {
+            Dimension::try_from(buffer.get_i32_le())?
+        } else {
+            Dimension::Overworld
+        };
+
+        let tag = DatabaseTag::from(buffer.get_u8());
+        let y = if has_index { buffer.get_i8() } else { 0 };
+
+        Ok(Self { x, z, y, dimension, tag })
+    }
+}
+
+/// Prefix of player keys in the database.
+const PLAYER_PREFIX: &str = "player_";
+/// Prefix of village keys in the database.
+const VILLAGE_PREFIX: &str = "VILLAGE_";
+
+/// Any key that can be found in the world database.
+///
+/// Besides chunk data, Bedrock also stores several global records under string keys.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum LevelKey {
+    /// Data belonging to a specific chunk.
+    Chunk(DatabaseKey),
+    /// List of the entities in a chunk.
+    EntityDigest { x: i32, z: i32, dimension: Dimension },
+    /// A single entity, identified by its storage ID.
+    Actor([u8; 8]),
+    /// Player data of the local player in singleplayer worlds.
+    LocalPlayer,
+    /// Player data of a specific player.
+    /// This contains the part of the key after `player_`.
+    Player(String),
+    /// Village data.
+    /// This contains the part of the key after `VILLAGE_`.
+    Village(String),
+    /// Nether portal locations.
+    Portals,
+    /// Mob event settings.
+    MobEvents,
+    /// Scoreboard data.
+    Scoreboard,
+    /// Entities that are not attached to a chunk, such as the ender dragon.
+    AutonomousEntities,
+    /// Global biome data.
+    BiomeData,
+    /// A key that is not known by the server.
+    Unknown(Bytes),
+}
+
+impl LevelKey {
+    /// Parses a string key, returning `None` if it is not a known string key.
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "~local_player" => Self::LocalPlayer,
+            "portals" => Self::Portals,
+            "mobevents" => Self::MobEvents,
+            "scoreboard" => Self::Scoreboard,
+            "AutonomousEntities" => Self::AutonomousEntities,
+            "BiomeData" => Self::BiomeData,
+            _ => {
+                if let Some(id) = name.strip_prefix(PLAYER_PREFIX) {
+                    Self::Player(id.to_owned())
+                } else if let Some(id) = name.strip_prefix(VILLAGE_PREFIX) {
+                    Self::Village(id.to_owned())
+                } else {
+                    return None;
+                }
+            }
+        })
+    }
+}
+
+impl Serialize for LevelKey {
+    fn serialize(&self, buffer: &mut BytesMut) {
+        match self {
+            Self::Chunk(key) => key.serialize(buffer),
+            Self::EntityDigest { x, z, dimension } => {
+                buffer.put(Entity::digest_key(*x, *z, *dimension))
+            }
+            Self::Actor(id) => buffer.put(Entity::actor_key(id)),
+            Self::LocalPlayer => buffer.put(b"~local_player".as_ref()),
+            Self::Player(id) => {
+                buffer.put(PLAYER_PREFIX.as_bytes());
+                buffer.put(id.as_bytes());
+            }
+            Self::Village(id) => {
+                buffer.put(VILLAGE_PREFIX.as_bytes());
+                buffer.put(id.as_bytes());
+            }
+            Self::Portals => buffer.put(b"portals".as_ref()),
+            Self::MobEvents => buffer.put(b"mobevents".as_ref()),
+            Self::Scoreboard => buffer.put(b"scoreboard".as_ref()),
+            Self::AutonomousEntities => {
+                buffer.put(b"AutonomousEntities".as_ref())
+            }
+            Self::BiomeData => buffer.put(b"BiomeData".as_ref()),
+            Self::Unknown(key) => buffer.put(key.as_ref()),
+        }
+    }
+}
+
+impl Deserialize for LevelKey {
+    fn deserialize(buffer: Bytes) -> VResult<Self> {
+        // String keys are checked first because some of them, such as `mobevents`,
+        // have the same length as chunk keys.
+        if let Ok(name) = std::str::from_utf8(buffer.as_ref()) {
+            if let Some(key) = Self::from_name(name) {
+                return Ok(key);
+            }
+        }
+
+        if let Some(id) = buffer.strip_prefix(ACTOR_PREFIX) {
+            if let Ok(id) = <[u8; 8]>::try_from(id) {
+                return Ok(Self::Actor(id));
+            }
+        }
+
+        if let Some(mut position) = buffer.strip_prefix(DIGEST_PREFIX) {
+            if position.len() == 8 || position.len() == 12 {
+                let x = position.get_i32_le();
+                let z = position.get_i32_le();
+                let dimension = if position.has_remaining() {
+                    Dimension::try_from(position.get_i32_le())
+                } else {
+                    Ok(Dimension::Overworld)
+                };
+
+                if let Ok(dimension) = dimension {
+                    return Ok(Self::EntityDigest { x, z, dimension });
+                }
+            }
+        }
+
+        Ok(match DatabaseKey::deserialize(buffer.clone()) {
+            Ok(key) => Self::Chunk(key),
+            Err(_) => Self::Unknown(buffer),
+        })
+    }
+}
+
 /// The Minecraft dimensions.
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum Dimension {
     /// The overworld dimension.
     Overworld,
@@ -88,3 +316,50 @@ pub enum Dimension {
     /// The end dimension.
     End,
 }
+
+impl Dimension {
+    /// Range of Y coordinates that blocks can be placed at.
+    pub const fn height_range(self) -> Range<i32> {
+        match self {
+            Self::Overworld => -64..320,
+            Self::Nether => 0..128,
+            Self::End => 0..256,
+        }
+    }
+
+    /// Vertical index of the lowest sub chunk.
+    pub const fn min_sub_chunk(self) -> i8 {
+        (self.height_range().start >> 4) as i8
+    }
+
+    /// Amount of sub chunks in a single chunk.
+    pub const fn sub_chunk_count(self) -> usize {
+        let range = self.height_range();
+        ((range.end - range.start) >> 4) as usize
+    }
+
+    /// ID of the biome that new chunks are filled with.
+    pub const fn default_biome(self) -> u32 {
+        match self {
+            // Plains
+            Self::Overworld => 1,
+            // Nether wastes
+            Self::Nether => 8,
+            // The end
+            Self::End => 9,
+        }
+    }
+}
+
+impl TryFrom<i32> for Dimension {
+    type Error = VError;
+
+    fn try_from(value: i32) -> VResult<Self> {
+        Ok(match value {
+            0 => Self::Overworld,
+            1 => Self::Nether,
+            2 => Self::End,
+            _ => bail!(InvalidChunk, "Invalid dimension {value}"),
+        })
+    }
+}
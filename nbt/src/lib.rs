@@ -36,6 +36,16 @@ pub const TAG_COMPOUND: u8 = 0x0a;
 pub const TAG_INT_ARRAY: u8 = 0x0b;
 pub const TAG_LONG_ARRAY: u8 = 0x0c;
 
+/// Maps a signed integer to the unsigned value written by `put_var_i32`.
+const fn zigzag_i32(value: i32) -> u32 {
+    ((value << 1) ^ (value >> 31)) as u32
+}
+
+/// Maps a signed integer to the unsigned value written by `put_var_i64`.
+const fn zigzag_i64(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
 /// NBT tag value.
 #[derive(Debug, Clone, PartialEq)]
 pub enum Value {
@@ -60,27 +70,75 @@ impl Value {
             Self::End => 0,
             Self::Byte(_) => 1,
             Self::Short(_) => 2,
-            Self::Int(_) => 4,
-            Self::Long(_) => 8,
+            Self::Int(v) => zigzag_i32(*v).var_len(),
+            Self::Long(v) => zigzag_i64(*v).var_len(),
             Self::Float(_) => 4,
-            Self::Double(_) => 4,
+            Self::Double(_) => 8,
             Self::String(s) => (s.len() as u32).var_len() + s.len(),
             Self::List(v) => {
-                (v.len() as u32).var_len()
+                1 + zigzag_i32(v.len() as i32).var_len()
                     + v.iter()
                         .fold(0, |acc, x| acc + x.serialized_value_net_size())
             }
-            Self::Compound(c) => c
-                .iter()
-                .fold(0, |acc, kv| acc + kv.1.serialized_net_size(&kv.0)),
-            Self::ByteArray(v) => (v.len() as u32).var_len() + v.len(),
-            Self::IntArray(v) => (v.len() as u32).var_len() + 4 * v.len(),
-            Self::LongArray(v) => (v.len() as u32).var_len() + 8 * v.len(),
+            Self::Compound(c) => {
+                c.iter()
+                    .fold(0, |acc, kv| acc + kv.1.serialized_net_size(kv.0))
+                    + 1
+            }
+            Self::ByteArray(v) => zigzag_i32(v.len() as i32).var_len() + v.len(),
+            Self::IntArray(v) => v.iter().fold(
+                zigzag_i32(v.len() as i32).var_len(),
+                |acc, x| acc + zigzag_i32(*x).var_len(),
+            ),
+            Self::LongArray(v) => v.iter().fold(
+                zigzag_i32(v.len() as i32).var_len(),
+                |acc, x| acc + zigzag_i64(*x).var_len(),
+            ),
         }
     }
 
+    /// Size of the tag in the network format, including its type and name.
     pub fn serialized_net_size(&self, name: &str) -> usize {
-        name.var_len() + self.serialized_value_net_size()
+        if matches!(self, Self::End) {
+            return 1;
+        }
+
+        1 + name.var_len() + self.serialized_value_net_size()
+    }
+
+    fn serialized_value_le_size(&self) -> usize {
+        match self {
+            Self::End => 0,
+            Self::Byte(_) => 1,
+            Self::Short(_) => 2,
+            Self::Int(_) => 4,
+            Self::Long(_) => 8,
+            Self::Float(_) => 4,
+            Self::Double(_) => 8,
+            Self::String(s) => 2 + s.len(),
+            Self::List(v) => {
+                1 + 4
+                    + v.iter()
+                        .fold(0, |acc, x| acc + x.serialized_value_le_size())
+            }
+            Self::Compound(c) => {
+                c.iter()
+                    .fold(0, |acc, kv| acc + kv.1.serialized_le_size(kv.0))
+                    + 1
+            }
+            Self::ByteArray(v) => 4 + v.len(),
+            Self::IntArray(v) => 4 + 4 * v.len(),
+            Self::LongArray(v) => 4 + 8 * v.len(),
+        }
+    }
+
+    /// Size of the tag in the little endian format, including its type and name.
+    pub fn serialized_le_size(&self, name: &str) -> usize {
+        if matches!(self, Self::End) {
+            return 1;
+        }
+
+        1 + 2 + name.len() + self.serialized_value_le_size()
     }
 
     /// Converts the value type to a numeric ID.
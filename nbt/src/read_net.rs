@@ -55,11 +55,11 @@ impl Value {
                 Self::Short(value)
             }
             TAG_INT => {
-                let value = stream.get_i32_le();
+                let value = stream.get_var_i32()?;
                 Self::Int(value)
             }
             TAG_LONG => {
-                let value = stream.get_i64_le();
+                let value = stream.get_var_i64()?;
                 Self::Long(value)
             }
             TAG_FLOAT => {
@@ -124,7 +124,7 @@ impl Value {
                 let mut list = Vec::with_capacity(length as usize);
 
                 for _ in 0..length {
-                    list.push(stream.get_i32());
+                    list.push(stream.get_var_i32()?);
                 }
 
                 Self::IntArray(list)
@@ -134,7 +134,7 @@ impl Value {
                 let mut list = Vec::with_capacity(length as usize);
 
                 for _ in 0..length {
-                    list.push(stream.get_i64());
+                    list.push(stream.get_var_i64()?);
                 }
 
                 Self::LongArray(list)
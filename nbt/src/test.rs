@@ -50,3 +50,48 @@ fn hello_world_nbt() {
 fn player_nan_value_nbt() {
     crate::deserialize_be(&mut Bytes::from(PLAYER_NAN_VALUE_NBT)).unwrap();
 }
+
+#[test]
+fn net_round_trip() {
+    let value = Value::Compound(HashMap::from([
+        ("int".to_owned(), Value::Int(-17959425)),
+        ("long".to_owned(), Value::Long(1 << 40)),
+        (
+            "list".to_owned(),
+            Value::List(vec![
+                Value::String("a".to_owned()),
+                Value::String("b".to_owned()),
+            ]),
+        ),
+        ("ints".to_owned(), Value::IntArray(vec![1, -2, 3])),
+    ]));
+
+    let mut encoded = BytesMut::new();
+    crate::serialize_net("", &value, &mut encoded);
+    assert_eq!(value.serialized_net_size(""), encoded.len());
+
+    let decoded = crate::deserialize_net(&mut encoded.freeze()).unwrap();
+    assert_eq!(decoded.value, value);
+}
+
+#[test]
+fn le_size() {
+    let value = Value::Compound(HashMap::from([
+        ("byte".to_owned(), Value::Byte(1)),
+        ("double".to_owned(), Value::Double(0.5)),
+        ("name".to_owned(), Value::String("Bananrama".to_owned())),
+        (
+            "list".to_owned(),
+            Value::List(vec![Value::Short(1), Value::Short(2)]),
+        ),
+        ("longs".to_owned(), Value::LongArray(vec![1, -2])),
+        (
+            "nested".to_owned(),
+            Value::Compound(HashMap::from([("int".to_owned(), Value::Int(5))])),
+        ),
+    ]));
+
+    let mut encoded = BytesMut::new();
+    crate::serialize_le("root", &value, &mut encoded);
+    assert_eq!(value.serialized_le_size("root"), encoded.len());
+}
@@ -45,6 +45,7 @@ impl Value {
                 stream.put_u8(
                     v.get(0).map(|t| t.as_numeric_id()).unwrap_or(TAG_BYTE),
                 );
+                stream.put_i32_le(v.len() as i32);
                 for t in v {
                     Self::serialize_value_le(stream, t);
                 }
@@ -46,6 +46,7 @@ impl Value {
                 stream.put_u8(
                     v.get(0).map(|t| t.as_numeric_id()).unwrap_or(TAG_BYTE),
                 );
+                stream.put_var_i32(v.len() as i32);
                 for t in v {
                     Self::serialize_value_net(stream, t);
                 }
@@ -0,0 +1,331 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::path::Path;
+
+use bytes::Bytes;
+use common::{bail, error, VResult};
+
+/// Vanilla biome definitions, in the format used by the `BiomeDefinitionList` packet.
+const DEFINITIONS: &[u8] = include_bytes!("../included/biomes.nbt");
+/// Numeric IDs of the vanilla biomes.
+const BIOME_IDS: &str = include_str!("../included/biome_ids.json");
+/// Water colour of biomes that do not specify one.
+const DEFAULT_WATER_COLOUR: u32 = 0xff3f76e4;
+
+/// Properties of a single biome.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BiomeDefinition {
+    /// Numeric ID used in chunk data.
+    pub id: u32,
+    /// Name of the biome.
+    pub name: String,
+    /// Temperature, this determines whether it rains or snows.
+    pub temperature: f32,
+    /// Amount of rainfall.
+    pub downfall: f32,
+    /// Colour of water in ARGB format.
+    pub water_colour: u32,
+    /// Tags used by the client, such as "ocean" or "nether".
+    pub tags: Vec<String>,
+}
+
+impl BiomeDefinition {
+    /// Reads a vanilla definition from the `BiomeDefinitionList` format.
+    ///
+    /// Properties that are missing from the definition use their default values.
+    fn from_nbt(id: u32, name: &str, definition: &nbt::Value) -> Self {
+        let nbt::Value::Compound(map) = definition else {
+            return Self {
+                id,
+                name: name.to_owned(),
+                temperature: 0.5,
+                downfall: 0.5,
+                water_colour: DEFAULT_WATER_COLOUR,
+                tags: Vec::new(),
+            };
+        };
+
+        let float = |key: &str, default: f32| match map.get(key) {
+            Some(nbt::Value::Float(value)) => *value,
+            _ => default,
+        };
+        let colour = |key: &str, shift: u32| {
+            let default = ((DEFAULT_WATER_COLOUR >> shift) & 0xff) as f32;
+            ((float(key, default / 255.0) * 255.0).round() as u32) << shift
+        };
+
+        let tags = match map.get("tags") {
+            Some(nbt::Value::List(tags)) => tags
+                .iter()
+                .filter_map(|t| match t {
+                    nbt::Value::String(tag) => Some(tag.clone()),
+                    _ => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        Self {
+            id,
+            name: name.to_owned(),
+            temperature: float("temperature", 0.5),
+            downfall: float("downfall", 0.5),
+            water_colour: colour("waterColorA", 24)
+                | colour("waterColorR", 16)
+                | colour("waterColorG", 8)
+                | colour("waterColorB", 0),
+            tags,
+        }
+    }
+
+    /// Converts the definition to the NBT format used by the `BiomeDefinitionList` packet.
+    fn to_nbt(&self) -> nbt::Value {
+        let colour = |shift: u32| {
+            nbt::Value::Float(
+                ((self.water_colour >> shift) & 0xff) as f32 / 255.0,
+            )
+        };
+
+        nbt::Value::Compound(HashMap::from([
+            (
+                "temperature".to_owned(),
+                nbt::Value::Float(self.temperature),
+            ),
+            ("downfall".to_owned(), nbt::Value::Float(self.downfall)),
+            (
+                "rain".to_owned(),
+                nbt::Value::Byte((self.downfall > 0.0) as i8),
+            ),
+            ("waterColorA".to_owned(), colour(24)),
+            ("waterColorR".to_owned(), colour(16)),
+            ("waterColorG".to_owned(), colour(8)),
+            ("waterColorB".to_owned(), colour(0)),
+            (
+                "tags".to_owned(),
+                nbt::Value::List(
+                    self.tags
+                        .iter()
+                        .map(|t| nbt::Value::String(t.clone()))
+                        .collect(),
+                ),
+            ),
+        ]))
+    }
+}
+
+/// Biome file from a behavior pack.
+#[derive(serde::Deserialize, Debug)]
+struct BiomeFile {
+    #[serde(rename = "minecraft:biome")]
+    biome: BiomeFileContents,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct BiomeFileContents {
+    description: BiomeFileDescription,
+    #[serde(default)]
+    components: HashMap<String, serde_json::Value>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct BiomeFileDescription {
+    identifier: String,
+}
+
+/// Contains every biome known by the server.
+#[derive(Debug)]
+pub struct BiomeRegistry {
+    /// All registered biomes.
+    biomes: Vec<BiomeDefinition>,
+    /// Maps biome names to their index in the biome list.
+    by_name: HashMap<String, usize>,
+    /// Maps biome IDs to their index in the biome list.
+    by_id: HashMap<u32, usize>,
+    /// Biome definitions in the format used by the `BiomeDefinitionList` packet.
+    definitions: nbt::Value,
+}
+
+impl BiomeRegistry {
+    /// Loads the vanilla biomes.
+    ///
+    /// The vanilla definitions are sent to the client unchanged.
+    pub fn new() -> VResult<Self> {
+        let ids: HashMap<String, u32> = serde_json::from_str(BIOME_IDS)
+            .map_err(|e| error!(Other, "Failed to parse biome IDs: {}", e))?;
+
+        let definitions =
+            nbt::deserialize_net(&mut Bytes::from_static(DEFINITIONS))?.value;
+        let nbt::Value::Compound(definitions) = definitions else {
+            bail!(Other, "Vanilla biome definitions must be a compound");
+        };
+
+        let mut registry = Self {
+            biomes: Vec::with_capacity(definitions.len()),
+            by_name: HashMap::new(),
+            by_id: HashMap::new(),
+            definitions: nbt::Value::Compound(HashMap::new()),
+        };
+
+        // Sorted so that biomes are always registered in the same order.
+        let mut definitions = definitions.into_iter().collect::<Vec<_>>();
+        definitions.sort_by(|a, b| a.0.cmp(&b.0));
+        for (name, definition) in definitions {
+            let Some(id) = ids.get(&name) else {
+                bail!(Other, "Vanilla biome {name} does not have an ID");
+            };
+
+            let biome = BiomeDefinition::from_nbt(*id, &name, &definition);
+            registry.insert(biome, definition)?;
+        }
+
+        Ok(registry)
+    }
+
+    /// Adds a biome to the registry.
+    ///
+    /// Fails if a biome with the same name or ID already exists.
+    pub fn register(&mut self, biome: BiomeDefinition) -> VResult<()> {
+        let definition = biome.to_nbt();
+        self.insert(biome, definition)
+    }
+
+    /// Adds a biome to the registry with the given `BiomeDefinitionList` entry.
+    fn insert(
+        &mut self,
+        biome: BiomeDefinition,
+        definition: nbt::Value,
+    ) -> VResult<()> {
+        if self.by_name.contains_key(&biome.name) {
+            bail!(Other, "Biome {} has already been registered", biome.name);
+        }
+        if self.by_id.contains_key(&biome.id) {
+            bail!(Other, "Biome ID {} has already been registered", biome.id);
+        }
+
+        if let nbt::Value::Compound(map) = &mut self.definitions {
+            map.insert(biome.name.clone(), definition);
+        }
+
+        self.by_name.insert(biome.name.clone(), self.biomes.len());
+        self.by_id.insert(biome.id, self.biomes.len());
+        self.biomes.push(biome);
+
+        Ok(())
+    }
+
+    /// Registers the custom biomes of a behavior pack.
+    ///
+    /// Biomes are read from the `biomes` directory of the pack,
+    /// and are given IDs following the highest registered ID.
+    /// Files are registered in order of their path, so that biomes keep their IDs across restarts.
+    /// Returns the amount of biomes that were added.
+    pub fn load_behavior_pack<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+    ) -> VResult<usize> {
+        let directory = path.as_ref().join("biomes");
+        if !directory.is_dir() {
+            return Ok(0);
+        }
+
+        let entries = std::fs::read_dir(&directory).map_err(|e| {
+            error!(Other, "Failed to read {}: {}", directory.display(), e)
+        })?;
+
+        let mut paths = Vec::new();
+        for entry in entries {
+            let path = entry
+                .map_err(|e| error!(Other, "Failed to read biome file: {}", e))?
+                .path();
+            if path.extension() == Some(OsStr::new("json")) {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+
+        let mut count = 0;
+        for path in paths {
+            let contents = std::fs::read_to_string(&path).map_err(|e| {
+                error!(Other, "Failed to read {}: {}", path.display(), e)
+            })?;
+            let file: BiomeFile =
+                serde_json::from_str(&contents).map_err(|e| {
+                    error!(
+                        Other,
+                        "Invalid biome file {}: {}",
+                        path.display(),
+                        e
+                    )
+                })?;
+
+            self.register(self.custom_definition(file.biome))?;
+            count += 1;
+        }
+
+        Ok(count)
+    }
+
+    /// Converts a behavior pack biome to a definition.
+    ///
+    /// Components without a namespace are tags.
+    fn custom_definition(&self, biome: BiomeFileContents) -> BiomeDefinition {
+        let climate = biome.components.get("minecraft:climate");
+        let climate_value = |key: &str, default: f32| {
+            climate
+                .and_then(|c| c.get(key))
+                .and_then(|v| v.as_f64())
+                .map_or(default, |v| v as f32)
+        };
+
+        let mut tags = biome
+            .components
+            .keys()
+            .filter(|k| !k.contains(':'))
+            .cloned()
+            .collect::<Vec<_>>();
+        tags.sort();
+
+        BiomeDefinition {
+            id: self.biomes.iter().map(|b| b.id + 1).max().unwrap_or(0),
+            name: biome.description.identifier,
+            temperature: climate_value("temperature", 0.5),
+            downfall: climate_value("downfall", 0.5),
+            water_colour: DEFAULT_WATER_COLOUR,
+            tags,
+        }
+    }
+
+    /// Amount of registered biomes.
+    #[inline]
+    pub const fn len(&self) -> usize {
+        self.biomes.len()
+    }
+
+    /// Whether the registry contains no biomes.
+    #[inline]
+    pub const fn is_empty(&self) -> bool {
+        self.biomes.is_empty()
+    }
+
+    /// Returns the biome with the given name.
+    pub fn get(&self, name: &str) -> Option<&BiomeDefinition> {
+        self.by_name.get(name).map(|i| &self.biomes[*i])
+    }
+
+    /// Returns the biome with the given ID.
+    pub fn get_by_id(&self, id: u32) -> Option<&BiomeDefinition> {
+        self.by_id.get(&id).map(|i| &self.biomes[*i])
+    }
+
+    /// Whether a biome with the given name exists.
+    #[inline]
+    pub fn contains(&self, name: &str) -> bool {
+        self.by_name.contains_key(name)
+    }
+
+    /// Biome definitions in the format used by the `BiomeDefinitionList` packet.
+    #[inline]
+    pub const fn definitions(&self) -> &nbt::Value {
+        &self.definitions
+    }
+}
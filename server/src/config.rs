@@ -3,9 +3,26 @@ use std::time::Duration;
 use lazy_static::lazy_static;
 use parking_lot::RwLock;
 
+use level::{RuntimeIdMode, DEFAULT_FLAT_PRESET};
+
 use crate::network::packets::login::{
     ClientThrottleSettings, CompressionAlgorithm,
 };
+use crate::network::packets::{ConnectedPacket, MovePlayer, TickSync};
+
+/// Generator used for chunks that do not exist in the level.
+#[derive(Debug, Clone)]
+pub enum LevelGenerator {
+    /// Missing chunks are not generated.
+    None,
+    /// Superflat world, using a layer preset in the vanilla format.
+    /// See [`FlatGenerator::from_preset`](level::FlatGenerator::from_preset).
+    Flat(String),
+    /// Chunks only contain air.
+    Void,
+    /// Overworld terrain generated from the world seed.
+    Terrain,
+}
 
 /// Global service that contains all configuration settings
 pub struct ServerConfig {
@@ -20,6 +37,17 @@ pub struct ServerConfig {
     /// When a packet's size surpasses this threshold, it will be compressed.
     /// Set the threshold to 0 to disable compression.
     pub compression_threshold: u16,
+    /// Deflate compression level, from 0 (fastest) to 9 (smallest output).
+    pub compression_level: u32,
+    /// IDs of packets that do not have to be compressed.
+    /// Batches that only contain these packets are sent uncompressed,
+    /// which saves time on small packets that are sent often.
+    /// This requires [`compression_prefix`](Self::compression_prefix),
+    /// since older clients cannot tell whether a batch is compressed.
+    pub uncompressed_packets: Vec<u32>,
+    /// Whether batches are prefixed with the ID of the compression algorithm.
+    /// This is required by clients from 1.20.60 onwards and must be disabled for older clients.
+    pub compression_prefix: bool,
     /// Client throttling settings.
     pub client_throttle: ClientThrottleSettings,
     /// Name of the server.
@@ -28,11 +56,30 @@ pub struct ServerConfig {
     /// Maximum render distance that the server will accept.
     /// Clients requesting a higher value will be told to use this.
     pub allowed_render_distance: i32,
+    /// Whether clients should request sub chunks individually.
+    /// This only sends the sub chunks that are visible to the client instead of entire chunks.
+    pub sub_chunk_requests: bool,
+    /// How block runtime IDs are assigned.
+    /// Hashed runtime IDs are only supported by clients from 1.19.80 onwards.
+    pub block_runtime_ids: RuntimeIdMode,
     /// Interval between world autosaves.
     /// Set to 0 to disable autosaves.
     pub autosave_interval: Duration,
     /// Path to the world to host.
+    /// A new world is created if it does not exist.
+    /// The level.dat file is stored in the parent directory of this path.
     pub level_path: String,
+    /// Generator used for chunks that do not exist in the world.
+    /// Generated chunks are saved to the world.
+    pub generator: LevelGenerator,
+    /// Seed of newly created levels.
+    /// Existing levels use the seed stored in their `level.dat` file.
+    pub world_seed: u64,
+    /// Paths to behavior packs whose custom biomes should be registered.
+    pub behavior_packs: Vec<String>,
+    /// Biome that players spawn in.
+    /// Set to `None` to let the client use the default spawn biome.
+    pub spawn_biome: Option<String>,
 }
 
 lazy_static! {
@@ -43,6 +90,9 @@ lazy_static! {
         max_players: 1000,
         compression_algorithm: CompressionAlgorithm::Deflate,
         compression_threshold: 1, // Compress all packets
+        compression_level: 6,
+        uncompressed_packets: vec![MovePlayer::ID, TickSync::ID],
+        compression_prefix: false,
         client_throttle: ClientThrottleSettings { // Disable client throttling
             enabled: false,
             threshold: 0,
@@ -50,7 +100,13 @@ lazy_static! {
         },
         server_name: "Pathfinders",
         allowed_render_distance: 16,
+        sub_chunk_requests: true,
+        block_runtime_ids: RuntimeIdMode::Sequential,
         autosave_interval: Duration::from_secs(60),
-        level_path: String::from("level/test/db")
+        level_path: String::from("world/db"),
+        generator: LevelGenerator::Flat(DEFAULT_FLAT_PRESET.to_owned()),
+        world_seed: 69420,
+        behavior_packs: Vec::new(),
+        spawn_biome: None
     });
 }
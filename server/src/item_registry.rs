@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+
+use common::{error, VResult};
+use level::BlockRegistry;
+
+use crate::network::packets::login::{ItemEntry, ItemStack, ItemType};
+
+/// Runtime IDs and properties of every item.
+const ITEMS: &str = include_str!("../included/items.json");
+/// Items that are shown in the creative inventory.
+const CREATIVE_ITEMS: &str = include_str!("../included/creative_items.json");
+/// Name of the shield, which has an additional blocking tick.
+const SHIELD_ITEM: &str = "minecraft:shield";
+
+/// Entry in the item list.
+#[derive(serde::Deserialize, Debug)]
+struct ItemData {
+    /// Runtime ID of the item.
+    runtime_id: i16,
+    /// Whether this item uses components.
+    component_based: bool,
+}
+
+/// List of creative inventory items.
+#[derive(serde::Deserialize, Debug)]
+struct CreativeItems {
+    items: Vec<CreativeItem>,
+}
+
+/// Entry in the creative inventory.
+#[derive(serde::Deserialize, Debug)]
+struct CreativeItem {
+    /// Name of the item.
+    id: String,
+    /// Damage value or variant of the item.
+    #[serde(default)]
+    damage: u32,
+}
+
+/// Contains every item known by the server.
+#[derive(Debug)]
+pub struct ItemRegistry {
+    /// Item table sent to the client in the `StartGame` packet.
+    entries: Vec<ItemEntry>,
+    /// Maps item names to their index in the item table.
+    by_name: HashMap<String, usize>,
+    /// Maps runtime IDs to their index in the item table.
+    by_id: HashMap<i16, usize>,
+    /// Block runtime IDs of items that place a block.
+    blocks: HashMap<i16, u32>,
+    /// Contents of the creative inventory.
+    creative_items: Vec<ItemStack>,
+}
+
+impl ItemRegistry {
+    /// Loads the bundled item data.
+    ///
+    /// Items that have the same name as a block are linked to the default state of that block.
+    pub fn new(block_registry: &BlockRegistry) -> VResult<Self> {
+        let items: HashMap<String, ItemData> = serde_json::from_str(ITEMS)
+            .map_err(|e| error!(Other, "Failed to parse item list: {}", e))?;
+
+        let mut entries = items
+            .into_iter()
+            .map(|(name, data)| ItemEntry {
+                name,
+                runtime_id: data.runtime_id,
+                component_based: data.component_based,
+            })
+            .collect::<Vec<_>>();
+        entries.sort_by_key(|e| e.runtime_id);
+
+        let by_name = entries
+            .iter()
+            .enumerate()
+            .map(|(i, e)| (e.name.clone(), i))
+            .collect::<HashMap<_, _>>();
+        let by_id = entries
+            .iter()
+            .enumerate()
+            .map(|(i, e)| (e.runtime_id, i))
+            .collect::<HashMap<_, _>>();
+        let blocks = entries
+            .iter()
+            .filter_map(|e| {
+                block_registry
+                    .default_runtime_id(&e.name)
+                    .map(|block| (e.runtime_id, block))
+            })
+            .collect::<HashMap<_, _>>();
+
+        let creative: CreativeItems = serde_json::from_str(CREATIVE_ITEMS)
+            .map_err(|e| {
+                error!(Other, "Failed to parse creative items: {}", e)
+            })?;
+
+        let mut creative_items = Vec::with_capacity(creative.items.len());
+        for item in creative.items {
+            let Some(index) = by_name.get(&item.id) else {
+                tracing::warn!("Unknown creative item {}", item.id);
+                continue;
+            };
+
+            let runtime_id = entries[*index].runtime_id;
+            creative_items.push(ItemStack {
+                item_type: ItemType {
+                    network_id: runtime_id as i32,
+                    metadata: item.damage,
+                },
+                runtime_id: blocks.get(&runtime_id).copied().unwrap_or(0),
+                count: 1,
+                nbt_data: nbt::Value::Compound(HashMap::new()),
+                can_be_placed_on: Vec::new(),
+                can_break: Vec::new(),
+                has_network_id: false,
+                blocking_tick: (item.id == SHIELD_ITEM).then_some(0),
+            });
+        }
+
+        tracing::debug!(
+            "Loaded {} items, {} of which are in the creative inventory",
+            entries.len(),
+            creative_items.len()
+        );
+
+        Ok(Self {
+            entries,
+            by_name,
+            by_id,
+            blocks,
+            creative_items,
+        })
+    }
+
+    /// Item table, sorted by runtime ID.
+    #[inline]
+    pub fn entries(&self) -> &[ItemEntry] {
+        &self.entries
+    }
+
+    /// Contents of the creative inventory.
+    #[inline]
+    pub fn creative_items(&self) -> &[ItemStack] {
+        &self.creative_items
+    }
+
+    /// Returns the runtime ID of an item.
+    pub fn runtime_id(&self, name: &str) -> Option<i16> {
+        self.by_name.get(name).map(|i| self.entries[*i].runtime_id)
+    }
+
+    /// Returns the name of the item with the given runtime ID.
+    pub fn name(&self, runtime_id: i16) -> Option<&str> {
+        self.by_id
+            .get(&runtime_id)
+            .map(|i| self.entries[*i].name.as_str())
+    }
+
+    /// Returns the runtime ID of the block that the item places.
+    ///
+    /// Returns `None` if the item is not a block.
+    pub fn block_runtime_id(&self, runtime_id: i16) -> Option<u32> {
+        self.blocks.get(&runtime_id).copied()
+    }
+}
@@ -1,34 +1,90 @@
-use std::sync::atomic::AtomicU64;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use common::VResult;
+use bytes::Bytes;
+use common::{BlockPosition, VResult, Vector3i};
 use dashmap::mapref::one::Ref;
-use dashmap::DashMap;
-use level::ChunkManager;
+use dashmap::{DashMap, DashSet};
+use level::{
+    BlockEntity, BlockRegistry, Chunk, ChunkDatabase, ChunkManager, Dimension,
+    FlatGenerator, Generator, LevelData, TerrainGenerator, VoidGenerator,
+    SPAWN_Y_UNDEFINED,
+};
 use parking_lot::{RwLock, RwLockReadGuard};
 use tokio::sync::oneshot::Receiver;
 use tokio::task::JoinHandle;
 use tokio_util::sync::CancellationToken;
 
+use crate::biome_registry::BiomeRegistry;
 use crate::command::Command;
-use crate::config::SERVER_CONFIG;
+use crate::config::{LevelGenerator, SERVER_CONFIG};
+use crate::item_registry::ItemRegistry;
 use crate::network::{
-    packets::{GameRule, GameRulesChanged},
-    session::SessionManager,
+    packets::{
+        BlockActorData, GameRule, GameRulesChanged, BOOLEAN_GAME_RULES,
+        INTEGER_GAME_RULES,
+    },
+    session::{chunk_position, in_render_distance, SessionManager},
 };
 
 /// Interval between standard Minecraft ticks.
 const LEVEL_TICK_INTERVAL: Duration = Duration::from_millis(1000 / 20);
+/// Interval between unloading chunks that are not in range of any player.
+const CHUNK_UNLOAD_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Canonical block states of the supported game version.
+pub const BLOCK_STATES: &[u8] = include_bytes!("../included/block_states.nbt");
 
+/// Chunks and players of a single dimension.
 #[derive(Debug)]
-pub struct LevelManager {
-    /// Used to load world data from disk.
+pub struct DimensionManager {
+    /// Used to load the chunks of this dimension from disk.
     chunks: Arc<ChunkManager>,
+    /// Runtime IDs of the players in this dimension.
+    players: DashSet<u64>,
+}
+
+impl DimensionManager {
+    /// Returns the chunk manager of this dimension.
+    #[inline]
+    pub fn chunks(&self) -> &ChunkManager {
+        &self.chunks
+    }
+
+    /// Runtime IDs of the players in this dimension.
+    pub fn players(&self) -> Vec<u64> {
+        self.players.iter().map(|id| *id).collect()
+    }
+
+    /// Whether the player with the given runtime ID is in this dimension.
+    #[inline]
+    pub fn contains_player(&self, runtime_id: u64) -> bool {
+        self.players.contains(&runtime_id)
+    }
+}
+
+#[derive(Debug)]
+pub struct LevelManager {
+    /// Database shared by all dimensions.
+    database: Arc<ChunkDatabase>,
+    /// Chunks and players of every dimension, indexed by dimension ID.
+    dimensions: [DimensionManager; 3],
+    /// Block states and their runtime IDs.
+    blocks: BlockRegistry,
+    /// Items and their runtime IDs.
+    items: ItemRegistry,
+    /// Vanilla and custom biomes.
+    biomes: BiomeRegistry,
     /// List of commands available in this level.
     commands: DashMap<String, Command>,
     /// Currently set game rules.
     game_rules: DashMap<String, GameRule>,
+    /// General information about the level, stored in the `level.dat` file.
+    level_data: Arc<RwLock<LevelData>>,
+    /// Location of the `level.dat` file.
+    level_data_path: PathBuf,
     /// Used to broadcast level events to the sessions.
     session_manager: Arc<SessionManager>,
     /// Current world tick.
@@ -43,35 +99,394 @@ impl LevelManager {
         session_manager: Arc<SessionManager>,
         token: CancellationToken,
     ) -> VResult<(Arc<Self>, Receiver<()>)> {
-        let (world_path, autosave_interval) = {
+        let (
+            world_path,
+            autosave_interval,
+            runtime_id_mode,
+            behavior_packs,
+            generator,
+            world_seed,
+        ) = {
             let config = SERVER_CONFIG.read();
-            (config.level_path.clone(), config.autosave_interval)
+            (
+                config.level_path.clone(),
+                config.autosave_interval,
+                config.block_runtime_ids,
+                config.behavior_packs.clone(),
+                config.generator.clone(),
+                config.world_seed,
+            )
         };
 
-        let (chunks, chunk_notifier) =
-            ChunkManager::new(world_path, autosave_interval, token.clone())?;
+        let blocks = BlockRegistry::new(
+            Bytes::from_static(BLOCK_STATES),
+            runtime_id_mode,
+        )?;
+
+        let items = ItemRegistry::new(&blocks)?;
+
+        let mut biomes = BiomeRegistry::new()?;
+        for pack in &behavior_packs {
+            let count = biomes.load_behavior_pack(pack)?;
+            tracing::debug!("Loaded {count} custom biomes from {pack}");
+        }
+
+        // The level.dat file is stored next to the database directory.
+        let level_data_path = Path::new(&world_path)
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join("level.dat");
+
+        let level_data =
+            LevelData::read(&level_data_path)?.unwrap_or_else(|| {
+                tracing::info!(
+                    "{} does not exist, creating new level data",
+                    level_data_path.display()
+                );
+
+                let mut data = LevelData::new("Nova", world_seed as i64);
+                data.generator = match generator {
+                    LevelGenerator::Flat(_) | LevelGenerator::Void => 2,
+                    LevelGenerator::None | LevelGenerator::Terrain => 1,
+                };
+                data
+            });
+
+        let mut generator: Option<Box<dyn Generator>> = match generator {
+            LevelGenerator::None => None,
+            LevelGenerator::Flat(preset) => Some(Box::new(
+                FlatGenerator::from_preset(&preset, &blocks, |name| {
+                    // Vanilla biomes are registered without a namespace.
+                    let name = name.strip_prefix("minecraft:").unwrap_or(name);
+                    biomes.get(name).map(|b| b.id)
+                })?,
+            )),
+            LevelGenerator::Void => Some(Box::new(VoidGenerator)),
+            LevelGenerator::Terrain => Some(Box::new(TerrainGenerator::new(
+                level_data.seed as u64,
+                &blocks,
+            )?)),
+        };
+
+        tracing::info!("Loading level {world_path}...");
+        let database = Arc::new(ChunkDatabase::new(&world_path)?);
+
+        // Generation in the nether and the end is not supported yet.
+        // These dimensions are left empty if generation is enabled.
+        let generation_enabled = generator.is_some();
+        let mut receivers = Vec::with_capacity(3);
+        let dimensions =
+            [Dimension::Overworld, Dimension::Nether, Dimension::End].map(
+                |dimension| {
+                    let generator = if dimension == Dimension::Overworld {
+                        generator.take()
+                    } else if generation_enabled {
+                        Some(Box::new(VoidGenerator) as Box<dyn Generator>)
+                    } else {
+                        None
+                    };
+
+                    let (chunks, receiver) = ChunkManager::new(
+                        database.clone(),
+                        dimension,
+                        autosave_interval,
+                        generator,
+                        token.clone(),
+                    );
+                    receivers.push(receiver);
+
+                    DimensionManager { chunks, players: DashSet::new() }
+                },
+            );
+
+        let game_rules = DashMap::from_iter([
+            (
+                "showcoordinates".to_owned(),
+                GameRule::ShowCoordinates(false),
+            ),
+            (
+                "naturalregeneration".to_owned(),
+                GameRule::NaturalRegeneration(false),
+            ),
+        ]);
+
+        for name in BOOLEAN_GAME_RULES.iter().chain(INTEGER_GAME_RULES) {
+            let Some(value) = level_data.game_rule(name) else {
+                continue;
+            };
+
+            match GameRule::from_value(name, value) {
+                Ok(game_rule) => {
+                    game_rules.insert((*name).to_owned(), game_rule);
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        "Ignoring game rule {name} in level.dat: {e}"
+                    )
+                }
+            }
+        }
+
+        let tick = AtomicU64::new(level_data.current_tick as u64);
+        let level_data = Arc::new(RwLock::new(level_data));
+
+        // Notifies the instance manager once every dimension has been saved.
+        let (sender, chunk_notifier) = tokio::sync::oneshot::channel();
+        let closing_data = (level_data.clone(), level_data_path.clone());
+        tokio::spawn(async move {
+            for receiver in receivers {
+                let _ = receiver.await;
+            }
+
+            let (level_data, level_data_path) = closing_data;
+            if let Err(e) = save_level_data(&level_data, &level_data_path) {
+                tracing::error!("Failed to save level data: {e}");
+            }
+
+            tracing::info!("Closed level");
+            let _ = sender.send(());
+        });
 
         let manager = Arc::new(Self {
-            chunks,
+            database,
+            dimensions,
+            blocks,
+            items,
+            biomes,
             commands: DashMap::new(),
-            game_rules: DashMap::from_iter([
-                (
-                    "showcoordinates".to_owned(),
-                    GameRule::ShowCoordinates(false),
-                ),
-                (
-                    "naturalregeneration".to_owned(),
-                    GameRule::NaturalRegeneration(false),
-                ),
-            ]),
+            game_rules,
+            level_data,
+            level_data_path,
             session_manager,
-            tick: AtomicU64::new(0),
+            tick,
             token,
         });
 
+        let clone = manager.clone();
+        tokio::spawn(async move { clone.unload_job().await });
+        let clone = manager.clone();
+        tokio::spawn(async move { clone.tick_job().await });
+        let clone = manager.clone();
+        tokio::spawn(
+            async move { clone.autosave_job(autosave_interval).await },
+        );
+
         Ok((manager, chunk_notifier))
     }
 
+    /// Loads the chunk at the given position.
+    ///
+    /// Chunks that do not exist are generated using the configured generator.
+    /// Returns `None` if the chunk does not exist and generation is disabled.
+    #[inline]
+    pub fn get_chunk(
+        &self,
+        x: i32,
+        z: i32,
+        dimension: Dimension,
+    ) -> VResult<Option<Chunk>> {
+        self.dimension(dimension).chunks.get_chunk(x, z)
+    }
+
+    /// Writes the level data and all modified chunks to disk.
+    pub fn flush(&self) -> VResult<()> {
+        for dimension in &self.dimensions {
+            dimension.chunks.flush()?;
+        }
+
+        save_level_data(&self.level_data, &self.level_data_path)
+    }
+
+    /// Advances the level by a single tick.
+    ///
+    /// The time of day only advances while the daylight cycle is enabled.
+    pub fn tick(&self) {
+        self.tick.fetch_add(1, Ordering::SeqCst);
+
+        let daylight_cycle = !matches!(
+            self.get_game_rule("dodaylightcycle"),
+            Some(GameRule::DaylightCycle(false))
+        );
+
+        let mut level_data = self.level_data.write();
+        level_data.current_tick += 1;
+        if daylight_cycle {
+            level_data.time += 1;
+        }
+    }
+
+    /// Returns the current world tick.
+    #[inline]
+    pub fn current_tick(&self) -> u64 {
+        self.tick.load(Ordering::SeqCst)
+    }
+
+    /// Returns the default spawn position of the level.
+    ///
+    /// Vanilla worlds whose spawn has not been placed on the ground yet
+    /// spawn players on top of the highest block in the spawn column.
+    pub fn spawn_position(&self) -> VResult<Vector3i> {
+        let mut spawn = self.level_data().spawn.clone();
+        if spawn.y != SPAWN_Y_UNDEFINED {
+            return Ok(spawn);
+        }
+
+        let dimension = Dimension::Overworld;
+        let chunk = self.get_chunk(spawn.x >> 4, spawn.z >> 4, dimension)?;
+        let height = chunk.map_or(0, |chunk| {
+            chunk.heightmap()
+                [(((spawn.z & 0xf) << 4) | (spawn.x & 0xf)) as usize]
+        });
+        spawn.y = dimension.height_range().start + height as i32;
+
+        Ok(spawn)
+    }
+
+    /// Returns the database that the level is stored in.
+    #[inline]
+    pub fn database(&self) -> &ChunkDatabase {
+        &self.database
+    }
+
+    /// Returns the general information about the level.
+    #[inline]
+    pub fn level_data(&self) -> RwLockReadGuard<'_, LevelData> {
+        self.level_data.read()
+    }
+
+    /// Adds or replaces a block entity and sends it to the clients.
+    ///
+    /// The block entity is written to disk on the next flush.
+    pub fn set_block_entity(
+        &self,
+        entity: BlockEntity,
+        dimension: Dimension,
+    ) -> VResult<()> {
+        let update = BlockActorData {
+            position: BlockPosition::new(
+                entity.position.x,
+                entity.position.y as u32,
+                entity.position.z,
+            ),
+            nbt: entity.to_nbt(),
+        };
+
+        self.dimension(dimension).chunks.set_block_entity(entity)?;
+        self.session_manager.broadcast(update)
+    }
+
+    /// Returns the chunks and players of a dimension.
+    #[inline]
+    pub const fn dimension(&self, dimension: Dimension) -> &DimensionManager {
+        &self.dimensions[dimension as usize]
+    }
+
+    /// Moves a player into a dimension, removing it from its previous dimension.
+    pub fn set_player_dimension(&self, runtime_id: u64, dimension: Dimension) {
+        self.remove_player(runtime_id);
+        self.dimension(dimension).players.insert(runtime_id);
+    }
+
+    /// Removes a player from the dimension it is in.
+    pub fn remove_player(&self, runtime_id: u64) {
+        for dimension in &self.dimensions {
+            dimension.players.remove(&runtime_id);
+        }
+    }
+
+    /// Unloads the cached chunks that are not within the render distance of any player.
+    ///
+    /// Chunks that have not been saved yet stay loaded until the next autosave.
+    pub fn unload_chunks(&self) {
+        let mut ranges: [Vec<((i32, i32), i32)>; 3] = Default::default();
+        for session in self.session_manager.sessions() {
+            let player = session.player.read();
+            ranges[player.dimension as usize].push((
+                chunk_position(&player.position),
+                player.render_distance,
+            ));
+        }
+
+        for (dimension, ranges) in self.dimensions.iter().zip(&ranges) {
+            let unloaded = dimension.chunks().unload_chunks(|x, z| {
+                ranges.iter().any(|(center, radius)| {
+                    in_render_distance(*center, *radius, x, z)
+                })
+            });
+
+            if unloaded > 0 {
+                tracing::debug!(
+                    "Unloaded {unloaded} chunks in {:?}",
+                    dimension.chunks().dimension()
+                );
+            }
+        }
+    }
+
+    /// Ticks the level 20 times per second until the server shuts down.
+    async fn tick_job(&self) {
+        let mut interval = tokio::time::interval(LEVEL_TICK_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => (),
+                _ = self.token.cancelled() => break
+            };
+
+            self.tick();
+        }
+    }
+
+    /// Periodically [flushes](Self::flush) the level until the server shuts down.
+    ///
+    /// The level data is saved one last time once every dimension has been closed.
+    async fn autosave_job(&self, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+        // The first tick completes immediately, while the level has just been loaded.
+        interval.tick().await;
+
+        loop {
+            tokio::select! {
+                _ = interval.tick() => (),
+                _ = self.token.cancelled() => break
+            };
+
+            if let Err(e) = self.flush() {
+                tracing::error!("Failed to save level: {e}");
+            }
+        }
+    }
+
+    /// Periodically runs [`unload_chunks`](Self::unload_chunks) until the server shuts down.
+    async fn unload_job(&self) {
+        let mut interval = tokio::time::interval(CHUNK_UNLOAD_INTERVAL);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => (),
+                _ = self.token.cancelled() => break
+            };
+
+            self.unload_chunks();
+        }
+    }
+
+    /// Returns the block state registry.
+    #[inline]
+    pub const fn block_registry(&self) -> &BlockRegistry {
+        &self.blocks
+    }
+
+    /// Returns the item registry.
+    #[inline]
+    pub const fn item_registry(&self) -> &ItemRegistry {
+        &self.items
+    }
+
+    /// Returns the biome registry.
+    #[inline]
+    pub const fn biome_registry(&self) -> &BiomeRegistry {
+        &self.biomes
+    }
+
     /// Returns the requested command
     #[inline]
     pub fn get_command(&self, name: &str) -> Option<Ref<String, Command>> {
@@ -119,6 +534,9 @@ impl LevelManager {
 
         self.session_manager
             .broadcast(GameRulesChanged { game_rules: &[game_rule] });
+        self.level_data
+            .write()
+            .set_game_rule(name, game_rule.value());
         self.game_rules.insert(name.to_owned(), game_rule)
     }
 
@@ -126,12 +544,26 @@ impl LevelManager {
     /// This function also notifies all the clients of the change.
     #[inline]
     pub fn set_game_rules(&self, game_rules: &[GameRule]) {
-        for game_rule in game_rules {
-            let name = game_rule.name();
-            self.game_rules.insert(name.to_owned(), *game_rule);
+        {
+            let mut level_data = self.level_data.write();
+            for game_rule in game_rules {
+                let name = game_rule.name();
+                level_data.set_game_rule(name, game_rule.value());
+                self.game_rules.insert(name.to_owned(), *game_rule);
+            }
         }
 
         self.session_manager
             .broadcast(GameRulesChanged { game_rules });
     }
 }
+
+/// Updates the last played timestamp and writes the level data to disk.
+fn save_level_data(level_data: &RwLock<LevelData>, path: &Path) -> VResult<()> {
+    let mut level_data = level_data.write();
+    level_data.last_played = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64);
+
+    level_data.write(path)
+}
@@ -13,10 +13,12 @@ use tokio::runtime;
 use crate::instance_manager::InstanceManager;
 use common::VResult;
 
+mod biome_registry;
 mod command;
 mod config;
 mod crypto;
 mod instance_manager;
+mod item_registry;
 mod level_manager;
 mod network;
 
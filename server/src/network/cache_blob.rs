@@ -1,4 +1,12 @@
 use bytes::{BufMut, Bytes, BytesMut};
+use common::{size_of_varint, WriteExtensions};
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use xxhash_rust::xxh64::xxh64;
+
+/// Maximum amount of blobs that can be waiting for confirmation by a single client.
+/// Chunks are sent without the cache while this limit is reached.
+pub const MAX_PENDING_BLOBS: usize = 4096;
 
 /// A blob used in the cache protocol.
 #[derive(Debug, Clone)]
@@ -10,13 +18,64 @@ pub struct CacheBlob {
 }
 
 impl CacheBlob {
+    /// Creates a new blob, hashing the payload.
+    pub fn new(payload: Bytes) -> Self {
+        Self { hash: xxh64(&payload, 0), payload }
+    }
+
     pub fn serialize(&self, buffer: &mut BytesMut) {
         buffer.put_u64_le(self.hash);
+        buffer.put_var_u32(self.payload.len() as u32);
         buffer.extend(&self.payload);
     }
 
     #[inline]
-    pub const fn len(&self) -> usize {
-        std::mem::size_of::<u64>() + self.payload.len()
+    pub fn len(&self) -> usize {
+        std::mem::size_of::<u64>()
+            + size_of_varint(self.payload.len() as u32)
+            + self.payload.len()
+    }
+}
+
+/// Blobs that have been announced to a client, but have not been confirmed yet.
+///
+/// Chunks often contain identical sub chunks, so the same blob can be announced several times.
+/// Every announcement is a separate reference that is resolved by a hit or a miss,
+/// the blob is only removed once all of its references have been resolved.
+#[derive(Debug, Default)]
+pub struct PendingBlobs {
+    /// Payload and reference count of each blob, indexed by hash.
+    blobs: DashMap<u64, (Bytes, usize)>,
+}
+
+impl PendingBlobs {
+    /// Adds a reference to the blob and returns its hash.
+    pub fn insert(&self, blob: CacheBlob) -> u64 {
+        self.blobs.entry(blob.hash).or_insert((blob.payload, 0)).1 += 1;
+        blob.hash
+    }
+
+    /// Resolves a single reference to the blob with the given hash.
+    ///
+    /// Returns the payload of the blob, or `None` if it is not pending.
+    pub fn resolve(&self, hash: u64) -> Option<Bytes> {
+        let Entry::Occupied(mut entry) = self.blobs.entry(hash) else {
+            return None;
+        };
+
+        let (payload, references) = entry.get_mut();
+        let payload = payload.clone();
+        *references -= 1;
+        if *references == 0 {
+            entry.remove();
+        }
+
+        Some(payload)
+    }
+
+    /// Whether the client has [too many](MAX_PENDING_BLOBS) unconfirmed blobs.
+    #[inline]
+    pub fn is_full(&self) -> bool {
+        self.blobs.len() >= MAX_PENDING_BLOBS
     }
 }
@@ -4,7 +4,6 @@ pub mod packets;
 pub mod raknet;
 pub mod session;
 
-mod cache_blob;
-
+glob_export!(cache_blob);
 glob_export!(header);
 glob_export!(skin);
@@ -1,28 +1,26 @@
-use bytes::BufMut;
-use bytes::Bytes;
 use bytes::BytesMut;
-use common::VResult;
 
 use common::Serialize;
 
 use super::ConnectedPacket;
 
-const DEFINITIONS: &[u8] = include_bytes!("../../../included/biomes.nbt");
-
 /// Sends a list of available biomes to the client.
 #[derive(Debug, Clone)]
-pub struct BiomeDefinitionList;
+pub struct BiomeDefinitionList<'a> {
+    /// Compound containing the definition of every biome, indexed by biome name.
+    pub definitions: &'a nbt::Value,
+}
 
-impl ConnectedPacket for BiomeDefinitionList {
+impl ConnectedPacket for BiomeDefinitionList<'_> {
     const ID: u32 = 0x7a;
 
     fn serialized_size(&self) -> usize {
-        DEFINITIONS.len()
+        self.definitions.serialized_net_size("")
     }
 }
 
-impl Serialize for BiomeDefinitionList {
+impl Serialize for BiomeDefinitionList<'_> {
     fn serialize(&self, buffer: &mut BytesMut) {
-        buffer.put(DEFINITIONS);
+        nbt::serialize_net("", self.definitions, buffer);
     }
 }
@@ -0,0 +1,47 @@
+use bytes::{Bytes, BytesMut};
+use common::{
+    size_of_varint, BlockPosition, Deserialize, ReadExtensions, Serialize,
+    VResult, WriteExtensions,
+};
+
+use super::ConnectedPacket;
+
+/// Updates the data of a block entity, such as the text of a sign.
+///
+/// This is sent by the server when a block entity changes
+/// and by the client when the player edits a sign.
+#[derive(Debug, Clone)]
+pub struct BlockActorData {
+    /// Position of the block entity.
+    /// Negative Y coordinates are sent as their two's complement.
+    pub position: BlockPosition,
+    /// Network NBT data of the block entity, including its ID and position.
+    pub nbt: nbt::Value,
+}
+
+impl ConnectedPacket for BlockActorData {
+    const ID: u32 = 0x38;
+
+    fn serialized_size(&self) -> usize {
+        size_of_varint(self.position.x)
+            + size_of_varint(self.position.y)
+            + size_of_varint(self.position.z)
+            + self.nbt.serialized_net_size("")
+    }
+}
+
+impl Serialize for BlockActorData {
+    fn serialize(&self, buffer: &mut BytesMut) {
+        buffer.put_block_pos(&self.position);
+        nbt::serialize_net("", &self.nbt, buffer);
+    }
+}
+
+impl Deserialize for BlockActorData {
+    fn deserialize(mut buffer: Bytes) -> VResult<Self> {
+        let position = buffer.get_block_pos()?;
+        let nbt = nbt::deserialize_net(&mut buffer)?.value;
+
+        Ok(Self { position, nbt })
+    }
+}
@@ -1,5 +1,5 @@
 use bytes::{BufMut, BytesMut, Bytes};
-use common::{VResult, Vector2i, WriteExtensions};
+use common::{size_of_varint, VResult, Vector2i, WriteExtensions};
 
 use common::Serialize;
 
@@ -35,12 +35,25 @@ pub struct LevelChunk {
 
 impl ConnectedPacket for LevelChunk {
     const ID: u32 = 0x3a;
+
+    fn serialized_size(&self) -> usize {
+        size_of_varint(self.position.x)
+            + size_of_varint(self.position.y)
+            + 5
+            + 2
+            + 1
+            + self
+                .blob_hashes
+                .as_ref()
+                .map(|h| size_of_varint(h.len() as u32) + h.len() * 8)
+                .unwrap_or(0)
+            + size_of_varint(self.raw_payload.len() as u32)
+            + self.raw_payload.len()
+    }
 }
 
 impl Serialize for LevelChunk {
     fn serialize(&self, buffer: &mut BytesMut) {
-        let mut buffer = BytesMut::new();
-
         buffer.put_vec2i(&self.position);
         match self.request_mode {
             SubChunkRequestMode::Legacy => {
@@ -51,7 +64,7 @@ impl Serialize for LevelChunk {
             }
             SubChunkRequestMode::Limited => {
                 buffer.put_var_u32(u32::MAX - 1);
-                buffer.put_u16(self.highest_sub_chunk);
+                buffer.put_u16_le(self.highest_sub_chunk);
             }
         }
 
@@ -59,10 +72,11 @@ impl Serialize for LevelChunk {
         if let Some(hashes) = &self.blob_hashes {
             buffer.put_var_u32(hashes.len() as u32);
             for hash in hashes {
-                buffer.put_u64(*hash);
+                buffer.put_u64_le(*hash);
             }
         }
 
+        buffer.put_var_u32(self.raw_payload.len() as u32);
         buffer.put(self.raw_payload.as_ref());
     }
 }
@@ -1,5 +1,3 @@
-use std::collections::HashMap;
-
 use bytes::Bytes;
 use bytes::{BufMut, BytesMut};
 use nbt::Value;
@@ -9,13 +7,11 @@ use common::{Serialize, VarInt};
 use common::VResult;
 use common::WriteExtensions;
 
-pub const ITEM_ID_SHIELD: u32 = 513;
-
 /// Represents a combination of a network ID and metadata value.
 #[derive(Debug, Clone)]
 pub struct ItemType {
     /// Numerical ID of the item.
-    pub network_id: u32,
+    pub network_id: i32,
     /// Damage value or variant of the item.
     pub metadata: u32,
 }
@@ -24,77 +20,103 @@ pub struct ItemType {
 #[derive(Debug, Clone)]
 pub struct ItemStack {
     pub item_type: ItemType,
+    /// Runtime ID of the block that this item places.
+    /// This is 0 if the item is not a block.
     pub runtime_id: u32,
     pub count: u16,
     pub nbt_data: nbt::Value,
     pub can_be_placed_on: Vec<String>,
     pub can_break: Vec<String>,
+    /// Whether the stack network ID flag is included.
+    /// Items in the creative inventory do not have this flag.
     pub has_network_id: bool,
+    /// Tick at which a shield started blocking.
+    /// This is only sent for shields and is `None` for every other item.
+    pub blocking_tick: Option<i64>,
 }
 
 impl ItemStack {
     pub fn serialized_size(&self) -> usize {
-        self.item_type.network_id.var_len() +
-        if self.item_type.network_id != 0 {
-            2 +
-            self.item_type.metadata.var_len() +
-            self.runtime_id.var_len() +
-            2 +
-            if let Value::Compound(ref map) = self.nbt_data {
-                if !map.is_empty() {
-                    1 + self.nbt_data.serialized_net_size("")
-                } else {
-                    0
-                }
+        self.item_type.network_id.var_len()
+            + if self.item_type.network_id != 0 {
+                let extra_size = self.extra_data_size();
+
+                2 + self.item_type.metadata.var_len()
+                    + self.has_network_id as usize
+                    + (self.runtime_id as i32).var_len()
+                    + (extra_size as u32).var_len()
+                    + extra_size
             } else {
                 0
             }
-        } else {
-            0
-        }
     }
 
     pub fn serialize(&self, buffer: &mut BytesMut) {
-        buffer.put_var_u32(self.item_type.network_id);
+        buffer.put_var_i32(self.item_type.network_id);
         if self.item_type.network_id == 0 {
             // Air has no data.
             return;
         }
 
-        buffer.put_u16(self.count);
+        buffer.put_u16_le(self.count);
         buffer.put_var_u32(self.item_type.metadata);
-        buffer.put_var_u32(self.runtime_id);
+        if self.has_network_id {
+            // Stack network IDs are not used yet.
+            buffer.put_bool(false);
+        }
+        buffer.put_var_i32(self.runtime_id as i32);
 
-        if let Value::Compound(ref map) = self.nbt_data {
-            let length = map.len();
-            if length == 0 {
-                buffer.put_i16(0); // Length
-            } else {
-                buffer.put_i16(-1); // Length
-                buffer.put_u8(1); // Version
+        // The remaining data is prefixed with its size and uses little endian encoding.
+        let mut extra = BytesMut::with_capacity(self.extra_data_size());
+        match &self.nbt_data {
+            Value::Compound(map) if !map.is_empty() => {
+                extra.put_i16_le(-1); // Length
+                extra.put_u8(1); // Version
 
-                nbt::serialize_net("", &self.nbt_data, buffer);
+                nbt::serialize_le("", &self.nbt_data, &mut extra);
             }
-        } else {
-            todo!()
+            _ => extra.put_i16_le(0), // Length
         }
 
-        buffer.put_u32(self.can_be_placed_on.len() as u32);
-        for item in &self.can_be_placed_on {
-            buffer.put_string(item);
+        extra.put_i32_le(self.can_be_placed_on.len() as i32);
+        for block in &self.can_be_placed_on {
+            extra.put_i16_le(block.len() as i16);
+            extra.put(block.as_bytes());
         }
 
-        buffer.put_u32(self.can_break.len() as u32);
-        for item in &self.can_break {
-            buffer.put_string(item);
+        extra.put_i32_le(self.can_break.len() as i32);
+        for block in &self.can_break {
+            extra.put_i16_le(block.len() as i16);
+            extra.put(block.as_bytes());
         }
 
-        if self.item_type.network_id == ITEM_ID_SHIELD {
-            buffer.put_u64(0); // Blocking tick.
+        if let Some(blocking_tick) = self.blocking_tick {
+            extra.put_i64_le(blocking_tick);
         }
+
+        buffer.put_var_u32(extra.len() as u32);
+        buffer.put(extra);
+    }
+
+    /// Size of the size-prefixed data at the end of the item.
+    fn extra_data_size(&self) -> usize {
+        let nbt_size = match &self.nbt_data {
+            Value::Compound(map) if !map.is_empty() => {
+                3 + self.nbt_data.serialized_le_size("")
+            }
+            _ => 2,
+        };
+
+        nbt_size
+            + 4
+            + self.can_be_placed_on.iter().fold(0, |acc, b| acc + 2 + b.len())
+            + 4
+            + self.can_break.iter().fold(0, |acc, b| acc + 2 + b.len())
+            + if self.blocking_tick.is_some() { 8 } else { 0 }
     }
 }
 
+/// Sends the items in the creative inventory.
 #[derive(Debug, Clone)]
 pub struct CreativeContent<'a> {
     pub items: &'a [ItemStack],
@@ -105,14 +127,18 @@ impl ConnectedPacket for CreativeContent<'_> {
 
     fn serialized_size(&self) -> usize {
         (self.items.len() as u32).var_len() +
-        self.items.iter().fold(0, |acc, s| acc + s.serialized_size())
+        self.items.iter().enumerate().fold(0, |acc, (i, s)| {
+            acc + (i as u32 + 1).var_len() + s.serialized_size()
+        })
     }
 }
 
 impl Serialize for CreativeContent<'_> {
     fn serialize(&self, buffer: &mut BytesMut) {
         buffer.put_var_u32(self.items.len() as u32);
-        for item in self.items {
+        for (i, item) in self.items.iter().enumerate() {
+            // Creative network IDs start at 1.
+            buffer.put_var_u32(i as u32 + 1);
             item.serialize(buffer);
         }
     }
@@ -1,10 +1,15 @@
+use std::io::{Read, Write};
+
 use bytes::Bytes;
 use bytes::{BufMut, BytesMut};
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
 
 use crate::network::packets::ConnectedPacket;
 use common::Serialize;
-use common::VResult;
 use common::WriteExtensions;
+use common::{bail, VResult};
 
 /// Supported compression algorithms.
 ///
@@ -20,6 +25,68 @@ pub enum CompressionAlgorithm {
     Snappy,
 }
 
+/// Compression prefix of batches that are not compressed.
+pub const NO_COMPRESSION_PREFIX: u8 = 0xff;
+
+impl CompressionAlgorithm {
+    /// ID that compressed batches are prefixed with.
+    ///
+    /// Clients from 1.20.60 onwards prefix every batch with the algorithm that was used to compress it,
+    /// or [`NO_COMPRESSION_PREFIX`] if the batch is not compressed.
+    #[inline]
+    pub const fn prefix(self) -> u8 {
+        self as u8
+    }
+
+    /// Returns the algorithm that a batch was compressed with, based on its prefix.
+    ///
+    /// Returns `None` if the batch is not compressed.
+    pub fn from_prefix(prefix: u8) -> VResult<Option<Self>> {
+        Ok(match prefix {
+            0 => Some(Self::Deflate),
+            1 => Some(Self::Snappy),
+            NO_COMPRESSION_PREFIX => None,
+            _ => bail!(BadPacket, "Invalid compression prefix {prefix:#04x}"),
+        })
+    }
+
+    /// Compresses a game packet using this algorithm.
+    ///
+    /// The level (0-9) is only used by Deflate, Snappy does not have compression levels.
+    pub fn compress(self, data: &[u8], level: u32) -> VResult<Bytes> {
+        Ok(match self {
+            Self::Deflate => {
+                let mut writer = DeflateEncoder::new(
+                    Vec::new(),
+                    Compression::new(level.min(9)),
+                );
+
+                writer.write_all(data)?;
+                Bytes::from(writer.finish()?)
+            }
+            // Bedrock uses the raw Snappy format, without any framing.
+            Self::Snappy => {
+                Bytes::from(snap::raw::Encoder::new().compress_vec(data)?)
+            }
+        })
+    }
+
+    /// Decompresses a game packet that was compressed using this algorithm.
+    pub fn decompress(self, data: &[u8]) -> VResult<Bytes> {
+        Ok(match self {
+            Self::Deflate => {
+                let mut decompressed = Vec::new();
+                DeflateDecoder::new(data).read_to_end(&mut decompressed)?;
+
+                Bytes::from(decompressed)
+            }
+            Self::Snappy => {
+                Bytes::from(snap::raw::Decoder::new().decompress_vec(data)?)
+            }
+        })
+    }
+}
+
 /// Settings for client throttling.
 ///
 /// If client throttling is enabled, the client will tick fewer players,
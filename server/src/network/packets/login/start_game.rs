@@ -36,6 +36,20 @@ pub enum PermissionLevel {
     Custom,
 }
 
+impl TryFrom<i32> for PermissionLevel {
+    type Error = VError;
+
+    fn try_from(value: i32) -> VResult<Self> {
+        Ok(match value {
+            0 => Self::Visitor,
+            1 => Self::Member,
+            2 => Self::Operator,
+            3 => Self::Custom,
+            _ => bail!(Other, "Invalid permission level {value}"),
+        })
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct EducationResourceURI {
     pub button_name: String,
@@ -118,7 +132,7 @@ pub struct ItemEntry {
     pub name: String,
     /// Runtime ID of the item.
     /// This ID is what Minecraft uses to refer to the item.
-    pub runtime_id: u16,
+    pub runtime_id: i16,
     /// Whether this is a custom item.
     pub component_based: bool,
 }
@@ -130,7 +144,7 @@ impl ItemEntry {
 
     pub fn serialize(&self, buffer: &mut BytesMut) {
         buffer.put_string(&self.name);
-        buffer.put_u16(self.runtime_id);
+        buffer.put_i16_le(self.runtime_id);
         buffer.put_bool(self.component_based);
     }
 }
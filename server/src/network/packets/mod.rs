@@ -9,6 +9,7 @@ glob_export!(add_painting);
 glob_export!(animate);
 glob_export!(available_actor_identifiers);
 glob_export!(biome_definition_list);
+glob_export!(block_actor_data);
 glob_export!(block_event);
 glob_export!(block_pick_request);
 glob_export!(book_edit);
@@ -29,6 +30,7 @@ glob_export!(move_player);
 glob_export!(network_chunk_publisher_update);
 glob_export!(packet);
 glob_export!(play_sound);
+glob_export!(player_action);
 glob_export!(player_list);
 glob_export!(request_ability);
 glob_export!(respawn);
@@ -38,6 +40,8 @@ glob_export!(show_credits);
 glob_export!(show_profile);
 glob_export!(simple_event);
 glob_export!(spawn_experience_orb);
+glob_export!(sub_chunk);
+glob_export!(sub_chunk_request);
 glob_export!(text);
 glob_export!(tick_sync);
 glob_export!(toast_request);
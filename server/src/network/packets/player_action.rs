@@ -0,0 +1,119 @@
+use bytes::Bytes;
+use common::{bail, BlockPosition, ReadExtensions, VError, VResult};
+
+use common::Deserialize;
+
+use super::ConnectedPacket;
+
+/// All types of player actions.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum PlayerActionType {
+    StartBreak,
+    AbortBreak,
+    StopBreak,
+    GetUpdatedBlock,
+    DropItem,
+    StartSleeping,
+    StopSleeping,
+    Respawn,
+    Jump,
+    StartSprint,
+    StopSprint,
+    StartSneak,
+    StopSneak,
+    CreativePlayerDestroyBlock,
+    /// The client has finished loading the dimension it was transferred to.
+    DimensionChangeDone,
+    StartGlide,
+    StopGlide,
+    BuildDenied,
+    CrackBreak,
+    ChangeSkin,
+    SetEnchantmentSeed,
+    StartSwimming,
+    StopSwimming,
+    StartSpinAttack,
+    StopSpinAttack,
+    StartBuildingBlock,
+    PredictDestroyBlock,
+    ContinueDestroyBlock,
+    StartItemUseOn,
+    StopItemUseOn,
+}
+
+impl TryFrom<i32> for PlayerActionType {
+    type Error = VError;
+
+    fn try_from(value: i32) -> VResult<Self> {
+        Ok(match value {
+            0 => Self::StartBreak,
+            1 => Self::AbortBreak,
+            2 => Self::StopBreak,
+            3 => Self::GetUpdatedBlock,
+            4 => Self::DropItem,
+            5 => Self::StartSleeping,
+            6 => Self::StopSleeping,
+            7 => Self::Respawn,
+            8 => Self::Jump,
+            9 => Self::StartSprint,
+            10 => Self::StopSprint,
+            11 => Self::StartSneak,
+            12 => Self::StopSneak,
+            13 => Self::CreativePlayerDestroyBlock,
+            14 => Self::DimensionChangeDone,
+            15 => Self::StartGlide,
+            16 => Self::StopGlide,
+            17 => Self::BuildDenied,
+            18 => Self::CrackBreak,
+            19 => Self::ChangeSkin,
+            20 => Self::SetEnchantmentSeed,
+            21 => Self::StartSwimming,
+            22 => Self::StopSwimming,
+            23 => Self::StartSpinAttack,
+            24 => Self::StopSpinAttack,
+            25 => Self::StartBuildingBlock,
+            26 => Self::PredictDestroyBlock,
+            27 => Self::ContinueDestroyBlock,
+            28 => Self::StartItemUseOn,
+            29 => Self::StopItemUseOn,
+            _ => bail!(BadPacket, "Invalid player action type {value}"),
+        })
+    }
+}
+
+/// Sent by the client when the player performs an action.
+#[derive(Debug, Clone)]
+pub struct PlayerAction {
+    /// Runtime ID of the player.
+    pub runtime_id: u64,
+    /// Type of action that was performed.
+    pub action: PlayerActionType,
+    /// Position of the block that the action was performed on, if any.
+    pub position: BlockPosition,
+    /// Position of the block that resulted from the action, if any.
+    pub result_position: BlockPosition,
+    /// Face of the block that the action was performed on.
+    pub face: i32,
+}
+
+impl ConnectedPacket for PlayerAction {
+    const ID: u32 = 0x24;
+}
+
+impl Deserialize for PlayerAction {
+    fn deserialize(mut buffer: Bytes) -> VResult<Self> {
+        let runtime_id = buffer.get_var_u64()?;
+        let action = PlayerActionType::try_from(buffer.get_var_i32()?)?;
+        let position = buffer.get_block_pos()?;
+        let result_position = buffer.get_block_pos()?;
+        let face = buffer.get_var_i32()?;
+
+        Ok(Self {
+            runtime_id,
+            action,
+            position,
+            result_position,
+            face,
+        })
+    }
+}
@@ -3,6 +3,8 @@ use std::{any::TypeId, fmt};
 use bytes::{BytesMut, Bytes};
 use common::{Serialize, VResult, WriteExtensions, size_of_varint, bail, VarString, VarInt};
 
+use level::GameRuleValue;
+
 use crate::{command::ParsedArgument, network::packets::ConnectedPacket};
 
 pub const BOOLEAN_GAME_RULES: &[&str] = &[
@@ -246,6 +248,52 @@ impl GameRule {
         }
     }
 
+    /// Converts a game rule stored in the level data.
+    pub fn from_value(name: &str, value: GameRuleValue) -> VResult<Self> {
+        let value = match value {
+            GameRuleValue::Bool(b) => ParsedArgument::String(b.to_string()),
+            GameRuleValue::Int(i) => ParsedArgument::Int(i)
+        };
+
+        Self::from_parsed(name, &value)
+    }
+
+    /// Returns the value of the game rule, as stored in the level data.
+    pub const fn value(&self) -> GameRuleValue {
+        match self {
+            Self::CommandBlocksEnabled(b)
+            | Self::CommandBlockOutput(b)
+            | Self::DaylightCycle(b)
+            | Self::EntityDrops(b)
+            | Self::FireTick(b)
+            | Self::Insomnia(b)
+            | Self::ImmediateRespawn(b)
+            | Self::MobLoot(b)
+            | Self::MobSpawning(b)
+            | Self::TileDrops(b)
+            | Self::WeatherCycle(b)
+            | Self::DrowningDamage(b)
+            | Self::FallDamage(b)
+            | Self::FireDamage(b)
+            | Self::FreezeDamage(b)
+            | Self::KeepInventory(b)
+            | Self::MobGriefing(b)
+            | Self::NaturalRegeneration(b)
+            | Self::Pvp(b)
+            | Self::RespawnBlocksExplode(b)
+            | Self::SendCommandFeedback(b)
+            | Self::ShowBorderEffect(b)
+            | Self::ShowCoordinates(b)
+            | Self::ShowDeathMessages(b)
+            | Self::ShowTags(b)
+            | Self::TntExplodes(b) => GameRuleValue::Bool(*b),
+            Self::FunctionCommandLimit(i)
+            | Self::MaxCommandChainLength(i)
+            | Self::RandomTickSpeed(i)
+            | Self::SpawnRadius(i) => GameRuleValue::Int(*i)
+        }
+    }
+
     pub fn serialize(&self, buffer: &mut BytesMut) {
         buffer.put_string(self.name());
         buffer.put_bool(true); // Player can modify. Doesn't seem to do anything.
@@ -0,0 +1,151 @@
+use bytes::{BufMut, Bytes, BytesMut};
+use common::{size_of_varint, Serialize, Vector3i, WriteExtensions};
+
+use super::ConnectedPacket;
+
+/// Result of a single sub chunk request.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SubChunkResult {
+    /// The sub chunk was found and is included in the response.
+    Success = 1,
+    /// The chunk the sub chunk is in does not exist.
+    ChunkNotFound,
+    /// The requested dimension does not exist.
+    InvalidDimension,
+    /// The player that requested the sub chunk could not be found.
+    PlayerNotFound,
+    /// The sub chunk is outside of the height range of the dimension.
+    IndexOutOfBounds,
+    /// The sub chunk only contains air.
+    /// No payload is sent in this case when the blob cache is enabled.
+    SuccessAllAir,
+}
+
+/// Heightmap of a single sub chunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubChunkHeightmap {
+    /// No heightmap is available.
+    None,
+    /// Height of the highest block in each column, relative to the bottom of the sub chunk.
+    /// Columns where the highest block is above the sub chunk are set to 16,
+    /// and columns where it is below the sub chunk are set to -1.
+    ///
+    /// The heightmap is stored in ZX order.
+    Data(Box<[i8; 256]>),
+    /// The highest block of every column is above this sub chunk.
+    TooHigh,
+    /// The highest block of every column is below this sub chunk.
+    TooLow,
+}
+
+impl SubChunkHeightmap {
+    /// Size of the heightmap in bytes, including the type.
+    const fn serialized_size(&self) -> usize {
+        match self {
+            Self::Data(_) => 1 + 256,
+            _ => 1,
+        }
+    }
+}
+
+/// A single sub chunk in a [`SubChunk`] response.
+#[derive(Debug, Clone)]
+pub struct SubChunkEntry {
+    /// Position of the sub chunk, relative to the base position of the request.
+    pub offset: [i8; 3],
+    /// Whether the sub chunk could be sent.
+    pub result: SubChunkResult,
+    /// Network encoded sub chunk, followed by the block entities.
+    /// If the blob cache is enabled, this only contains the block entities.
+    pub payload: Bytes,
+    /// Heightmap of the sub chunk.
+    pub heightmap: SubChunkHeightmap,
+    /// Hash of the blob containing the sub chunk.
+    /// This is only used if the blob cache is enabled.
+    pub blob_hash: u64,
+}
+
+/// Response to a [`SubChunkRequest`](super::SubChunkRequest).
+#[derive(Debug, Clone)]
+pub struct SubChunk {
+    /// Whether the sub chunks are sent as cache blobs.
+    pub cache_enabled: bool,
+    /// Dimension the sub chunks are in.
+    pub dimension: i32,
+    /// Base position of the request, in sub chunk coordinates.
+    pub position: Vector3i,
+    /// Requested sub chunks.
+    pub entries: Vec<SubChunkEntry>,
+}
+
+impl SubChunk {
+    /// Whether the payload of the entry is sent.
+    #[inline]
+    fn has_payload(&self, entry: &SubChunkEntry) -> bool {
+        !self.cache_enabled || entry.result != SubChunkResult::SuccessAllAir
+    }
+}
+
+impl ConnectedPacket for SubChunk {
+    const ID: u32 = 0xae;
+
+    fn serialized_size(&self) -> usize {
+        1 + size_of_varint(self.dimension)
+            + size_of_varint(self.position.x)
+            + size_of_varint(self.position.y)
+            + size_of_varint(self.position.z)
+            + 4
+            + self
+                .entries
+                .iter()
+                .map(|entry| {
+                    3 + 1
+                        + if self.has_payload(entry) {
+                            size_of_varint(entry.payload.len() as u32)
+                                + entry.payload.len()
+                        } else {
+                            0
+                        }
+                        + entry.heightmap.serialized_size()
+                        + if self.cache_enabled { 8 } else { 0 }
+                })
+                .sum::<usize>()
+    }
+}
+
+impl Serialize for SubChunk {
+    fn serialize(&self, buffer: &mut BytesMut) {
+        buffer.put_bool(self.cache_enabled);
+        buffer.put_var_i32(self.dimension);
+        buffer.put_vec3i(&self.position);
+
+        buffer.put_u32_le(self.entries.len() as u32);
+        for entry in &self.entries {
+            for offset in entry.offset {
+                buffer.put_i8(offset);
+            }
+            buffer.put_u8(entry.result as u8);
+
+            if self.has_payload(entry) {
+                buffer.put_var_u32(entry.payload.len() as u32);
+                buffer.put(entry.payload.as_ref());
+            }
+
+            match &entry.heightmap {
+                SubChunkHeightmap::None => buffer.put_u8(0),
+                SubChunkHeightmap::Data(data) => {
+                    buffer.put_u8(1);
+                    for height in data.iter() {
+                        buffer.put_i8(*height);
+                    }
+                }
+                SubChunkHeightmap::TooHigh => buffer.put_u8(2),
+                SubChunkHeightmap::TooLow => buffer.put_u8(3),
+            }
+
+            if self.cache_enabled {
+                buffer.put_u64_le(entry.blob_hash);
+            }
+        }
+    }
+}
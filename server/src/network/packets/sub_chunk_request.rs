@@ -0,0 +1,58 @@
+use bytes::{Buf, Bytes};
+use common::{bail, error, Deserialize, ReadExtensions, VResult, Vector3i};
+
+use crate::network::packets::ConnectedPacket;
+
+/// Sent by the client to request the sub chunks of a chunk that was sent
+/// using the limited or limitless request mode.
+#[derive(Debug, Clone)]
+pub struct SubChunkRequest {
+    /// Dimension the sub chunks are in.
+    /// This is not validated, so that an invalid dimension can be reported back to the client.
+    pub dimension: i32,
+    /// Base position of the request, in sub chunk coordinates.
+    pub position: Vector3i,
+    /// Positions of the requested sub chunks, relative to the base position.
+    pub offsets: Vec<[i8; 3]>,
+}
+
+impl ConnectedPacket for SubChunkRequest {
+    const ID: u32 = 0xaf;
+}
+
+impl Deserialize for SubChunkRequest {
+    fn deserialize(mut buffer: Bytes) -> VResult<Self> {
+        let dimension = buffer.get_var_i32()?;
+        let x = buffer.get_var_i32()?;
+        let y = buffer.get_var_i32()?;
+        let z = buffer.get_var_i32()?;
+
+        if buffer.remaining() < 4 {
+            bail!(BadPacket, "Sub chunk request is missing its offset count");
+        }
+
+        let count = buffer.get_u32_le() as usize;
+        let size = count.checked_mul(3).ok_or_else(|| {
+            error!(BadPacket, "Sub chunk request contains too many offsets")
+        })?;
+
+        if buffer.remaining() < size {
+            bail!(
+                BadPacket,
+                "Sub chunk request contains {count} offsets, but only {} bytes remain",
+                buffer.remaining()
+            );
+        }
+
+        let mut offsets = Vec::with_capacity(count);
+        for _ in 0..count {
+            offsets.push([buffer.get_i8(), buffer.get_i8(), buffer.get_i8()]);
+        }
+
+        Ok(Self {
+            dimension,
+            position: Vector3i::from([x, y, z]),
+            offsets,
+        })
+    }
+}
@@ -1,6 +1,9 @@
+use std::time::Instant;
+
 use bytes::{Bytes, BytesMut};
 
 use crate::network::raknet::packets::{Ack, Nak};
+use crate::network::raknet::FrameBatch;
 use crate::network::session::Session;
 use common::VResult;
 use common::{Deserialize, Serialize};
@@ -8,10 +11,20 @@ use common::{Deserialize, Serialize};
 impl Session {
     /// Processes an acknowledgement received from the client.
     ///
-    /// This function unregisters the specified packet IDs from the recovery queue.
+    /// This function unregisters the specified packet IDs from the recovery queue,
+    /// and uses them to update the round-trip time estimate and congestion window.
     pub fn handle_ack(&self, pk: Bytes) -> VResult<()> {
         let ack = Ack::deserialize(pk)?;
-        self.raknet.recovery_queue.confirm(&ack.records);
+        let confirmed =
+            self.raknet.recovery_queue.confirm(&ack.records, Instant::now());
+
+        let mut congestion = self.raknet.congestion.lock();
+        for batch in confirmed {
+            if let Some(rtt) = batch.rtt {
+                congestion.on_rtt_sample(rtt);
+            }
+            congestion.on_ack();
+        }
 
         Ok(())
     }
@@ -22,18 +35,38 @@ impl Session {
     /// client again.
     pub async fn handle_nack(&self, pk: Bytes) -> VResult<()> {
         let nack = Nak::deserialize(pk)?;
-        let frame_batches = self.raknet.recovery_queue.recover(&nack.records);
+        let frame_batches =
+            self.raknet.recovery_queue.recover(&nack.records, Instant::now());
         tracing::info!("Recovered packets: {:?}", nack.records);
 
+        self.raknet.congestion.lock().on_nak();
+        self.resend_batches(frame_batches).await
+    }
+
+    /// Resends batches that have not been acknowledged within the retransmission timeout.
+    pub async fn resend_expired(&self) -> VResult<()> {
+        let timeout = self.raknet.congestion.lock().retransmission_timeout();
+        let frame_batches =
+            self.raknet.recovery_queue.expired(timeout, Instant::now());
+
+        if frame_batches.is_empty() {
+            return Ok(());
+        }
+
+        tracing::trace!("Resending {} timed out batches", frame_batches.len());
+
+        self.raknet.congestion.lock().on_resend();
+        self.resend_batches(frame_batches).await
+    }
+
+    async fn resend_batches(&self, frame_batches: Vec<FrameBatch>) -> VResult<()> {
         let mut serialized = BytesMut::new();
         for frame_batch in frame_batches {
-            frame_batch.serialize(&mut serialized);            
+            frame_batch.serialize(&mut serialized);
 
             self.raknet
                 .udp_socket
-                .send_to(
-                    serialized.as_ref(), self.raknet.address
-                )
+                .send_to(serialized.as_ref(), self.raknet.address)
                 .await?;
 
             serialized.clear();
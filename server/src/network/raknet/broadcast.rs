@@ -16,6 +16,8 @@ pub struct BroadcastPacket {
     /// If it matches, the packet will not be sent.
     /// This can be used to broadcast packets to every client other than self.
     pub sender: Option<NonZeroU64>,
+    /// ID of the packet.
+    pub id: u32,
     /// Content of the packet.
     ///
     /// This must be an already serialized packet (use the [`Serialize`] trait)
@@ -32,6 +34,7 @@ impl BroadcastPacket {
         
         Ok(Self {
             sender,
+            id: T::ID,
             content: packet.serialize()
         })
     }
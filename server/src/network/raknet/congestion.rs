@@ -0,0 +1,117 @@
+use std::time::Duration;
+
+/// Retransmission timeout used before any round-trip time has been measured.
+pub const INITIAL_RTO: Duration = Duration::from_millis(2000);
+/// Maximum retransmission timeout.
+pub const MAX_RTO: Duration = Duration::from_millis(2000);
+/// Extra time added to the retransmission timeout to account for the client's acknowledgement delay.
+const RTO_VARIANCE: Duration = Duration::from_millis(30);
+
+/// Sliding window congestion control, modelled after RakNet's `CCRakNetSlidingWindow`.
+///
+/// The window starts at a single MTU and grows by one MTU for every acknowledged batch (slow start).
+/// Once it passes the slow start threshold, it only grows by about one MTU per round trip (congestion avoidance).
+/// Negative acknowledgements lower the threshold, while timeouts also reset the window back to a single MTU.
+///
+/// The round-trip time is estimated from acknowledgements, as described in RFC 6298.
+#[derive(Debug)]
+pub struct CongestionController {
+    /// Maximum size of a datagram.
+    mtu: usize,
+    /// Amount of bytes that can be in flight at once.
+    window: usize,
+    /// Window size at which slow start ends.
+    /// A value of 0 means that slow start has not been ended yet.
+    slow_start_threshold: usize,
+    /// Smoothed round-trip time.
+    rtt: Option<Duration>,
+    /// Round-trip time variation.
+    rtt_variance: Duration,
+}
+
+impl CongestionController {
+    /// Creates a new controller for a connection with the given MTU.
+    pub const fn new(mtu: usize) -> Self {
+        Self {
+            mtu,
+            window: mtu,
+            slow_start_threshold: 0,
+            rtt: None,
+            rtt_variance: Duration::ZERO,
+        }
+    }
+
+    /// Amount of bytes that can be in flight at once.
+    #[inline]
+    pub const fn window(&self) -> usize {
+        self.window
+    }
+
+    /// Smoothed round-trip time, if it has been measured.
+    #[inline]
+    pub const fn rtt(&self) -> Option<Duration> {
+        self.rtt
+    }
+
+    /// Whether the window is still growing exponentially.
+    #[inline]
+    pub const fn in_slow_start(&self) -> bool {
+        self.slow_start_threshold == 0
+            || self.window <= self.slow_start_threshold
+    }
+
+    /// Amount of bytes that can be sent, given the amount of bytes that have not been acknowledged yet.
+    #[inline]
+    pub const fn transmission_budget(&self, bytes_in_flight: usize) -> usize {
+        self.window.saturating_sub(bytes_in_flight)
+    }
+
+    /// Time after which unacknowledged batches are resent.
+    pub fn retransmission_timeout(&self) -> Duration {
+        self.rtt.map_or(INITIAL_RTO, |rtt| {
+            (rtt * 2 + self.rtt_variance * 4 + RTO_VARIANCE).min(MAX_RTO)
+        })
+    }
+
+    /// Updates the round-trip time estimate using the time it took for a batch to be acknowledged.
+    ///
+    /// Only batches that have not been resent should be measured,
+    /// since it is unknown which transmission an acknowledgement for a resent batch belongs to.
+    pub fn on_rtt_sample(&mut self, sample: Duration) {
+        match self.rtt {
+            Some(rtt) => {
+                let difference = rtt.abs_diff(sample);
+                self.rtt_variance = self.rtt_variance * 3 / 4 + difference / 4;
+                self.rtt = Some(rtt * 7 / 8 + sample / 8);
+            }
+            None => {
+                self.rtt = Some(sample);
+                self.rtt_variance = sample / 2;
+            }
+        }
+    }
+
+    /// Grows the window after a batch has been acknowledged.
+    pub const fn on_ack(&mut self) {
+        if self.in_slow_start() {
+            self.window += self.mtu;
+        } else {
+            self.window += self.mtu * self.mtu / self.window;
+        }
+    }
+
+    /// Ends slow start after the client reported lost batches.
+    pub fn on_nak(&mut self) {
+        if self.in_slow_start() {
+            self.slow_start_threshold = (self.window / 2).max(self.mtu);
+        }
+    }
+
+    /// Resets the window after batches had to be resent because they timed out.
+    pub fn on_resend(&mut self) {
+        if self.window > self.mtu * 2 {
+            self.slow_start_threshold = (self.window / 2).max(self.mtu);
+            self.window = self.mtu;
+        }
+    }
+}
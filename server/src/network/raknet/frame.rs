@@ -4,8 +4,8 @@ use bytes::Bytes;
 use bytes::{Buf, BufMut, BytesMut};
 
 use crate::network::raknet::Reliability;
-use common::nvassert;
 use common::VResult;
+use common::{bail, nvassert};
 use common::{Deserialize, Serialize};
 use common::{ReadExtensions, WriteExtensions};
 
@@ -23,6 +23,8 @@ pub const CONTINUOUS_SEND_BIT_FLAG: u8 = 0x08;
 /// Unknown what this is.
 /// Possibly used for Raknet congestion control.
 pub const NEEDS_B_AND_AS_BIT_FLAG: u8 = 0x04;
+/// Size of the IPv4 and UDP headers, which are included in the MTU.
+pub const UDP_HEADER_SIZE: usize = 28;
 
 /// Contains a set of frames.
 #[derive(Debug, Default, Clone)]
@@ -34,14 +36,17 @@ pub struct FrameBatch {
 }
 
 impl FrameBatch {
-    /// Gives a rough estimate of the size of this batch in bytes.
-    /// This estimate will always be greater than the actual size of the batch.
-    #[inline]
-    pub fn estimate_size(&self) -> usize {
-        std::mem::size_of::<Self>()
-            + self.frames.iter().fold(0, |acc, f| {
-                acc + std::mem::size_of::<Frame>() + f.body.len()
-            })
+    /// Size of the flags and sequence number that precede the frames.
+    pub const HEADER_SIZE: usize = 1 + 3;
+
+    /// Returns the exact size of this batch when serialized.
+    pub fn serialized_size(&self) -> usize {
+        Self::HEADER_SIZE
+            + self
+                .frames
+                .iter()
+                .map(Frame::serialized_size)
+                .sum::<usize>()
     }
 
     #[inline]
@@ -109,6 +114,74 @@ impl Frame {
         Self { reliability, body, ..Default::default() }
     }
 
+    /// Returns the size of the frame header for the given reliability.
+    pub const fn header_size(
+        reliability: Reliability,
+        is_compound: bool,
+    ) -> usize {
+        let mut size = 1 + 2;
+        if reliability.is_reliable() {
+            size += 3;
+        }
+        if reliability.is_sequenced() {
+            size += 3;
+        }
+        if reliability.is_ordered() {
+            size += 3 + 1;
+        }
+        if is_compound {
+            size += 4 + 2 + 4;
+        }
+
+        size
+    }
+
+    /// Returns the exact size of this frame when serialized.
+    pub const fn serialized_size(&self) -> usize {
+        Self::header_size(self.reliability, self.is_compound) + self.body.len()
+    }
+
+    /// Splits the frame into fragments that are at most `max_size` bytes when serialized.
+    ///
+    /// The fragments share the ordering and sequencing information of this frame,
+    /// but every reliable fragment still needs its own reliable index.
+    ///
+    /// Returns an error if `max_size` is too small to fit the fragment header and any data.
+    pub fn split(
+        &self,
+        max_size: usize,
+        compound_id: u16,
+    ) -> VResult<Vec<Self>> {
+        let reliability = self.reliability.into_reliable();
+        let header_size = Self::header_size(reliability, true);
+        if max_size <= header_size {
+            bail!(
+                Other,
+                "Fragments of {max_size} bytes cannot fit the {header_size} byte frame header"
+            );
+        }
+
+        let chunk_size = max_size - header_size;
+        let compound_size = self.body.len().div_ceil(chunk_size);
+
+        Ok((0..compound_size)
+            .map(|index| {
+                let start = index * chunk_size;
+                let end = (start + chunk_size).min(self.body.len());
+
+                Self {
+                    reliability,
+                    is_compound: true,
+                    compound_id,
+                    compound_size: compound_size as u32,
+                    compound_index: index as u32,
+                    body: self.body.slice(start..end),
+                    ..self.clone()
+                }
+            })
+            .collect())
+    }
+
     /// Decodes the frame.
     #[allow(clippy::useless_let_if_seq)]
     fn deserialize(buffer: &mut Bytes) -> VResult<Self> {
@@ -5,6 +5,7 @@ pub mod packets;
 glob_export!(ack);
 glob_export!(broadcast);
 glob_export!(compound_collector);
+glob_export!(congestion);
 glob_export!(frame);
 glob_export!(login);
 glob_export!(order_channel);
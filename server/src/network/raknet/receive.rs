@@ -1,4 +1,3 @@
-use std::io::Read;
 use std::sync::atomic::Ordering;
 use std::time::Instant;
 
@@ -6,29 +5,87 @@ use async_recursion::async_recursion;
 use bytes::{Buf, Bytes, BytesMut};
 
 use crate::config::SERVER_CONFIG;
+use crate::crypto::Encryptor;
 use crate::network::header::Header;
-use crate::network::packets::cache::CacheStatus;
+use crate::network::packets::cache::{CacheBlobStatus, CacheStatus};
 use crate::network::packets::command::{CommandRequest, SettingsCommand};
 use crate::network::packets::login::{
     ChunkRadiusRequest, ClientToServerHandshake, CompressionAlgorithm, Login,
     RequestNetworkSettings, ResourcePackClientResponse,
 };
 use crate::network::packets::{
-    Animate, ConnectedPacket, Interact, MovePlayer, RequestAbility,
-    SetLocalPlayerAsInitialized, TextMessage, UpdateSkin, ViolationWarning,
-    CONNECTED_PACKET_ID,
+    Animate, BlockActorData, ConnectedPacket, Interact, MovePlayer, PlayerAction, RequestAbility,
+    SetLocalPlayerAsInitialized, SubChunkRequest, TextMessage, UpdateSkin,
+    ViolationWarning, CONNECTED_PACKET_ID,
 };
 use crate::network::raknet::packets::{
     Ack, ConnectionRequest, DisconnectNotification, Nak, NewIncomingConnection,
 };
-use crate::network::raknet::{BroadcastPacket, Frame, FrameBatch};
+use crate::network::raknet::{
+    BroadcastPacket, Frame, FrameBatch, DEFAULT_SEND_CONFIG,
+};
 use crate::network::session::Session;
 use common::{bail, nvassert, ReadExtensions, VResult};
 use common::{Deserialize, Serialize};
 
-use super::DEFAULT_SEND_CONFIG;
 use super::packets::ConnectedPing;
 
+/// Splits a game packet into the packets of its batch.
+///
+/// The batch is decrypted if an encryptor is given,
+/// and decompressed if `compression_enabled` is set.
+/// Every packet in the batch is prefixed with its length.
+pub fn decode_batch(
+    mut pk: Bytes,
+    compression_enabled: bool,
+    encryptor: Option<&Encryptor>,
+) -> VResult<Vec<Bytes>> {
+    nvassert!(pk.get_u8() == 0xfe);
+
+    if let Some(encryptor) = encryptor {
+        pk = encryptor.decrypt(pk)?;
+    }
+
+    if compression_enabled {
+        let algorithm = {
+            let config = SERVER_CONFIG.read();
+            if config.compression_prefix {
+                if !pk.has_remaining() {
+                    bail!(BadPacket, "Batch is missing its compression prefix");
+                }
+
+                CompressionAlgorithm::from_prefix(pk.get_u8())?
+            } else {
+                // Without a prefix, only batches above the threshold are compressed.
+                (config.compression_threshold != 0
+                    && pk.len() > config.compression_threshold as usize)
+                    .then_some(config.compression_algorithm)
+            }
+        };
+
+        if let Some(algorithm) = algorithm {
+            pk = algorithm.decompress(pk.as_ref())?;
+        }
+    }
+
+    // A batch can contain multiple packets, each prefixed with its length.
+    let mut packets = Vec::new();
+    while pk.has_remaining() {
+        let length = pk.get_var_u32()? as usize;
+        if length > pk.remaining() {
+            bail!(
+                BadPacket,
+                "Game packet length ({length}) exceeds remaining batch size ({})",
+                pk.remaining()
+            );
+        }
+
+        packets.push(pk.split_to(length));
+    }
+
+    Ok(packets)
+}
+
 impl Session {
     /// Processes the raw packet coming directly from the network.
     ///
@@ -61,7 +118,7 @@ impl Session {
             }
         }
 
-        self.send_serialized(pk.content, DEFAULT_SEND_CONFIG)
+        self.send_serialized(pk.id, pk.content, DEFAULT_SEND_CONFIG)
     }
 
     /// Processes a batch of frames.
@@ -153,62 +210,24 @@ impl Session {
         Ok(())
     }
 
-    async fn handle_game_packet(&self, mut pk: Bytes) -> VResult<()> {
-        nvassert!(pk.get_u8() == 0xfe);
-
-        // Decrypt packet
-        if self.encryptor.initialized() {
-            // Safe to unwrap because the encryptor is confirmed to exist.
-            let encryptor = self
-                .encryptor
-                .get()
-                .expect("Encryptor was destroyed while it was in use");
-
-            pk = match encryptor.decrypt(pk) {
-                Ok(t) => t,
-                Err(e) => {
-                    return Err(e);
-                }
-            }
-        }
-
-        let compression_enabled =
-            self.raknet.compression_enabled.load(Ordering::SeqCst);
-        let compression_threshold = SERVER_CONFIG.read().compression_threshold;
-
-        if compression_enabled
-            && compression_threshold != 0
-            && pk.len() > compression_threshold as usize
-        {
-            // Packet is compressed
-            let decompressed = match SERVER_CONFIG.read().compression_algorithm
-            {
-                CompressionAlgorithm::Snappy => {
-                    unimplemented!("Snappy decompression");
-                }
-                CompressionAlgorithm::Deflate => {
-                    let mut reader =
-                        flate2::read::DeflateDecoder::new(pk.as_ref());
-
-                    let mut decompressed = Vec::new();
-                    reader.read_to_end(&mut decompressed)?;
-                    // .context("Failed to decompress packet using Deflate")?;
+    async fn handle_game_packet(&self, pk: Bytes) -> VResult<()> {
+        let packets = decode_batch(
+            pk,
+            self.raknet.compression_enabled.load(Ordering::SeqCst),
+            self.encryptor.get(),
+        )?;
 
-                    Bytes::copy_from_slice(decompressed.as_slice())
-                }
-            };
-
-            self.handle_decompressed_game_packet(decompressed).await
-        } else {
-            self.handle_decompressed_game_packet(pk).await
+        for packet in packets {
+            self.handle_decompressed_game_packet(packet).await?;
         }
+
+        Ok(())
     }
 
     async fn handle_decompressed_game_packet(
         &self,
         mut pk: Bytes,
     ) -> VResult<()> {
-        let length = pk.get_var_u32()?;
         let header = Header::deserialize(&mut pk)?;
 
         match header.id {
@@ -220,6 +239,8 @@ impl Session {
                 self.handle_client_to_server_handshake(pk)
             }
             CacheStatus::ID => self.handle_cache_status(pk),
+            CacheBlobStatus::ID => self.handle_cache_blob_status(pk),
+            SubChunkRequest::ID => self.handle_sub_chunk_request(pk),
             ResourcePackClientResponse::ID => {
                 self.handle_resource_pack_client_response(pk)
             }
@@ -231,6 +252,8 @@ impl Session {
                 self.handle_local_player_initialized(pk)
             }
             MovePlayer::ID => self.handle_move_player(pk),
+            PlayerAction::ID => self.handle_player_action(pk),
+            BlockActorData::ID => self.handle_block_actor_data(pk),
             RequestAbility::ID => self.handle_ability_request(pk),
             Animate::ID => self.handle_animation(pk),
             CommandRequest::ID => self.handle_command_request(pk),
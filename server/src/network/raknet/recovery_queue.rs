@@ -1,11 +1,36 @@
+use std::time::{Duration, Instant};
+
 use dashmap::DashMap;
 
 use crate::network::raknet::packets::AckRecord;
 use crate::network::raknet::FrameBatch;
 
+/// A batch that has not been acknowledged yet.
+#[derive(Debug)]
+struct RecoveryEntry {
+    batch: FrameBatch,
+    /// Estimated size of the batch in bytes.
+    size: usize,
+    /// When the batch was last sent.
+    sent: Instant,
+    /// Whether the batch has been resent.
+    resent: bool,
+}
+
+/// Batch that was acknowledged by the client.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct AcknowledgedBatch {
+    /// Estimated size of the batch in bytes.
+    pub size: usize,
+    /// Time between sending the batch and receiving the acknowledgement.
+    /// This is `None` if the batch was resent,
+    /// since it is unknown which transmission was acknowledged.
+    pub rtt: Option<Duration>,
+}
+
 #[derive(Debug)]
 pub struct RecoveryQueue {
-    frames: DashMap<u32, FrameBatch>,
+    frames: DashMap<u32, RecoveryEntry>,
 }
 
 impl RecoveryQueue {
@@ -14,47 +39,85 @@ impl RecoveryQueue {
     }
 
     #[inline]
-    pub fn insert(&self, batch: FrameBatch) {
-        self.frames.insert(batch.sequence_number, batch);
+    pub fn insert(&self, batch: FrameBatch, now: Instant) {
+        self.frames.insert(
+            batch.sequence_number,
+            RecoveryEntry {
+                size: batch.serialized_size(),
+                batch,
+                sent: now,
+                resent: false,
+            },
+        );
+    }
+
+    /// Amount of bytes that have been sent, but not acknowledged yet.
+    pub fn bytes_in_flight(&self) -> usize {
+        self.frames.iter().map(|entry| entry.size).sum()
     }
 
-    pub fn confirm(&self, records: &Vec<AckRecord>) {
-        for record in records {
-            match record {
-                AckRecord::Single(id) => {
-                    self.frames.remove(id);
-                }
-                AckRecord::Range(range) => {
-                    for id in range.clone() {
-                        self.frames.remove(&id);
-                    }
-                }
+    /// Removes the acknowledged batches from the queue.
+    pub fn confirm(
+        &self,
+        records: &[AckRecord],
+        now: Instant,
+    ) -> Vec<AcknowledgedBatch> {
+        let mut confirmed = Vec::new();
+        for id in records.iter().flat_map(record_ids) {
+            if let Some((_, entry)) = self.frames.remove(&id) {
+                confirmed.push(AcknowledgedBatch {
+                    size: entry.size,
+                    rtt: (!entry.resent).then(|| now - entry.sent),
+                });
             }
         }
+
+        confirmed
     }
 
-    pub fn recover(&self, records: &Vec<AckRecord>) -> Vec<FrameBatch> {
+    /// Returns the batches that the client reported as lost, so that they can be resent.
+    pub fn recover(
+        &self,
+        records: &[AckRecord],
+        now: Instant,
+    ) -> Vec<FrameBatch> {
         let mut recovered = Vec::new();
-        for record in records {
-            match record {
-                AckRecord::Single(id) => {
-                    if let Some(frame) = self.frames.get(id) {
-                        recovered.push((*frame.value()).clone());
-                    }
-                }
-                AckRecord::Range(range) => {
-                    recovered.reserve(range.len());
-                    for id in range.clone() {
-                        if let Some(frame) = self.frames.get(&id) {
-                            recovered.push((*frame.value()).clone());
-                        }
-                    }
-                }
+        for id in records.iter().flat_map(record_ids) {
+            if let Some(mut entry) = self.frames.get_mut(&id) {
+                entry.sent = now;
+                entry.resent = true;
+                recovered.push(entry.batch.clone());
             }
         }
 
         recovered
     }
+
+    /// Returns the batches that have not been acknowledged within the given timeout,
+    /// so that they can be resent.
+    pub fn expired(&self, timeout: Duration, now: Instant) -> Vec<FrameBatch> {
+        let mut expired = Vec::new();
+        for mut entry in self.frames.iter_mut() {
+            if now.duration_since(entry.sent) >= timeout {
+                entry.sent = now;
+                entry.resent = true;
+                expired.push(entry.batch.clone());
+            }
+        }
+
+        expired.sort_unstable_by_key(|batch| batch.sequence_number);
+        expired
+    }
+}
+
+/// Returns the sequence numbers contained in an (N)ACK record.
+///
+/// Both the start and end of ranges are inclusive.
+const fn record_ids(record: &AckRecord) -> std::ops::RangeInclusive<u32> {
+    match record {
+        AckRecord::Single(id) => *id..=*id,
+        AckRecord::Range(range) => range.start..=range.end,
+    }
 }
 
 impl Default for RecoveryQueue {
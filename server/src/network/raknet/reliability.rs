@@ -65,6 +65,19 @@ impl Reliability {
         )
     }
 
+    /// Returns the reliable variant of this reliability.
+    ///
+    /// Fragments of a compound are always sent reliably,
+    /// because the compound can only be merged once every fragment has arrived.
+    #[inline]
+    pub const fn into_reliable(self) -> Self {
+        match self {
+            Self::Unreliable => Self::Reliable,
+            Self::UnreliableSequenced => Self::ReliableSequenced,
+            _ => self,
+        }
+    }
+
     /// Returns whether this reliability is sequenced.
     #[inline]
     pub const fn is_sequenced(self) -> bool {
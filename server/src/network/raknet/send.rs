@@ -1,18 +1,16 @@
-use std::io::Write;
 use std::sync::atomic::Ordering;
+use std::time::Instant;
 
-use async_recursion::async_recursion;
 use bytes::{Buf, BufMut, Bytes, BytesMut};
-use flate2::write::DeflateEncoder;
-use flate2::Compression;
 
 use crate::config::SERVER_CONFIG;
+use crate::crypto::Encryptor;
 use crate::network::header::Header;
-use crate::network::packets::login::CompressionAlgorithm;
+use crate::network::packets::login::NO_COMPRESSION_PREFIX;
 use crate::network::packets::{ConnectedPacket, Packet, CONNECTED_PACKET_ID};
 use crate::network::raknet::packets::{Ack, AckRecord};
 use crate::network::raknet::Reliability;
-use crate::network::raknet::{Frame, FrameBatch};
+use crate::network::raknet::{Frame, FrameBatch, UDP_HEADER_SIZE};
 use crate::network::session::Session;
 use common::ReadExtensions;
 use common::VResult;
@@ -20,6 +18,41 @@ use common::{Deserialize, Serialize};
 
 use super::SendPriority;
 
+/// Game packets waiting to be sent in the next batch.
+#[derive(Debug, Default)]
+pub struct PacketBatch {
+    /// Packets in the batch, each prefixed with its length.
+    pub buffer: BytesMut,
+    /// Whether the batch contains a packet that should be compressed.
+    /// Batches that only contain packets listed in
+    /// [`uncompressed_packets`](crate::config::ServerConfig::uncompressed_packets) are sent uncompressed.
+    pub compress: bool,
+    /// Reliability and priority of every packet in the batch.
+    pub config: PacketConfig,
+}
+
+impl PacketBatch {
+    /// Adds a packet that is prefixed with its length to the batch.
+    pub fn push(&mut self, pk: Bytes, compress: bool) {
+        self.buffer.put(pk);
+        self.compress |= compress;
+    }
+
+    /// Removes all packets from the batch.
+    ///
+    /// Returns the packets and whether they should be compressed,
+    /// or `None` if the batch is empty.
+    pub fn take(&mut self) -> Option<(Bytes, bool)> {
+        if self.buffer.is_empty() {
+            return None;
+        }
+
+        let compress = std::mem::take(&mut self.compress);
+        Some((self.buffer.split().freeze(), compress))
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub struct PacketConfig {
     pub reliability: Reliability,
     pub priority: SendPriority,
@@ -30,59 +63,136 @@ pub const DEFAULT_SEND_CONFIG: PacketConfig = PacketConfig {
     priority: SendPriority::Medium,
 };
 
+impl Default for PacketConfig {
+    fn default() -> Self {
+        DEFAULT_SEND_CONFIG
+    }
+}
+
+/// Turns the packets of a batch into a game packet.
+///
+/// The batch is compressed if `compression_enabled` is set,
+/// and encrypted if an encryptor is given.
+/// The returned buffer starts with the game packet ID.
+pub fn encode_batch(
+    mut pk: Bytes,
+    compress: bool,
+    compression_enabled: bool,
+    encryptor: Option<&Encryptor>,
+) -> VResult<Bytes> {
+    if compression_enabled {
+        let config = SERVER_CONFIG.read();
+        let algorithm = if config.compression_prefix {
+            // The prefix tells the client whether the batch is compressed.
+            (compress
+                && config.compression_threshold != 0
+                && pk.len() > config.compression_threshold as usize)
+                .then_some(config.compression_algorithm)
+        } else {
+            // Without a prefix, the client assumes that batches above the threshold are compressed.
+            (pk.len() > config.compression_threshold as usize)
+                .then_some(config.compression_algorithm)
+        };
+
+        let mut buffer = BytesMut::new();
+        if config.compression_prefix {
+            buffer.put_u8(
+                algorithm.map_or(NO_COMPRESSION_PREFIX, |a| a.prefix()),
+            );
+        }
+
+        match algorithm {
+            Some(algorithm) => buffer.put(
+                algorithm.compress(pk.as_ref(), config.compression_level)?,
+            ),
+            None => buffer.put(pk),
+        }
+
+        pk = buffer.freeze();
+    }
+
+    if let Some(encryptor) = encryptor {
+        pk = encryptor.encrypt(pk)?;
+    }
+
+    let mut buffer = BytesMut::with_capacity(1 + pk.len());
+    buffer.put_u8(CONNECTED_PACKET_ID);
+    buffer.put(pk);
+
+    Ok(buffer.freeze())
+}
+
 impl Session {
     /// Sends a game packet with default settings
-    /// (reliable ordered and medium priority)
+    /// (reliable ordered and medium priority).
+    ///
+    /// The packet is added to the current batch, which is sent during the next flush.
     #[inline]
     pub fn send<T: ConnectedPacket + Serialize>(&self, pk: T) -> VResult<()> {
-        let pk = Packet::new(pk);
-        let mut serialized = pk.serialize();
+        self.send_with_config(pk, DEFAULT_SEND_CONFIG)
+    }
 
-        self.send_serialized(serialized, DEFAULT_SEND_CONFIG)
+    /// Sends a game packet with custom reliability and priority.
+    pub fn send_with_config<T: ConnectedPacket + Serialize>(
+        &self,
+        pk: T,
+        config: PacketConfig,
+    ) -> VResult<()> {
+        let pk = Packet::new(pk);
+        self.send_serialized(T::ID, pk.serialize(), config)
     }
 
-    /// Sends a game packet with custom reliability and priority
+    /// Adds an already serialized game packet to the current batch.
+    ///
+    /// The packet should be prefixed with its length, like the output of [`Packet::serialize`].
+    /// If the current batch uses a different config, it is sent first
+    /// so that the packets stay in order.
     pub fn send_serialized(
         &self,
-        mut pk: Bytes,
+        id: u32,
+        pk: Bytes,
         config: PacketConfig,
     ) -> VResult<()> {
-        let mut buffer = BytesMut::new();
-        buffer.put_u8(CONNECTED_PACKET_ID);
-
-        if self.raknet.compression_enabled.load(Ordering::SeqCst) {
-            let (algorithm, threshold) = {
-                let config = SERVER_CONFIG.read();
-                (config.compression_algorithm, config.compression_threshold)
-            };
-
-            if pk.len() > threshold as usize {
-                // Compress packet
-                match SERVER_CONFIG.read().compression_algorithm {
-                    CompressionAlgorithm::Snappy => {
-                        unimplemented!("Snappy compression");
-                    }
-                    CompressionAlgorithm::Deflate => {
-                        let mut writer = DeflateEncoder::new(
-                            Vec::new(),
-                            Compression::best(),
-                        );
-
-                        writer.write_all(pk.as_ref())?;
-                        pk =
-                            Bytes::copy_from_slice(writer.finish()?.as_slice());
-                    }
-                }
-            }
-        }
+        let compress =
+            !SERVER_CONFIG.read().uncompressed_packets.contains(&id);
 
-        if let Some(encryptor) = self.encryptor.get() {
-            pk = encryptor.encrypt(pk)?;
+        let mut batch = self.raknet.packet_batch.lock();
+        if batch.config != config {
+            self.send_packet_batch(&mut batch)?;
+            batch.config = config;
         }
-        
-        buffer.put(pk);
+        batch.push(pk, compress);
+
+        Ok(())
+    }
+
+    /// Compresses and encrypts all game packets in the current batch,
+    /// and inserts the result into the send queue.
+    ///
+    /// This must be called before changing the compression or encryption settings,
+    /// so that packets are encoded using the settings that were active when they were sent.
+    pub fn flush_packet_batch(&self) -> VResult<()> {
+        let mut batch = self.raknet.packet_batch.lock();
+        self.send_packet_batch(&mut batch)
+    }
+
+    /// Encodes the packets in the batch and inserts them into the send queue.
+    ///
+    /// The batch must stay locked until the packets have been queued,
+    /// otherwise concurrent batches could be encrypted and sent out of order.
+    fn send_packet_batch(&self, batch: &mut PacketBatch) -> VResult<()> {
+        let Some((pk, compress)) = batch.take() else {
+            return Ok(());
+        };
+
+        let buffer = encode_batch(
+            pk,
+            compress,
+            self.raknet.compression_enabled.load(Ordering::SeqCst),
+            self.encryptor.get(),
+        )?;
 
-        self.send_raw_buffer_with_config(buffer.freeze(), config);
+        self.send_raw_buffer_with_config(buffer, batch.config);
         Ok(())
     }
 
@@ -106,25 +216,43 @@ impl Session {
     }
 
     /// Flushes the send queue.
+    ///
+    /// Only as much data as the congestion window allows is sent,
+    /// the remaining frames are kept in the queue until the next flush.
     pub async fn flush(&self) -> VResult<()> {
+        self.flush_packet_batch()?;
+        self.resend_expired().await?;
+
         let tick = self.current_tick.load(Ordering::SeqCst);
+        let mut budget = {
+            let in_flight = self.raknet.recovery_queue.bytes_in_flight();
+            self.raknet.congestion.lock().transmission_budget(in_flight)
+        };
 
-        if let Some(frames) = self.raknet.send_queue.flush(SendPriority::High) {
+        if let Some(frames) = self
+            .raknet
+            .send_queue
+            .flush_limited(SendPriority::High, &mut budget)
+        {
             self.send_raw_frames(frames).await?;
         }
 
         if tick % 2 == 0 {
             // Also flush broadcast packets.
-            if let Some(frames) =
-                self.raknet.send_queue.flush(SendPriority::Medium)
+            if let Some(frames) = self
+                .raknet
+                .send_queue
+                .flush_limited(SendPriority::Medium, &mut budget)
             {
                 self.send_raw_frames(frames).await?;
             }
         }
 
         if tick % 4 == 0 {
-            if let Some(frames) =
-                self.raknet.send_queue.flush(SendPriority::Low)
+            if let Some(frames) = self
+                .raknet
+                .send_queue
+                .flush_limited(SendPriority::Low, &mut budget)
             {
                 self.send_raw_frames(frames).await?;
             }
@@ -139,6 +267,8 @@ impl Session {
     }
 
     pub async fn flush_all(&self) -> VResult<()> {
+        self.flush_packet_batch()?;
+
         if let Some(frames) = self.raknet.send_queue.flush(SendPriority::High) {
             self.send_raw_frames(frames).await?;
         }
@@ -199,140 +329,95 @@ impl Session {
         Ok(())
     }
 
-    #[async_recursion]
+    /// Sends frames, combining as many as possible into each batch.
+    ///
+    /// Frames that do not fit in a single batch are split into fragments.
     async fn send_raw_frames(&self, frames: Vec<Frame>) -> VResult<()> {
-        let mut serialized = BytesMut::new();
-
-        // Process fragments first to prevent sequence number duplication.
-        for frame in &frames {
-            let frame_size = frame.body.len() + std::mem::size_of::<Frame>();
+        let max_batch_size =
+            (self.raknet.mtu as usize).saturating_sub(UDP_HEADER_SIZE);
+        let max_frame_size =
+            max_batch_size.saturating_sub(FrameBatch::HEADER_SIZE);
+
+        let mut batch = FrameBatch::default();
+        for frame in frames {
+            for mut frame in self.split_frame(frame, max_frame_size)? {
+                if frame.reliability.is_reliable() {
+                    frame.reliable_index =
+                        self.raknet.ack_index.fetch_add(1, Ordering::SeqCst);
+                }
 
-            if frame_size > self.raknet.mtu as usize {
-                self.raknet
-                    .batch_sequence_number
-                    .fetch_sub(1, Ordering::SeqCst);
+                if !batch.is_empty()
+                    && batch.serialized_size() + frame.serialized_size()
+                        > max_batch_size
+                {
+                    self.send_frame_batch(std::mem::take(&mut batch)).await?;
+                }
 
-                let compound = self.split_frame(frame);
-                self.send_raw_frames(compound).await?;
+                batch.frames.push(frame);
             }
         }
 
-        let mut batch = FrameBatch {
-            sequence_number: self
-                .raknet
-                .batch_sequence_number
-                .fetch_add(1, Ordering::SeqCst),
-
-            frames: vec![],
-        };
-
-        let mut has_reliable_packet = false;
-        for mut frame in frames {
-            let frame_size = frame.body.len() + std::mem::size_of::<Frame>();
+        // Send remaining frames not sent by loop
+        if !batch.is_empty() {
+            self.send_frame_batch(batch).await?;
+        }
 
-            if frame.reliability.is_ordered() {
-                let order_index = self.raknet.order_channels
-                    [frame.order_channel as usize]
-                    .get_server_index();
-                frame.order_index = order_index;
-            }
+        Ok(())
+    }
 
-            if frame.reliability.is_sequenced() {
-                let sequence_index =
-                    self.raknet.sequence_index.fetch_add(1, Ordering::SeqCst);
-                frame.sequence_index = sequence_index;
-            }
+    /// Assigns a sequence number to the batch and sends it.
+    ///
+    /// Batches containing reliable frames are kept in the recovery queue until they are acknowledged.
+    async fn send_frame_batch(&self, mut batch: FrameBatch) -> VResult<()> {
+        batch.sequence_number = self
+            .raknet
+            .batch_sequence_number
+            .fetch_add(1, Ordering::SeqCst);
 
-            if frame.reliability.is_reliable() {
-                frame.reliable_index =
-                    self.raknet.ack_index.fetch_add(1, Ordering::SeqCst);
-                has_reliable_packet = true;
-            }
+        let mut serialized = BytesMut::with_capacity(batch.serialized_size());
+        batch.serialize(&mut serialized);
 
-            if batch.estimate_size() + frame_size <= self.raknet.mtu as usize {
-                batch.frames.push(frame);
-            } else if !batch.is_empty() {
-                if has_reliable_packet {
-                    self.raknet.recovery_queue.insert(batch.clone());
-                }
-
-                serialized.clear();
-                batch.serialize(&mut serialized);
-
-                // TODO: Add IPv6 support
-                self.raknet
-                    .udp_socket
-                    .send_to(serialized.as_ref(), self.raknet.address)
-                    .await?;
-
-                has_reliable_packet = false;
-                batch = FrameBatch {
-                    sequence_number: self
-                        .raknet
-                        .batch_sequence_number
-                        .fetch_add(1, Ordering::SeqCst),
-                    frames: vec![frame],
-                };
-            }
+        if batch.frames.iter().any(|f| f.reliability.is_reliable()) {
+            self.raknet.recovery_queue.insert(batch, Instant::now());
         }
 
-        // Send remaining packets not sent by loop
-        if !batch.is_empty() {
-            if has_reliable_packet {
-                self.raknet.recovery_queue.insert(batch.clone());
-            }
-
-            serialized.clear();
-            batch.serialize(&mut serialized);
-
-            // TODO: Add IPv6 support
-            self.raknet
-                .udp_socket
-                .send_to(serialized.as_ref(), self.raknet.address)
-                .await?;
-        } else {
-            self.raknet
-                .batch_sequence_number
-                .fetch_sub(1, Ordering::SeqCst);
-        }
+        // TODO: Add IPv6 support
+        self.raknet
+            .udp_socket
+            .send_to(serialized.as_ref(), self.raknet.address)
+            .await?;
 
         Ok(())
     }
 
-    fn split_frame(&self, mut frame: &Frame) -> Vec<Frame> {
-        let chunk_max_size = self.raknet.mtu as usize
-            - std::mem::size_of::<Frame>()
-            - std::mem::size_of::<FrameBatch>();
-        let compound_size = {
-            let frame_size = frame.body.len() + std::mem::size_of::<Frame>();
-
-            // Ceiling divide without floating point conversion.
-            // usize::div_ceil is still unstable.
-            (frame_size + chunk_max_size - 1) / chunk_max_size
-        };
+    /// Assigns ordering and sequencing information to a frame,
+    /// and splits it into fragments if it is larger than `max_size` bytes.
+    ///
+    /// All fragments share the order and sequence index of the original frame.
+    fn split_frame(
+        &self,
+        mut frame: Frame,
+        max_size: usize,
+    ) -> VResult<Vec<Frame>> {
+        if frame.reliability.is_ordered() {
+            let order_index = self.raknet.order_channels
+                [frame.order_channel as usize]
+                .get_server_index();
+            frame.order_index = order_index;
+        }
 
-        let mut compound = Vec::with_capacity(compound_size);
-        let chunks = frame.body.chunks(chunk_max_size);
+        if frame.reliability.is_sequenced() {
+            let sequence_index =
+                self.raknet.sequence_index.fetch_add(1, Ordering::SeqCst);
+            frame.sequence_index = sequence_index;
+        }
 
-        debug_assert_eq!(chunks.len(), compound_size);
+        if frame.serialized_size() <= max_size {
+            return Ok(vec![frame]);
+        }
 
         let compound_id =
             self.raknet.compound_id.fetch_add(1, Ordering::SeqCst);
-
-        for (i, chunk) in chunks.enumerate() {
-            let mut fragment = Frame {
-                reliability: frame.reliability,
-                is_compound: true,
-                compound_index: i as u32,
-                compound_size: compound_size as u32,
-                compound_id,
-                body: Bytes::copy_from_slice(chunk),
-                ..Default::default()
-            };
-
-            compound.push(fragment);
-        }
-
-        compound
+        frame.split(max_size, compound_id)
     }
 }
@@ -97,6 +97,41 @@ impl SendQueue {
             }
         }
     }
+
+    /// Removes frames of the given priority from the queue,
+    /// until their total size exceeds the budget.
+    ///
+    /// The budget is reduced by the size of the returned frames.
+    /// Frames that do not fit are kept in the queue, so that they can be sent once the budget allows it.
+    pub fn flush_limited(
+        &self,
+        priority: SendPriority,
+        budget: &mut usize,
+    ) -> Option<Vec<Frame>> {
+        let mut lock = match priority {
+            SendPriority::High => self.high_priority.lock(),
+            SendPriority::Medium => self.medium_priority.lock(),
+            SendPriority::Low => self.low_priority.lock(),
+        };
+
+        let mut frames = Vec::new();
+        while *budget > 0 {
+            let Some(frame) = lock.pop_front() else {
+                break;
+            };
+
+            // A frame is sent as long as there is some budget left,
+            // otherwise frames larger than the budget would never be sent.
+            *budget = budget.saturating_sub(frame.serialized_size());
+            frames.push(frame);
+        }
+
+        if frames.is_empty() {
+            None
+        } else {
+            Some(frames)
+        }
+    }
 }
 
 impl Default for SendQueue {
@@ -3,7 +3,7 @@ use std::{sync::{Arc, atomic::{AtomicU32, AtomicU16, AtomicBool}}, net::SocketAd
 use parking_lot::{RwLock, Mutex};
 use tokio::net::UdpSocket;
 
-use super::{CompoundCollector, OrderChannel, SendQueue, RecoveryQueue};
+use super::{CompoundCollector, CongestionController, OrderChannel, PacketBatch, SendQueue, RecoveryQueue};
 
 const ORDER_CHANNEL_COUNT: usize = 5;
 
@@ -42,7 +42,11 @@ pub struct RaknetData {
     pub confirmed_packets: Mutex<Vec<u32>>,
     /// Queue that stores packets in case they need to be recovered due to packet loss.
     pub recovery_queue: RecoveryQueue,
+    /// Limits the amount of data in flight and estimates the round-trip time.
+    pub congestion: Mutex<CongestionController>,
     /// Whether compression has been configured for this session.
     /// This is set to true after network settings have been sent to the client.
     pub compression_enabled: AtomicBool,
+    /// Game packets that will be sent in the next batch.
+    pub packet_batch: Mutex<PacketBatch>,
 }
\ No newline at end of file
@@ -48,7 +48,7 @@ impl Session {
             }
 
             // Flush last packets before closing
-            match self.flush().await {
+            match self.flush_all().await {
                 Ok(_) => (),
                 Err(e) => {
                     tracing::error!(
@@ -95,7 +95,13 @@ impl Session {
             return
         }
 
-        self.initialized.store(false, Ordering::SeqCst);
+        // Only players that have spawned have data worth saving.
+        if self.initialized.swap(false, Ordering::SeqCst) {
+            if let Err(e) = self.save_player_data() {
+                tracing::error!("Failed to save player data: {e}");
+            }
+        }
+        self.level_manager.remove_player(self.player.read().runtime_id);
 
         if let Ok(display_name) = self.get_display_name() {
             if let Ok(uuid) = self.get_uuid() {
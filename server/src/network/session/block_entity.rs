@@ -0,0 +1,44 @@
+use bytes::Bytes;
+use common::{bail, BlockPosition, Deserialize, VResult};
+
+use crate::network::packets::BlockActorData;
+use crate::network::session::Session;
+
+/// Block entities that clients are allowed to modify, such as signs that are being edited.
+const EDITABLE_BLOCK_ENTITIES: &[&str] = &["Sign", "HangingSign"];
+
+impl Session {
+    /// Handles a [`BlockActorData`] packet.
+    ///
+    /// The data of the block entity is replaced and sent to the other clients.
+    /// Only existing block entities that are editable can be modified.
+    pub fn handle_block_actor_data(&self, pk: Bytes) -> VResult<()> {
+        let request = BlockActorData::deserialize(pk)?;
+        let dimension = self.get_dimension();
+
+        let BlockPosition { x, y, z } = request.position;
+        let y = y as i32;
+        let Some(mut entity) = self
+            .level_manager
+            .dimension(dimension)
+            .chunks()
+            .get_block_entity(x, y, z)?
+        else {
+            bail!(BadPacket, "There is no block entity at [{x}, {y}, {z}]");
+        };
+
+        if !EDITABLE_BLOCK_ENTITIES.contains(&entity.id.as_str()) {
+            bail!(BadPacket, "Block entity {} cannot be edited", entity.id);
+        }
+
+        let nbt::Value::Compound(mut data) = request.nbt else {
+            bail!(BadPacket, "Expected block entity data to be a compound");
+        };
+
+        // The ID and position cannot be changed by the client.
+        data.retain(|name, _| !matches!(name.as_str(), "id" | "x" | "y" | "z"));
+        entity.data = data;
+
+        self.level_manager.set_block_entity(entity, dimension)
+    }
+}
@@ -0,0 +1,381 @@
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+
+use bytes::{BufMut, Bytes, BytesMut};
+use common::{BlockPosition, Deserialize, VResult, Vector2i, Vector3f};
+use level::{Chunk, Dimension};
+
+use crate::config::SERVER_CONFIG;
+use crate::network::cache_blob::CacheBlob;
+use crate::network::packets::cache::{CacheBlobStatus, CacheMissResponse};
+use crate::network::packets::{
+    LevelChunk, NetworkChunkPublisherUpdate, SubChunk, SubChunkEntry,
+    SubChunkHeightmap, SubChunkRequest, SubChunkRequestMode, SubChunkResult,
+};
+use crate::network::session::Session;
+
+/// Creates a sub chunk response entry without any data.
+const fn empty_entry(offset: [i8; 3], result: SubChunkResult) -> SubChunkEntry {
+    SubChunkEntry {
+        offset,
+        result,
+        payload: Bytes::new(),
+        heightmap: SubChunkHeightmap::None,
+        blob_hash: 0,
+    }
+}
+
+/// Converts the heightmap of a chunk to the heightmap of one of its sub chunks.
+fn sub_chunk_heightmap(chunk: &Chunk, index: i8) -> SubChunkHeightmap {
+    let bottom = index as i32 * 16;
+    let min_y = chunk.dimension().height_range().start;
+
+    let mut data = Box::new([0i8; 256]);
+    let (mut too_high, mut too_low) = (true, true);
+    for (column, height) in chunk.heightmap().iter().enumerate() {
+        // Columns without any blocks have a height of 0, which ends up below every sub chunk.
+        let relative = min_y + *height as i32 - 1 - bottom;
+        data[column] = if relative > 15 {
+            too_low = false;
+            16
+        } else if relative < 0 {
+            too_high = false;
+            -1
+        } else {
+            too_high = false;
+            too_low = false;
+            relative as i8
+        };
+    }
+
+    if too_high {
+        SubChunkHeightmap::TooHigh
+    } else if too_low {
+        SubChunkHeightmap::TooLow
+    } else {
+        SubChunkHeightmap::Data(data)
+    }
+}
+
+/// Whether a chunk is within the given render distance of the center chunk.
+pub const fn in_render_distance(
+    center: (i32, i32),
+    radius: i32,
+    x: i32,
+    z: i32,
+) -> bool {
+    let (dx, dz) = (x - center.0, z - center.1);
+    dx * dx + dz * dz <= radius * radius
+}
+
+/// Converts a position in the world to the position of the chunk it is in.
+pub fn chunk_position(position: &Vector3f) -> (i32, i32) {
+    (
+        (position.x.floor() as i32) >> 4,
+        (position.z.floor() as i32) >> 4,
+    )
+}
+
+impl Session {
+    /// Sends all chunks within the render distance of the player that have not been sent yet.
+    ///
+    /// This also updates the client's chunk publisher, which makes it discard chunks that
+    /// are out of range.
+    /// Nothing is sent if the client has not requested a chunk radius yet.
+    pub fn send_chunks(&self) -> VResult<()> {
+        let (position, radius, dimension) = {
+            let player = self.player.read();
+            (
+                player.position.clone(),
+                player.render_distance,
+                player.dimension,
+            )
+        };
+
+        if radius == 0 {
+            return Ok(());
+        }
+
+        self.send(NetworkChunkPublisherUpdate {
+            position: BlockPosition::new(
+                position.x.floor() as i32,
+                position.y.max(0.0) as u32,
+                position.z.floor() as i32,
+            ),
+            radius: (radius as u32) << 4,
+        })?;
+
+        let (center_x, center_z) = chunk_position(&position);
+        let in_range = |x: i32, z: i32| {
+            in_render_distance((center_x, center_z), radius, x, z)
+        };
+
+        // Determine which chunks are missing, closest chunks first.
+        let mut missing = Vec::new();
+        {
+            let mut player = self.player.write();
+            player.sent_chunks.retain(|(x, z)| in_range(*x, *z));
+
+            for x in center_x - radius..=center_x + radius {
+                for z in center_z - radius..=center_z + radius {
+                    if in_range(x, z) && !player.sent_chunks.contains(&(x, z)) {
+                        missing.push((x, z));
+                    }
+                }
+            }
+        }
+        missing.sort_by_key(|(x, z)| {
+            (x - center_x) * (x - center_x) + (z - center_z) * (z - center_z)
+        });
+
+        for (x, z) in missing {
+            self.send_chunk(x, z, dimension)?;
+            self.player.write().sent_chunks.insert((x, z));
+        }
+
+        Ok(())
+    }
+
+    /// Sends a single chunk to the client.
+    ///
+    /// Chunks that do not exist in the level are sent as empty chunks,
+    /// so that the client does not wait for them forever.
+    pub fn send_chunk(
+        &self,
+        x: i32,
+        z: i32,
+        dimension: Dimension,
+    ) -> VResult<()> {
+        let chunk = self
+            .level_manager
+            .get_chunk(x, z, dimension)?
+            .unwrap_or_else(|| Chunk::new(x, z, dimension));
+
+        let registry = self.level_manager.block_registry();
+        let sub_chunk_count = chunk.network_sub_chunk_count();
+        if SERVER_CONFIG.read().sub_chunk_requests {
+            // Only the biomes are sent, the client requests the sub chunks it needs
+            // using sub chunk requests.
+            // Limitless mode lets the client request any sub chunk in the dimension,
+            // sub chunks that are not stored are answered with an empty result.
+            let mut biomes = BytesMut::new();
+            chunk.serialize_network_biomes(&mut biomes);
+
+            let mut raw_payload = BytesMut::new();
+            let blob_hashes = if self.use_blob_cache() {
+                Some(vec![self.add_pending_blob(biomes.freeze())])
+            } else {
+                raw_payload.put(biomes.as_ref());
+                None
+            };
+            // Education Edition border blocks.
+            raw_payload.put_u8(0);
+
+            return self.send(LevelChunk {
+                position: Vector2i::from([x, z]),
+                request_mode: SubChunkRequestMode::Limitless,
+                highest_sub_chunk: 0,
+                sub_chunk_count: 0,
+                blob_hashes,
+                raw_payload,
+            });
+        }
+
+        let mut raw_payload = BytesMut::new();
+        let blob_hashes = if self.use_blob_cache() {
+            // Every sub chunk and the biomes are sent as separate blobs.
+            // The client requests the ones it does not have cached yet.
+            let min = dimension.min_sub_chunk();
+            let mut hashes = Vec::with_capacity(sub_chunk_count + 1);
+            for index in min..min + sub_chunk_count as i8 {
+                let mut blob = BytesMut::new();
+                chunk.serialize_network_sub_chunk(index, registry, &mut blob);
+                hashes.push(self.add_pending_blob(blob.freeze()));
+            }
+
+            let mut blob = BytesMut::new();
+            chunk.serialize_network_biomes(&mut blob);
+            hashes.push(self.add_pending_blob(blob.freeze()));
+
+            // Education Edition border blocks.
+            raw_payload.put_u8(0);
+            chunk.serialize_network_block_entities(None, &mut raw_payload);
+
+            Some(hashes)
+        } else {
+            chunk.serialize_network(registry, &mut raw_payload);
+            None
+        };
+
+        self.send(LevelChunk {
+            position: Vector2i::from([x, z]),
+            request_mode: SubChunkRequestMode::Legacy,
+            highest_sub_chunk: 0,
+            sub_chunk_count: sub_chunk_count as u32,
+            blob_hashes,
+            raw_payload,
+        })
+    }
+
+    /// Handles a [`SubChunkRequest`] packet.
+    ///
+    /// Every requested sub chunk is answered individually, together with its heightmap.
+    pub fn handle_sub_chunk_request(&self, pk: Bytes) -> VResult<()> {
+        let request = SubChunkRequest::deserialize(pk)?;
+        let cache_enabled = self.use_blob_cache();
+
+        let Ok(dimension) = Dimension::try_from(request.dimension) else {
+            let entries = request
+                .offsets
+                .iter()
+                .map(|offset| {
+                    empty_entry(*offset, SubChunkResult::InvalidDimension)
+                })
+                .collect();
+
+            return self.send(SubChunk {
+                cache_enabled,
+                dimension: request.dimension,
+                position: request.position,
+                entries,
+            });
+        };
+
+        let min = dimension.min_sub_chunk() as i32;
+        let max = min + dimension.sub_chunk_count() as i32;
+
+        // Requests usually contain many sub chunks of the same chunk.
+        let mut chunks = HashMap::new();
+        let mut entries = Vec::with_capacity(request.offsets.len());
+        for offset in &request.offsets {
+            let x = request.position.x + offset[0] as i32;
+            let y = request.position.y + offset[1] as i32;
+            let z = request.position.z + offset[2] as i32;
+
+            if !(min..max).contains(&y) {
+                entries.push(empty_entry(
+                    *offset,
+                    SubChunkResult::IndexOutOfBounds,
+                ));
+                continue;
+            }
+
+            let chunk = match chunks.entry((x, z)) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    entry.insert(self.level_manager.get_chunk(x, z, dimension)?)
+                }
+            };
+
+            let Some(chunk) = chunk else {
+                entries
+                    .push(empty_entry(*offset, SubChunkResult::ChunkNotFound));
+                continue;
+            };
+
+            let index = y as i8;
+            let heightmap = sub_chunk_heightmap(chunk, index);
+            let entry = match chunk.sub_chunk(index) {
+                Some(sub_chunk) if !sub_chunk.is_empty() => {
+                    let mut payload = BytesMut::new();
+                    sub_chunk.serialize_network(
+                        self.level_manager.block_registry(),
+                        &mut payload,
+                    );
+
+                    // Block entities are never cached, they are sent after the sub chunk data.
+                    let mut block_entities = BytesMut::new();
+                    chunk.serialize_network_block_entities(
+                        Some(index),
+                        &mut block_entities,
+                    );
+
+                    let (payload, blob_hash) = if cache_enabled {
+                        let hash = self.add_pending_blob(payload.freeze());
+                        (block_entities.freeze(), hash)
+                    } else {
+                        payload.put(block_entities);
+                        (payload.freeze(), 0)
+                    };
+
+                    SubChunkEntry {
+                        offset: *offset,
+                        result: SubChunkResult::Success,
+                        payload,
+                        heightmap,
+                        blob_hash,
+                    }
+                }
+                _ => SubChunkEntry {
+                    heightmap,
+                    ..empty_entry(*offset, SubChunkResult::SuccessAllAir)
+                },
+            };
+
+            entries.push(entry);
+        }
+
+        self.send(SubChunk {
+            cache_enabled,
+            dimension: request.dimension,
+            position: request.position,
+            entries,
+        })
+    }
+
+    /// Handles a [`CacheBlobStatus`] packet.
+    ///
+    /// Blobs that the client is missing are sent in a [`CacheMissResponse`].
+    /// Both hits and misses resolve a single reference to a pending blob.
+    pub fn handle_cache_blob_status(&self, pk: Bytes) -> VResult<()> {
+        let request = CacheBlobStatus::deserialize(pk)?;
+
+        for hash in &request.hits {
+            self.pending_blobs.resolve(*hash);
+        }
+
+        let mut unknown = 0;
+        let mut blobs: Vec<CacheBlob> = Vec::new();
+        for hash in &request.misses {
+            let Some(payload) = self.pending_blobs.resolve(*hash) else {
+                unknown += 1;
+                continue;
+            };
+
+            // Blobs shared by several chunks only have to be sent once.
+            if !blobs.iter().any(|blob| blob.hash == *hash) {
+                blobs.push(CacheBlob { hash: *hash, payload });
+            }
+        }
+
+        if unknown > 0 {
+            tracing::warn!("Client requested {unknown} unknown cache blobs");
+        }
+
+        if !blobs.is_empty() {
+            self.send(CacheMissResponse { blobs: &blobs })?;
+        }
+
+        Ok(())
+    }
+
+    /// Whether the client has enabled the blob cache.
+    #[inline]
+    fn supports_cache(&self) -> bool {
+        self.cache_support.get().copied().unwrap_or(false)
+    }
+
+    /// Whether chunks should be sent as cache blobs.
+    ///
+    /// Clients that leave too many blobs unconfirmed receive chunks without the cache
+    /// until they catch up, so that the pending blobs cannot grow without bounds.
+    #[inline]
+    fn use_blob_cache(&self) -> bool {
+        self.supports_cache() && !self.pending_blobs.is_full()
+    }
+
+    /// Hashes the blob and stores it until the client requests it or confirms it has it cached.
+    fn add_pending_blob(&self, payload: Bytes) -> u64 {
+        self.pending_blobs.insert(CacheBlob::new(payload))
+    }
+}
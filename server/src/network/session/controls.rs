@@ -6,7 +6,7 @@ use crate::network::{
         CameraShake, CameraShakeAction, CameraShakeType, Interact,
         InteractAction, MovePlayer, PlaySound,
     },
-    session::Session,
+    session::{chunk_position, Session},
 };
 
 impl Session {
@@ -18,7 +18,21 @@ impl Session {
 
     pub fn handle_move_player(&self, packet: Bytes) -> VResult<()> {
         let request = MovePlayer::deserialize(packet)?;
+
+        // Stream new chunks when the player moves into another chunk.
+        let changed_chunk = {
+            let mut player = self.player.write();
+            let previous = chunk_position(&player.position);
+            player.position = request.position.clone();
+            player.rotation = request.rotation.clone();
+
+            previous != chunk_position(&player.position)
+        };
+
         self.broadcast_others(request)?;
+        if changed_chunk {
+            self.send_chunks()?;
+        }
 
         Ok(())
     }
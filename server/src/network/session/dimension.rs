@@ -0,0 +1,87 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use common::{bail, Deserialize, VResult, Vector3f};
+use level::Dimension;
+use tokio::sync::oneshot;
+
+use crate::network::packets::login::{PlayStatus, Status};
+use crate::network::packets::{
+    ChangeDimension, PlayerAction, PlayerActionType,
+};
+use crate::network::session::Session;
+
+/// How long the client is given to load a dimension after it has been transferred.
+const DIMENSION_CHANGE_TIMEOUT: Duration = Duration::from_secs(30);
+
+impl Session {
+    pub fn handle_player_action(&self, pk: Bytes) -> VResult<()> {
+        let request = PlayerAction::deserialize(pk)?;
+
+        // Acknowledgements that do not belong to a transfer are ignored,
+        // so that they cannot complete the next one early.
+        if request.action == PlayerActionType::DimensionChangeDone {
+            if let Some(sender) = self.dimension_change.lock().take() {
+                let _ = sender.send(());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Returns the dimension that the player is in.
+    #[inline]
+    pub fn get_dimension(&self) -> Dimension {
+        self.player.read().dimension
+    }
+
+    /// Transfers the player to another dimension.
+    ///
+    /// This sends the chunks around the new position and
+    /// waits until the client has finished loading the dimension.
+    /// The client cannot be transferred to the dimension it is already in.
+    pub async fn change_dimension(
+        &self,
+        dimension: Dimension,
+        position: Vector3f,
+    ) -> VResult<()> {
+        let runtime_id = {
+            let mut player = self.player.write();
+            if player.dimension == dimension {
+                bail!(Other, "Player is already in {:?}", dimension);
+            }
+
+            player.dimension = dimension;
+            player.position = position.clone();
+            // Chunks of the previous dimension are discarded by the client.
+            player.sent_chunks.clear();
+
+            player.runtime_id
+        };
+
+        self.level_manager
+            .set_player_dimension(runtime_id, dimension);
+
+        // A transfer that is still in progress is replaced by this one.
+        let (sender, receiver) = oneshot::channel();
+        *self.dimension_change.lock() = Some(sender);
+
+        self.send(ChangeDimension { dimension, position, respawn: false })?;
+        self.send_chunks()?;
+        self.send(PlayStatus { status: Status::PlayerSpawn })?;
+
+        match tokio::time::timeout(DIMENSION_CHANGE_TIMEOUT, receiver).await {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(_)) => bail!(
+                Aborted,
+                "Transfer to {:?} was replaced by another transfer",
+                dimension
+            ),
+            Err(_) => bail!(
+                Aborted,
+                "Client did not finish loading {:?} in time",
+                dimension
+            ),
+        }
+    }
+}
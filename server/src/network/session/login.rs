@@ -4,9 +4,8 @@ use std::sync::atomic::Ordering;
 
 use bytes::{BufMut, Bytes, BytesMut};
 use jsonwebtoken::jwk::KeyOperations::Encrypt;
-use level::Dimension;
 
-use crate::config::SERVER_CONFIG;
+use crate::config::{LevelGenerator, SERVER_CONFIG};
 use crate::crypto::Encryptor;
 use crate::network::packets::cache::CacheStatus;
 use crate::network::packets::command::AvailableCommands;
@@ -62,6 +61,12 @@ impl Session {
         // Add player to other's player lists.
         tracing::info!("{} has connected", self.get_display_name()?);
 
+        let (runtime_id, dimension) = {
+            let player = self.player.read();
+            (player.runtime_id, player.dimension)
+        };
+        self.level_manager.set_player_dimension(runtime_id, dimension);
+
         // Tell rest of server that this client has joined...
         {
             let identity_data = self.get_identity_data()?;
@@ -108,11 +113,28 @@ impl Session {
     }
 
     /// Handles a [`ChunkRadiusRequest`] packet by returning the maximum allowed render distance.
+    ///
+    /// The chunks within the render distance are sent afterwards.
+    /// The first time this packet is received, the player is also spawned.
     pub fn handle_chunk_radius_request(&self, pk: Bytes) -> VResult<()> {
         let request = ChunkRadiusRequest::deserialize(pk)?;
-        self.send(ChunkRadiusReply {
-            allowed_radius: SERVER_CONFIG.read().allowed_render_distance,
-        })
+        // The configured maximum is not validated, so it is not used as a clamp bound.
+        let allowed_radius = request
+            .radius
+            .min(SERVER_CONFIG.read().allowed_render_distance)
+            .max(1);
+
+        self.send(ChunkRadiusReply { allowed_radius })?;
+
+        self.player.write().render_distance = allowed_radius;
+        self.send_chunks()?;
+
+        if !self.spawn_sent.swap(true, Ordering::SeqCst) {
+            let play_status = PlayStatus { status: Status::PlayerSpawn };
+            self.send(play_status)?;
+        }
+
+        Ok(())
     }
 
     pub fn handle_resource_pack_client_response(
@@ -123,20 +145,64 @@ impl Session {
 
         // TODO: Implement resource packs.
 
+        let level_data = self.level_manager.level_data().clone();
+        let spawn = self.level_manager.spawn_position()?;
+
+        // Returning players continue where they left off,
+        // new players are spawned in the center of the spawn block.
+        if !self.load_player_data()? {
+            self.player.write().position = Vector3f::from([
+                spawn.x as f32 + 0.5,
+                spawn.y as f32,
+                spawn.z as f32 + 0.5,
+            ]);
+        }
+
+        let (position, rotation) = {
+            let player = self.player.read();
+            (
+                player.position.clone(),
+                Vector2f::from([player.rotation.x, player.rotation.y]),
+            )
+        };
+
+        // The custom spawn biome must be known by the client.
+        let spawn_biome = SERVER_CONFIG.read().spawn_biome.clone();
+        let spawn_biome = spawn_biome.filter(|name| {
+            let exists = self.level_manager.biome_registry().contains(name);
+            if !exists {
+                tracing::warn!("Spawn biome {name} does not exist, using the default biome instead");
+            }
+            exists
+        });
+
+        // Void worlds are flat worlds without any layers.
+        let generator = match SERVER_CONFIG.read().generator {
+            LevelGenerator::Flat(_) | LevelGenerator::Void => WorldGenerator::Flat,
+            LevelGenerator::None | LevelGenerator::Terrain => WorldGenerator::Infinite,
+        };
+
         let start_game = StartGame {
             entity_id: 1,
             runtime_id: 1,
             game_mode: self.get_game_mode(),
-            position: Vector3f::from([0.0, 50.0, 0.0]),
-            rotation: Vector2f::from([0.0, 0.0]),
-            world_seed: 69420,
-            spawn_biome_type: SpawnBiomeType::Default,
-            custom_biome_name: "plains",
-            dimension: Dimension::Overworld,
-            generator: WorldGenerator::Infinite,
-            world_game_mode: GameMode::Creative,
-            difficulty: Difficulty::Normal,
-            world_spawn: BlockPosition::new(0, 50, 0),
+            position,
+            rotation,
+            world_seed: level_data.seed as u64,
+            spawn_biome_type: if spawn_biome.is_some() {
+                SpawnBiomeType::Custom
+            } else {
+                SpawnBiomeType::Default
+            },
+            custom_biome_name: spawn_biome.as_deref().unwrap_or(""),
+            dimension: self.get_dimension(),
+            generator,
+            world_game_mode: GameMode::try_from(level_data.game_type)
+                .unwrap_or(GameMode::Creative),
+            difficulty: Difficulty::try_from(level_data.difficulty)
+                .unwrap_or(Difficulty::Normal),
+            // Negative Y coordinates are sent as their two's complement.
+            world_spawn: BlockPosition::new(spawn.x, spawn.y as u32, spawn.z),
             achievements_disabled: true,
             editor_world: false,
             day_cycle_lock_time: 0,
@@ -172,35 +238,39 @@ impl Session {
             chat_restriction_level: ChatRestrictionLevel::None,
             disable_player_interactions: false,
             level_id: "",
-            level_name: "World name",
+            level_name: &level_data.level_name,
             template_content_identity: "",
             movement_settings: PlayerMovementSettings {
                 movement_type: PlayerMovementType::ClientAuthoritative,
                 rewind_history_size: 0,
                 server_authoritative_breaking: true,
             },
-            time: 0,
+            time: level_data.time,
             enchantment_seed: 0,
             block_properties: &[],
-            item_properties: &[],
+            item_properties: self.level_manager.item_registry().entries(),
             server_authoritative_inventory: false,
             game_version: "1.19.60",
             property_data: nbt::Value::Compound(HashMap::new()),
-            server_block_state_checksum: 0,
+            server_block_state_checksum: self
+                .level_manager
+                .block_registry()
+                .checksum(),
             world_template_id: 0,
             client_side_generation: false,
         };
         self.send(start_game)?;
 
-        let creative_content = CreativeContent { items: &[] };
+        let creative_content = CreativeContent {
+            items: self.level_manager.item_registry().creative_items(),
+        };
         self.send(creative_content)?;
 
-        let biome_definition_list = BiomeDefinitionList;
+        let biome_definition_list = BiomeDefinitionList {
+            definitions: self.level_manager.biome_registry().definitions(),
+        };
         self.send(biome_definition_list)?;
 
-        let play_status = PlayStatus { status: Status::PlayerSpawn };
-        self.send(play_status)?;
-
         let commands = self
             .level_manager
             .get_commands()
@@ -262,10 +332,10 @@ impl Session {
         self.user_data.set(request.user_data)?;
         self.player.write().skin = Some(request.skin);
 
-        // Flush packets before enabling encryption
-        self.flush().await?;
-
+        // The handshake itself is not encrypted,
+        // encode the current batch before enabling encryption.
         self.send(ServerToClientHandshake { jwt: &jwt })?;
+        self.flush_packet_batch()?;
         self.encryptor.set(encryptor)?;
 
         Ok(())
@@ -307,7 +377,10 @@ impl Session {
             }
         };
 
+        // Network settings are not compressed,
+        // encode the current batch before enabling compression.
         self.send(response)?;
+        self.flush_packet_batch()?;
         self.raknet
             .compression_enabled
             .store(true, Ordering::SeqCst);
@@ -168,6 +168,14 @@ impl SessionManager {
         Ok(())
     }
 
+    /// Returns all sessions that are currently tracked.
+    pub fn sessions(&self) -> Vec<Arc<Session>> {
+        self.list
+            .iter()
+            .map(|entry| entry.value().1.clone())
+            .collect()
+    }
+
     /// Returns how many clients are currently connected this tracker.
     #[inline]
     pub fn session_count(&self) -> usize {
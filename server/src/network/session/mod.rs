@@ -3,5 +3,9 @@ use common::glob_export;
 glob_export!(session);
 glob_export!(manager);
 glob_export!(login);
+glob_export!(block_entity);
+glob_export!(chunks);
 glob_export!(controls);
+glob_export!(dimension);
+glob_export!(persistence);
 glob_export!(util);
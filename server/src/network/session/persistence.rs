@@ -0,0 +1,56 @@
+use common::{VResult, Vector3f};
+use level::PlayerRecord;
+
+use crate::network::packets::login::PermissionLevel;
+use crate::network::packets::GameMode;
+use crate::network::session::Session;
+
+impl Session {
+    /// Restores the data of a player that has joined before.
+    ///
+    /// Returns whether the player has been restored.
+    pub fn load_player_data(&self) -> VResult<bool> {
+        let uuid = self.get_uuid()?.to_string();
+        let xuid = self.get_xuid()?;
+
+        let Some(record) =
+            PlayerRecord::load(self.level_manager.database(), xuid, &uuid)?
+        else {
+            return Ok(false);
+        };
+
+        let mut player = self.player.write();
+        player.position = record.position;
+        player.rotation =
+            Vector3f::from([record.pitch, record.yaw, record.yaw]);
+        player.dimension = record.dimension;
+        player.game_mode = GameMode::try_from(record.game_mode)?;
+        player.permission_level =
+            PermissionLevel::try_from(record.permission_level)?;
+
+        Ok(true)
+    }
+
+    /// Writes the data of the player to the world database.
+    ///
+    /// Tags that are not managed by the server, such as the inventory, are kept.
+    pub fn save_player_data(&self) -> VResult<()> {
+        let uuid = self.get_uuid()?.to_string();
+        let xuid = self.get_xuid()?;
+        let database = self.level_manager.database();
+
+        let mut record =
+            PlayerRecord::load(database, xuid, &uuid)?.unwrap_or_default();
+        {
+            let player = self.player.read();
+            record.position = player.position.clone();
+            record.pitch = player.rotation.x;
+            record.yaw = player.rotation.y;
+            record.dimension = player.dimension;
+            record.game_mode = player.game_mode as i32;
+            record.permission_level = player.permission_level as i32;
+        }
+
+        record.save(database, xuid, &uuid)
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::HashSet;
 use std::net::SocketAddr;
 use std::num::NonZeroU64;
 use std::sync::atomic::{
@@ -10,7 +11,7 @@ use aes::cipher::typenum::NonZero;
 use bytes::{Bytes, BytesMut};
 use parking_lot::{Mutex, RwLock, RwLockReadGuard};
 use tokio::net::UdpSocket;
-use tokio::sync::{broadcast, mpsc, OnceCell};
+use tokio::sync::{broadcast, mpsc, oneshot, OnceCell};
 use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
@@ -22,10 +23,13 @@ use crate::network::packets::{
     ConnectedPacket, GameMode, MessageType, Packet, PlayerListRemove,
     TextMessage,
 };
-use crate::network::raknet::{BroadcastPacket, RaknetData};
-use crate::network::Skin;
+use crate::network::raknet::{
+    BroadcastPacket, CongestionController, PacketBatch, RaknetData,
+};
+use crate::network::{PendingBlobs, Skin};
 use common::{bail, Serialize, Vector3f};
 use common::{error, VResult};
+use level::Dimension;
 
 use super::SessionManager;
 
@@ -47,6 +51,14 @@ pub struct PlayerData {
     pub skin: Option<Skin>,
     /// Runtime ID.
     pub runtime_id: u64,
+    /// Render distance in chunks, as agreed on with the client.
+    /// This is 0 until the client has requested a chunk radius.
+    pub render_distance: i32,
+    /// Dimension the player is in.
+    pub dimension: Dimension,
+    /// Chunks that have been sent to the client and are within its render distance.
+    /// These are in the dimension the player is in.
+    pub sent_chunks: HashSet<(i32, i32)>,
 }
 
 /// Sessions directly correspond to clients connected to the server.
@@ -63,9 +75,18 @@ pub struct Session {
     pub encryptor: OnceCell<Encryptor>,
     /// Whether the client supports the chunk cache.
     pub cache_support: OnceCell<bool>,
+    /// Blobs that have been announced to the client, but have not been confirmed yet.
+    /// These are sent when the client reports them as missing.
+    pub pending_blobs: PendingBlobs,
     /// Whether the client has fully been initialised.
     /// This is set to true after receiving the [`SetLocalPlayerAsInitialized`](crate::network::packets::SetLocalPlayerAsInitialized) packet
     pub initialized: AtomicBool,
+    /// Whether the client has been told to spawn the player.
+    /// This happens after the first chunk radius request.
+    pub spawn_sent: AtomicBool,
+    /// Notified when the client has finished loading the dimension it is being transferred to.
+    /// This is only set while a transfer is in progress.
+    pub dimension_change: Mutex<Option<oneshot::Sender<()>>>,
     /// Manages entire world.
     pub level_manager: Arc<LevelManager>,
     /// Sends packets into the broadcasting channel.
@@ -98,7 +119,10 @@ impl Session {
             user_data: OnceCell::new(),
             encryptor: OnceCell::new(),
             cache_support: OnceCell::new(),
+            pending_blobs: PendingBlobs::default(),
             initialized: AtomicBool::new(false),
+            spawn_sent: AtomicBool::new(false),
+            dimension_change: Mutex::new(None),
             broadcast,
             level_manager,
 
@@ -112,6 +136,9 @@ impl Session {
                 game_mode: GameMode::Survival,
                 permission_level: PermissionLevel::Member,
                 skin: None,
+                render_distance: 0,
+                dimension: Dimension::Overworld,
+                sent_chunks: HashSet::new(),
             }),
             raknet: RaknetData {
                 udp_socket: ipv4_socket,
@@ -128,8 +155,10 @@ impl Session {
                 send_queue: Default::default(),
                 confirmed_packets: Mutex::new(Vec::new()),
                 compression_enabled: AtomicBool::new(false),
+                packet_batch: Mutex::new(PacketBatch::default()),
                 address,
                 recovery_queue: Default::default(),
+                congestion: Mutex::new(CongestionController::new(mtu as usize)),
             },
         });
 
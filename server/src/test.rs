@@ -1,16 +1,48 @@
+use std::collections::HashMap;
 use std::io::{Read, Write};
 use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use base64::Engine;
-use bytes::{Buf, BufMut, BytesMut};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use flate2::read::DeflateDecoder;
-use tokio::net::windows::named_pipe::PipeMode::Byte;
+use tokio::net::UdpSocket;
+use tokio::sync::{broadcast, mpsc};
+use tokio_util::sync::CancellationToken;
 
+use crate::biome_registry::{BiomeDefinition, BiomeRegistry};
+use crate::config::SERVER_CONFIG;
 use crate::instance_manager::{IPV4_LOCAL_ADDR, IPV6_LOCAL_ADDR};
-use crate::network::raknet::{Frame, OrderChannel};
-use crate::network::Header;
-use common::{ReadExtensions, WriteExtensions};
+use crate::item_registry::ItemRegistry;
+use crate::level_manager::{LevelManager, BLOCK_STATES};
+use crate::network::packets::cache::CacheMissResponse;
+use crate::network::packets::login::{
+    ChunkRadiusReply, CompressionAlgorithm, CreativeContent, ItemStack,
+    ItemType, NO_COMPRESSION_PREFIX,
+};
+use crate::network::packets::{
+    BlockActorData, ConnectedPacket, GameRule, Packet, PlayerAction,
+    PlayerActionType, SubChunk, SubChunkEntry, SubChunkHeightmap,
+    SubChunkRequest, SubChunkResult, CONNECTED_PACKET_ID,
+};
+use crate::network::raknet::packets::AckRecord;
+use crate::network::raknet::{
+    decode_batch, encode_batch, CompoundCollector, CongestionController, Frame,
+    FrameBatch, OrderChannel, PacketBatch, RecoveryQueue, Reliability,
+    INITIAL_RTO, UDP_HEADER_SIZE,
+};
+use crate::network::session::{Session, SessionManager};
+use crate::network::{CacheBlob, Header, PendingBlobs, MAX_PENDING_BLOBS};
+use common::{
+    BlockPosition, Deserialize, ReadExtensions, Vector3f, Vector3i,
+    WriteExtensions,
+};
 use common::{Serialize, VResult};
+use level::{
+    BlockRegistry, Dimension, LevelData, RuntimeIdMode, SPAWN_Y_UNDEFINED,
+};
 
 #[test]
 fn read_write_header() {
@@ -44,3 +76,625 @@ fn order_channel() {
     assert_eq!(output[0].order_index, 1);
     assert_eq!(output[1].order_index, 2);
 }
+
+#[test]
+fn cache_miss_response() {
+    // xxHash64 of an empty input with a seed of 0.
+    assert_eq!(CacheBlob::new(Bytes::new()).hash, 0xef46db3751d8e999);
+
+    let blob = CacheBlob::new(Bytes::from_static(&[1, 2, 3]));
+    let response = CacheMissResponse { blobs: std::slice::from_ref(&blob) };
+
+    let mut buffer = BytesMut::new();
+    response.serialize(&mut buffer);
+
+    let mut buffer = buffer.freeze();
+    assert_eq!(buffer.get_var_u32().unwrap(), 1);
+    assert_eq!(buffer.get_u64_le(), blob.hash);
+    assert_eq!(buffer.get_var_u32().unwrap(), 3);
+    assert_eq!(buffer.as_ref(), &[1, 2, 3]);
+}
+
+#[test]
+fn pending_blobs() {
+    let pending = PendingBlobs::default();
+    let shared = Bytes::from_static(&[1, 2, 3]);
+
+    // Two chunks share the same sub chunk.
+    let hash = pending.insert(CacheBlob::new(shared.clone()));
+    assert_eq!(pending.insert(CacheBlob::new(shared.clone())), hash);
+    let other = pending.insert(CacheBlob::new(Bytes::from_static(&[4])));
+
+    // A hit for the first chunk must not drop the blob requested by the second.
+    assert_eq!(pending.resolve(hash), Some(shared.clone()));
+    assert_eq!(pending.resolve(hash), Some(shared));
+    assert_eq!(pending.resolve(hash), None);
+    assert!(pending.resolve(other).is_some());
+
+    for i in 0..MAX_PENDING_BLOBS as u32 {
+        assert!(!pending.is_full());
+        pending
+            .insert(CacheBlob::new(Bytes::copy_from_slice(&i.to_le_bytes())));
+    }
+    assert!(pending.is_full());
+}
+
+#[test]
+fn sub_chunk_request() {
+    let mut buffer = BytesMut::new();
+    buffer.put_var_i32(0);
+    buffer.put_vec3i(&Vector3i::from([1, -4, 2]));
+    buffer.put_u32_le(2);
+    buffer.put_slice(&[0, 0, 0, 255, 1, 0]);
+
+    let request = SubChunkRequest::deserialize(buffer.freeze()).unwrap();
+    assert_eq!(request.dimension, 0);
+    assert_eq!(request.position.components(), [1, -4, 2]);
+    assert_eq!(request.offsets, vec![[0, 0, 0], [-1, 1, 0]]);
+
+    // Truncated requests are rejected instead of panicking.
+    let mut buffer = BytesMut::new();
+    buffer.put_var_i32(0);
+    buffer.put_vec3i(&Vector3i::from([1, -4, 2]));
+    buffer.put_u16_le(2);
+    assert!(SubChunkRequest::deserialize(buffer.freeze()).is_err());
+
+    let mut buffer = BytesMut::new();
+    buffer.put_var_i32(0);
+    buffer.put_vec3i(&Vector3i::from([1, -4, 2]));
+    buffer.put_u32_le(u32::MAX);
+    buffer.put_slice(&[0, 0, 0]);
+    assert!(SubChunkRequest::deserialize(buffer.freeze()).is_err());
+
+    let response = SubChunk {
+        cache_enabled: true,
+        dimension: 0,
+        position: request.position,
+        entries: vec![
+            SubChunkEntry {
+                offset: [0, 0, 0],
+                result: SubChunkResult::Success,
+                payload: Bytes::new(),
+                heightmap: SubChunkHeightmap::Data(Box::new([-1; 256])),
+                blob_hash: 1,
+            },
+            SubChunkEntry {
+                offset: [-1, 1, 0],
+                result: SubChunkResult::SuccessAllAir,
+                payload: Bytes::new(),
+                heightmap: SubChunkHeightmap::TooLow,
+                blob_hash: 0,
+            },
+        ],
+    };
+
+    let mut buffer = BytesMut::new();
+    response.serialize(&mut buffer);
+    // The payload of the all air sub chunk is omitted.
+    assert_eq!(buffer.len(), response.serialized_size());
+    assert_eq!(
+        buffer.len(),
+        1 + 1 + 3 + 4 + (4 + 1 + 257 + 8) + (4 + 1 + 8)
+    );
+}
+
+#[test]
+fn item_registry() {
+    let blocks = BlockRegistry::new(
+        Bytes::from_static(BLOCK_STATES),
+        RuntimeIdMode::Sequential,
+    )
+    .unwrap();
+    let items = ItemRegistry::new(&blocks).unwrap();
+
+    let stone = items.runtime_id("minecraft:stone").unwrap();
+    assert_eq!(items.name(stone), Some("minecraft:stone"));
+    assert_eq!(
+        items.block_runtime_id(stone),
+        blocks.default_runtime_id("minecraft:stone")
+    );
+
+    let apple = items.runtime_id("minecraft:apple").unwrap();
+    assert_eq!(items.block_runtime_id(apple), None);
+
+    let content = CreativeContent { items: items.creative_items() };
+    let mut buffer = BytesMut::new();
+    content.serialize(&mut buffer);
+
+    let mut buffer = buffer.freeze();
+    assert_eq!(
+        buffer.get_var_u32().unwrap() as usize,
+        items.creative_items().len()
+    );
+    // Creative network IDs start at 1.
+    assert_eq!(buffer.get_var_u32().unwrap(), 1);
+
+    // The extra data is little endian NBT, its size must match what is written.
+    let shield = items.runtime_id("minecraft:shield").unwrap();
+    let stack = ItemStack {
+        item_type: ItemType { network_id: shield as i32, metadata: 0 },
+        runtime_id: 0,
+        count: 1,
+        nbt_data: nbt::Value::Compound(HashMap::from([(
+            "Damage".to_owned(),
+            nbt::Value::Int(1000),
+        )])),
+        can_be_placed_on: vec!["minecraft:stone".to_owned()],
+        can_break: Vec::new(),
+        has_network_id: true,
+        blocking_tick: Some(0),
+    };
+    let mut buffer = BytesMut::new();
+    stack.serialize(&mut buffer);
+    assert_eq!(stack.serialized_size(), buffer.len());
+}
+
+#[test]
+fn biome_registry() {
+    let mut biomes = BiomeRegistry::new().unwrap();
+    assert_eq!(biomes.get("plains").unwrap().id, 1);
+    assert_eq!(biomes.get_by_id(8).unwrap().name, "hell");
+
+    let custom = BiomeDefinition {
+        id: 200,
+        name: "nova:test".to_owned(),
+        temperature: 0.5,
+        downfall: 0.0,
+        water_colour: 0xff000000,
+        tags: vec!["overworld".to_owned()],
+    };
+    biomes.register(custom.clone()).unwrap();
+    assert!(biomes.contains("nova:test"));
+    // Names and IDs must be unique.
+    assert!(biomes.register(custom).is_err());
+
+    let nbt::Value::Compound(definitions) = biomes.definitions() else {
+        panic!("Biome definitions must be a compound");
+    };
+    assert_eq!(definitions.len(), biomes.len());
+    assert!(matches!(
+        definitions.get("nova:test"),
+        Some(nbt::Value::Compound(d)) if d.get("rain") == Some(&nbt::Value::Byte(0))
+    ));
+    // Vanilla definitions are sent unchanged.
+    assert!(matches!(
+        definitions.get("plains"),
+        Some(nbt::Value::Compound(d)) if d.len() == 2
+    ));
+
+    // Custom biomes are registered in order of their file names.
+    let pack = std::env::temp_dir().join("nova_biome_pack");
+    std::fs::create_dir_all(pack.join("biomes")).unwrap();
+    for name in ["b", "a", "c"] {
+        std::fs::write(
+            pack.join("biomes").join(format!("{name}.json")),
+            format!(
+                r#"{{"minecraft:biome": {{"description": {{"identifier": "nova:{name}"}}}}}}"#
+            ),
+        )
+        .unwrap();
+    }
+
+    let mut biomes = BiomeRegistry::new().unwrap();
+    assert_eq!(biomes.load_behavior_pack(&pack).unwrap(), 3);
+    std::fs::remove_dir_all(&pack).unwrap();
+
+    let a = biomes.get("nova:a").unwrap().id;
+    assert_eq!(biomes.get("nova:b").unwrap().id, a + 1);
+    assert_eq!(biomes.get("nova:c").unwrap().id, a + 2);
+}
+
+/// Encodes a [`PlayerAction`] that reports a finished dimension change.
+fn dimension_change_done() -> Bytes {
+    let mut buffer = BytesMut::new();
+    buffer.put_var_u64(1);
+    buffer.put_var_i32(14);
+    for _ in 0..2 {
+        buffer.put_block_pos(&BlockPosition::new(-1, 64, 3));
+    }
+    buffer.put_var_i32(0);
+    buffer.freeze()
+}
+
+#[test]
+fn player_action() {
+    let action = PlayerAction::deserialize(dimension_change_done()).unwrap();
+    assert_eq!(action.runtime_id, 1);
+    assert_eq!(action.action, PlayerActionType::DimensionChangeDone);
+    assert_eq!(action.result_position.y, 64);
+}
+
+#[test]
+fn block_actor_data() {
+    let position = BlockPosition::new(-7, -20i32 as u32, 12);
+    let mut nbt = HashMap::new();
+    nbt.insert("id".to_owned(), nbt::Value::String("Sign".to_owned()));
+    let update = BlockActorData { position, nbt: nbt::Value::Compound(nbt) };
+
+    let mut buffer = BytesMut::new();
+    update.serialize(&mut buffer);
+    assert_eq!(buffer.len(), update.serialized_size());
+
+    let mut header = buffer.clone().freeze();
+    assert_eq!(header.get_var_i32().unwrap(), -7);
+    assert_eq!(header.get_var_u32().unwrap() as i32, -20);
+
+    let decoded = BlockActorData::deserialize(buffer.freeze()).unwrap();
+    assert_eq!(decoded.position.y as i32, -20);
+    assert_eq!(decoded.position.z, 12);
+}
+
+#[test]
+fn compression() {
+    let mut packet = BytesMut::new();
+    for i in 0..1000u32 {
+        packet.put_var_u32(i % 37);
+    }
+
+    for algorithm in
+        [CompressionAlgorithm::Deflate, CompressionAlgorithm::Snappy]
+    {
+        let compressed = algorithm.compress(&packet, 6).unwrap();
+        assert!(compressed.len() < packet.len());
+        assert_eq!(algorithm.decompress(&compressed).unwrap(), packet);
+    }
+
+    // Snappy data is not framed, it starts with the decompressed length.
+    let snappy = Bytes::from_static(b"\x05\x10hello");
+    assert_eq!(
+        CompressionAlgorithm::Snappy.decompress(&snappy).unwrap(),
+        "hello"
+    );
+    assert!(CompressionAlgorithm::Snappy
+        .decompress(b"\x05\x10he")
+        .is_err());
+
+    for algorithm in
+        [CompressionAlgorithm::Deflate, CompressionAlgorithm::Snappy]
+    {
+        let prefix = algorithm.prefix();
+        assert!(matches!(
+            CompressionAlgorithm::from_prefix(prefix),
+            Ok(Some(a)) if a.prefix() == prefix
+        ));
+    }
+    assert!(matches!(
+        CompressionAlgorithm::from_prefix(NO_COMPRESSION_PREFIX),
+        Ok(None)
+    ));
+    assert!(CompressionAlgorithm::from_prefix(2).is_err());
+}
+
+#[test]
+fn packet_batch() {
+    let mut batch = PacketBatch::default();
+    assert!(batch.take().is_none());
+
+    for radius in 1..=3 {
+        let packet = Packet::new(ChunkRadiusReply { allowed_radius: radius });
+        batch.push(packet.serialize(), true);
+    }
+
+    let (packets, compress) = batch.take().unwrap();
+    assert!(compress);
+    assert!(batch.take().is_none());
+
+    for compression_enabled in [false, true] {
+        let encoded =
+            encode_batch(packets.clone(), compress, compression_enabled, None)
+                .unwrap();
+        assert_eq!(encoded[0], CONNECTED_PACKET_ID);
+
+        let decoded = decode_batch(encoded, compression_enabled, None).unwrap();
+        assert_eq!(decoded.len(), 3);
+        for (radius, mut packet) in (1..=3).zip(decoded) {
+            let header = Header::deserialize(&mut packet).unwrap();
+            assert_eq!(header.id, ChunkRadiusReply::ID);
+            assert_eq!(packet.get_var_i32().unwrap(), radius);
+        }
+    }
+
+    // The last packet claims to be longer than the rest of the batch.
+    let mut truncated = BytesMut::from(packets.as_ref());
+    truncated.put_var_u32(10);
+    truncated.put_u8(ChunkRadiusReply::ID as u8);
+    let encoded = encode_batch(truncated.freeze(), false, false, None).unwrap();
+    assert!(decode_batch(encoded, false, None).is_err());
+}
+
+#[test]
+fn rtt_estimate() {
+    let mut congestion = CongestionController::new(1400);
+    assert_eq!(congestion.rtt(), None);
+    assert_eq!(congestion.retransmission_timeout(), INITIAL_RTO);
+
+    congestion.on_rtt_sample(Duration::from_millis(100));
+    assert_eq!(congestion.rtt(), Some(Duration::from_millis(100)));
+    // 2 * 100 + 4 * 50 + 30
+    assert_eq!(
+        congestion.retransmission_timeout(),
+        Duration::from_millis(430)
+    );
+
+    // The variation shrinks when the round-trip time is stable.
+    for _ in 0..50 {
+        congestion.on_rtt_sample(Duration::from_millis(100));
+    }
+    let timeout = congestion.retransmission_timeout();
+    assert!(timeout < Duration::from_millis(240), "{timeout:?}");
+    assert!(timeout >= Duration::from_millis(230), "{timeout:?}");
+
+    // Very slow connections are capped.
+    congestion.on_rtt_sample(Duration::from_secs(30));
+    assert_eq!(
+        congestion.retransmission_timeout(),
+        Duration::from_millis(2000)
+    );
+}
+
+/// Simulates a connection that drops every third batch without reporting it.
+#[test]
+fn congestion_loss() {
+    const MTU: usize = 1400;
+
+    let batch = |sequence_number| FrameBatch {
+        sequence_number,
+        frames: vec![Frame::new(
+            Reliability::Reliable,
+            Bytes::from(vec![0; 1000]),
+        )],
+    };
+
+    let mut congestion = CongestionController::new(MTU);
+    let queue = RecoveryQueue::new();
+    let start = Instant::now();
+
+    // Slow start: the window grows by one MTU per acknowledged batch.
+    for i in 0..8 {
+        queue.insert(batch(i), start);
+    }
+    let in_flight = queue.bytes_in_flight();
+    assert!(in_flight >= 8000);
+    assert_eq!(congestion.transmission_budget(in_flight), 0);
+
+    let lost: Vec<u32> = (0..8).filter(|i| i % 3 == 0).collect();
+    let records: Vec<AckRecord> = (0..8)
+        .filter(|i| !lost.contains(i))
+        .map(AckRecord::Single)
+        .collect();
+
+    let confirmed = queue.confirm(&records, start + Duration::from_millis(50));
+    assert_eq!(confirmed.len(), 5);
+    for batch in confirmed {
+        assert_eq!(batch.rtt, Some(Duration::from_millis(50)));
+        congestion.on_rtt_sample(batch.rtt.unwrap());
+        congestion.on_ack();
+    }
+    assert!(congestion.in_slow_start());
+    assert_eq!(congestion.window(), MTU * 6);
+
+    // Lost batches are not resent before the timeout.
+    let timeout = congestion.retransmission_timeout();
+    assert!(queue
+        .expired(timeout, start + timeout - Duration::from_millis(1))
+        .is_empty());
+
+    let resent = start + timeout;
+    let expired = queue.expired(timeout, resent);
+    let expired: Vec<u32> = expired.iter().map(|b| b.sequence_number).collect();
+    assert_eq!(expired, lost);
+
+    // Timeouts reset the window and halve the slow start threshold.
+    congestion.on_resend();
+    assert_eq!(congestion.window(), MTU);
+    assert!(congestion.in_slow_start());
+
+    // Resent batches are not used as round-trip time samples.
+    // Ranges include their end.
+    let confirmed = queue.confirm(
+        &[AckRecord::Range(0..3), AckRecord::Single(6)],
+        resent + Duration::from_millis(20),
+    );
+    assert_eq!(confirmed.len(), 3);
+    assert!(confirmed.iter().all(|batch| batch.rtt.is_none()));
+    assert_eq!(queue.bytes_in_flight(), 0);
+
+    // Past the threshold, the window grows by less than an MTU per batch.
+    for _ in 0..3 {
+        congestion.on_ack();
+    }
+    assert_eq!(congestion.window(), MTU * 4);
+    assert!(!congestion.in_slow_start());
+
+    let window = congestion.window();
+    congestion.on_ack();
+    assert!(congestion.window() - window < MTU);
+
+    // Lost batches reported by the client are resent immediately.
+    queue.insert(batch(8), resent);
+    let recovered = queue
+        .recover(&[AckRecord::Single(8)], resent + Duration::from_millis(1));
+    assert_eq!(recovered.len(), 1);
+    assert_eq!(recovered[0].sequence_number, 8);
+}
+
+#[test]
+fn fragmentation() {
+    const MTU: usize = 1400;
+    let max_size = MTU - UDP_HEADER_SIZE - FrameBatch::HEADER_SIZE;
+
+    let body: Vec<u8> = (0..5000).map(|i| i as u8).collect();
+    let frame = Frame {
+        reliability: Reliability::ReliableOrdered,
+        order_index: 7,
+        body: Bytes::from(body.clone()),
+        ..Default::default()
+    };
+    assert!(frame.serialized_size() > max_size);
+
+    let fragments = frame.split(max_size, 3).unwrap();
+    assert_eq!(fragments.len(), 4);
+
+    let collector = CompoundCollector::new();
+    for (index, fragment) in fragments.iter().enumerate().rev() {
+        assert!(fragment.serialized_size() <= max_size);
+        assert!(fragment.is_compound);
+        assert_eq!(fragment.compound_id, 3);
+        assert_eq!(fragment.compound_index, index as u32);
+        assert_eq!(fragment.compound_size, 4);
+        assert_eq!(fragment.order_index, 7);
+
+        // Send every fragment in its own batch.
+        let batch = FrameBatch {
+            sequence_number: 0,
+            frames: vec![fragment.clone()],
+        };
+        let mut serialized = BytesMut::new();
+        batch.serialize(&mut serialized);
+        assert_eq!(serialized.len(), batch.serialized_size());
+        assert!(serialized.len() <= MTU - UDP_HEADER_SIZE);
+
+        let mut received =
+            FrameBatch::deserialize(serialized.freeze()).unwrap();
+        let merged = collector.insert(received.frames.remove(0));
+        if index == 0 {
+            let merged = merged.expect("Compound should be complete");
+            assert!(!merged.is_compound);
+            assert_eq!(merged.order_index, 7);
+            assert_eq!(merged.body, body);
+        } else {
+            assert!(merged.is_none());
+        }
+    }
+
+    // Fragments are always reliable.
+    let frame = Frame::new(Reliability::Unreliable, Bytes::from(body));
+    let fragments = frame.split(max_size, 4).unwrap();
+    assert!(fragments
+        .iter()
+        .all(|f| f.reliability == Reliability::Reliable));
+
+    // The MTU must leave room for the fragment header.
+    let header_size = Frame::header_size(Reliability::Reliable, true);
+    assert!(frame.split(header_size, 5).is_err());
+    assert_eq!(frame.split(header_size + 1, 5).unwrap().len(), 5000);
+}
+
+/// Creates the directory of a temporary level, containing the given level data.
+fn level_dir(name: &str, level_data: Option<LevelData>) -> PathBuf {
+    let path = std::env::temp_dir()
+        .join(format!("nova-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(&path).unwrap();
+    if let Some(level_data) = level_data {
+        level_data.write(path.join("level.dat")).unwrap();
+    }
+
+    path
+}
+
+/// Loads the level in the given directory.
+fn load_level(path: &Path, token: &CancellationToken) -> Arc<LevelManager> {
+    // The level path is read from the global config.
+    static CONFIG_LOCK: Mutex<()> = Mutex::new(());
+    let _guard = CONFIG_LOCK.lock().unwrap();
+
+    SERVER_CONFIG.write().level_path =
+        path.join("db").to_str().unwrap().to_owned();
+    let session_manager = Arc::new(SessionManager::new(token.clone()));
+    LevelManager::new(session_manager, token.clone()).unwrap().0
+}
+
+#[tokio::test]
+async fn level_ticks() {
+    let mut level_data = LevelData::new("Ticks", 0);
+    level_data.spawn = Vector3i::from([3, SPAWN_Y_UNDEFINED, -5]);
+    level_data.current_tick = 100;
+    let path = level_dir("ticks", Some(level_data));
+
+    let token = CancellationToken::new();
+    let level_manager = load_level(&path, &token);
+
+    // The spawn is placed on top of the default flat world.
+    let spawn = level_manager.spawn_position().unwrap();
+    assert_eq!((spawn.x, spawn.y, spawn.z), (3, -60, -5));
+
+    let time = level_manager.level_data().time;
+    let tick = level_manager.current_tick();
+    level_manager.tick();
+    level_manager.set_game_rule(GameRule::DaylightCycle(false));
+    level_manager.tick();
+    assert_eq!(level_manager.current_tick(), tick + 2);
+    assert_eq!(level_manager.level_data().time, time + 1);
+
+    level_manager.flush().unwrap();
+    let saved = LevelData::read(path.join("level.dat")).unwrap().unwrap();
+    assert_eq!(saved.current_tick as u64, level_manager.current_tick());
+
+    token.cancel();
+    let _ = std::fs::remove_dir_all(path);
+}
+
+#[tokio::test]
+async fn change_dimension() {
+    let path = level_dir("dimension", None);
+    let token = CancellationToken::new();
+    let level_manager = load_level(&path, &token);
+
+    // Packets are sent to the server socket itself.
+    let socket = Arc::new(UdpSocket::bind("127.0.0.1:0").await.unwrap());
+    let address = socket.local_addr().unwrap();
+    let (broadcast, _) = broadcast::channel(1);
+    let (_sender, receiver) = mpsc::channel(1);
+    let session = Session::new(
+        broadcast,
+        receiver,
+        level_manager.clone(),
+        socket,
+        address,
+        1400,
+        0,
+    );
+
+    let runtime_id = session.player.read().runtime_id;
+    level_manager.set_player_dimension(runtime_id, Dimension::Overworld);
+
+    // An acknowledgement of an earlier transfer must not complete the next one.
+    session
+        .handle_player_action(dimension_change_done())
+        .unwrap();
+
+    let transfer = tokio::spawn({
+        let session = session.clone();
+        async move {
+            session
+                .change_dimension(
+                    Dimension::Nether,
+                    Vector3f::from([0.0, 64.0, 0.0]),
+                )
+                .await
+        }
+    });
+
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!transfer.is_finished());
+    assert_eq!(session.get_dimension(), Dimension::Nether);
+    assert!(level_manager
+        .dimension(Dimension::Nether)
+        .contains_player(runtime_id));
+    assert!(!level_manager
+        .dimension(Dimension::Overworld)
+        .contains_player(runtime_id));
+
+    session
+        .handle_player_action(dimension_change_done())
+        .unwrap();
+    transfer.await.unwrap().unwrap();
+
+    assert!(session
+        .change_dimension(Dimension::Nether, Vector3f::from([0.0; 3]))
+        .await
+        .is_err());
+
+    token.cancel();
+    let _ = std::fs::remove_dir_all(path);
+}